@@ -0,0 +1,43 @@
+//! Parses arbitrary exiftool dumps and renders patterns against them. The
+//! first line is the pattern, the rest the dump, so hostile tag values
+//! (newlines, NULs, bidi overrides, huge numbers) reach every filter.
+//!
+//! ```shell
+//! cargo +nightly fuzz run dump
+//! ```
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// only part of each module is exercised here
+#[allow(dead_code)]
+#[path = "../../src/dump.rs"]
+mod dump;
+#[allow(dead_code)]
+#[path = "../../src/pattern.rs"]
+mod pattern;
+
+fuzz_target!(|data: &str| {
+    let (pattern, dump) = data.split_once('\n').unwrap_or((data, ""));
+    let vars = dump::exif_vars(dump);
+
+    for value in vars.values() {
+        let text = dump::sanitize_text(value, 64);
+        assert!(text.chars().count() <= 64);
+        assert!(!text.chars().any(|c| c.is_control() || r#"<>:"/\|?*"#.contains(c)));
+        assert!(!text.ends_with([' ', '.']));
+        assert!(!dump::strip_invisible(value).chars().any(dump::is_invisible));
+    }
+
+    if let Ok(pattern) = pattern::Pattern::parse(pattern) {
+        let complete = pattern.variables().iter().all(|v| vars.contains_key(*v));
+        match pattern.render(&vars) {
+            Ok(_) => {}
+            Err(e) => {
+                assert!(!complete, "render failed with every variable set: {}", e);
+                let _ = e.to_string();
+            }
+        }
+    }
+});
@@ -0,0 +1,23 @@
+//! Parses arbitrary bytes as JPEG or TIFF EXIF data. Offsets and counts in
+//! the IFDs come straight from the file, so they must never index out of
+//! bounds or overflow.
+//!
+//! ```shell
+//! cargo +nightly fuzz run exif
+//! ```
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// `read` is only used by the tool
+#[allow(dead_code)]
+#[path = "../../src/exif.rs"]
+mod exif;
+
+fuzz_target!(|data: &[u8]| {
+    for (name, value) in exif::parse(data) {
+        assert!(!name.is_empty());
+        assert!(!value.is_empty());
+    }
+});
@@ -0,0 +1,32 @@
+//! Parses arbitrary patterns and renders the valid ones.
+//!
+//! ```shell
+//! cargo +nightly fuzz run pattern
+//! ```
+
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+
+// reading names back with captures is only used by the tool
+#[allow(dead_code)]
+#[path = "../../src/pattern.rs"]
+mod pattern;
+
+fuzz_target!(|data: &str| {
+    let Ok(pattern) = pattern::Pattern::parse(data) else {
+        return;
+    };
+    let vars: HashMap<String, String> = [("Y", "2023"), ("T2", "Canon EOS R5"), ("title", "")]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let complete = pattern.variables().iter().all(|v| vars.contains_key(*v));
+    if let Err(e) = pattern.render(&vars) {
+        assert!(!complete, "render failed with every variable set: {}", e);
+        // error display must not panic on any span
+        let _ = e.to_string();
+    }
+});
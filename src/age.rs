@@ -0,0 +1,116 @@
+//! `{age_bucket}`: how old a capture is relative to the import, in buckets
+//! bounded by configurable thresholds.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Ascending age thresholds, e.g. `1y,5y` giving `recent`, `1-5y` and `5y+`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgeBuckets(Vec<Threshold>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Threshold {
+    value: u32,
+    unit: char,
+}
+
+impl Threshold {
+    fn days(&self) -> i64 {
+        let per_unit = match self.unit {
+            'd' => 1,
+            'w' => 7,
+            'm' => 30,
+            _ => 365,
+        };
+        self.value as i64 * per_unit
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.value, self.unit)
+    }
+}
+
+impl Default for AgeBuckets {
+    fn default() -> Self {
+        "1y,5y".parse().unwrap()
+    }
+}
+
+impl FromStr for AgeBuckets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let thresholds = s
+            .split(',')
+            .map(|t| {
+                let t = t.trim();
+                let unit = t
+                    .chars()
+                    .last()
+                    .filter(|u| "dwmy".contains(*u))
+                    .ok_or_else(|| format!("invalid age '{}', expected e.g. 30d, 6m or 1y", t))?;
+                let value = t[..t.len() - 1]
+                    .parse()
+                    .map_err(|_| format!("invalid age '{}', expected e.g. 30d, 6m or 1y", t))?;
+                Ok(Threshold { value, unit })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        if thresholds.windows(2).any(|w| w[0].days() >= w[1].days()) {
+            return Err(format!("age thresholds must be ascending: '{}'", s));
+        }
+        Ok(AgeBuckets(thresholds))
+    }
+}
+
+impl AgeBuckets {
+    /// Returns the bucket label for an age in days. Captures dated after
+    /// the import count as `recent`.
+    pub fn bucket(&self, age_days: i64) -> String {
+        let upper = self.0.iter().position(|t| age_days < t.days());
+        match upper {
+            Some(0) => "recent".to_string(),
+            Some(i) => {
+                let (lower, upper) = (&self.0[i - 1], &self.0[i]);
+                if lower.unit == upper.unit {
+                    format!("{}-{}", lower.value, upper)
+                } else {
+                    format!("{}-{}", lower, upper)
+                }
+            }
+            None => match self.0.last() {
+                Some(last) => format!("{}+", last),
+                None => "recent".to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_buckets() {
+        let buckets = AgeBuckets::default();
+        assert_eq!(buckets.bucket(-3), "recent");
+        assert_eq!(buckets.bucket(100), "recent");
+        assert_eq!(buckets.bucket(365), "1-5y");
+        assert_eq!(buckets.bucket(5 * 365), "5y+");
+    }
+
+    #[test]
+    fn test_custom_buckets() {
+        let buckets: AgeBuckets = "30d,6m,2y".parse().unwrap();
+        assert_eq!(buckets.bucket(10), "recent");
+        assert_eq!(buckets.bucket(40), "30d-6m");
+        assert_eq!(buckets.bucket(200), "6m-2y");
+        assert_eq!(buckets.bucket(1000), "2y+");
+
+        assert!("1y,6m".parse::<AgeBuckets>().is_err());
+        assert!("1x".parse::<AgeBuckets>().is_err());
+        assert!("y".parse::<AgeBuckets>().is_err());
+    }
+}
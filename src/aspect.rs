@@ -0,0 +1,126 @@
+//! `{aspect}`: the shape of the frame as displayed, e.g. `4x3`, `16x9` or
+//! `vertical`, from the image dimensions and orientation.
+
+use crate::Vars;
+
+/// Landscape ratios a frame is rounded to, narrowest first.
+const RATIOS: &[(&str, f64)] = &[
+    ("1x1", 1.0),
+    ("5x4", 5.0 / 4.0),
+    ("4x3", 4.0 / 3.0),
+    ("3x2", 3.0 / 2.0),
+    ("16x9", 16.0 / 9.0),
+];
+
+/// Frames this much wider than high are panoramas.
+const PANORAMA: f64 = 2.0;
+
+/// Frames whose sides differ by at most this fraction are square, even
+/// when taller than wide.
+const SQUARE_TOLERANCE: f64 = 0.02;
+
+/// Returns `{aspect}` for the dimensions in `exif`: the closest of
+/// [`RATIOS`], `pano` for panoramas and `vertical` for frames taller than
+/// wide once rotated by `Orientation` or `Rotation`.
+pub fn aspect(exif: &Vars) -> Option<&'static str> {
+    let (width, height) = dimensions(exif)?;
+    let (width, height) = if is_rotated(exif) {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let ratio = width / height;
+    if (ratio - 1.0).abs() <= SQUARE_TOLERANCE {
+        return Some("1x1");
+    }
+    if ratio < 1.0 {
+        return Some("vertical");
+    }
+    if ratio >= PANORAMA {
+        return Some("pano");
+    }
+    RATIOS
+        .iter()
+        .min_by(|a, b| {
+            let distance = |r: f64| (ratio.ln() - r.ln()).abs();
+            distance(a.1).total_cmp(&distance(b.1))
+        })
+        .map(|(name, _)| *name)
+}
+
+/// Width and height in pixels, from `ImageWidth`/`ImageHeight`,
+/// `ExifImageWidth`/`ExifImageHeight` or the composite `ImageSize`.
+fn dimensions(exif: &Vars) -> Option<(f64, f64)> {
+    let pixels = |tag: &str| {
+        exif.get(tag)
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|v| *v > 0.0)
+    };
+    let pair = [
+        ("ImageWidth", "ImageHeight"),
+        ("ExifImageWidth", "ExifImageHeight"),
+    ]
+    .iter()
+    .find_map(|(w, h)| Some((pixels(w)?, pixels(h)?)));
+    pair.or_else(|| {
+        // `4032x3024`, or `4032 3024` with `exiftool -n`
+        let size = exif.get("ImageSize")?;
+        let (w, h) = size.trim().split_once(['x', ' '])?;
+        let (w, h) = (w.trim().parse::<f64>().ok()?, h.trim().parse::<f64>().ok()?);
+        (w > 0.0 && h > 0.0).then_some((w, h))
+    })
+}
+
+/// True if the frame is displayed turned by a quarter, per the EXIF
+/// `Orientation` (`Rotate 90 CW`, or 5-8 with `exiftool -n`) or the
+/// QuickTime `Rotation` of videos.
+fn is_rotated(exif: &Vars) -> bool {
+    let orientation = exif.get("Orientation").map(|o| o.trim());
+    let rotated = match orientation {
+        Some(o) => o.contains("90") || o.contains("270") || matches!(o, "5" | "6" | "7" | "8"),
+        None => false,
+    };
+    let rotation = exif
+        .get("Rotation")
+        .and_then(|r| r.trim().parse::<u32>().ok());
+    rotated || matches!(rotation, Some(90 | 270))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(tags: &[(&str, &str)]) -> Vars {
+        tags.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_aspect() {
+        let frame = |w, h| aspect(&vars(&[("ImageWidth", w), ("ImageHeight", h)]));
+        assert_eq!(frame("4032", "3024"), Some("4x3"));
+        assert_eq!(frame("6000", "4000"), Some("3x2"));
+        assert_eq!(frame("1920", "1080"), Some("16x9"));
+        assert_eq!(frame("3000", "3000"), Some("1x1"));
+        assert_eq!(frame("1080", "1920"), Some("vertical"));
+        assert_eq!(frame("12000", "3000"), Some("pano"));
+        assert_eq!(frame("0", "3000"), None);
+        assert_eq!(aspect(&Vars::new()), None);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let rotated = vars(&[("ImageSize", "4032x3024"), ("Orientation", "Rotate 90 CW")]);
+        assert_eq!(aspect(&rotated), Some("vertical"));
+        let numeric = vars(&[("ImageSize", "4032 3024"), ("Orientation", "6")]);
+        assert_eq!(aspect(&numeric), Some("vertical"));
+        let normal = vars(&[
+            ("ImageSize", "4032x3024"),
+            ("Orientation", "Horizontal (normal)"),
+        ]);
+        assert_eq!(aspect(&normal), Some("4x3"));
+        let video = vars(&[("ImageSize", "1920x1080"), ("Rotation", "270")]);
+        assert_eq!(aspect(&video), Some("vertical"));
+    }
+}
@@ -0,0 +1,156 @@
+//! `export-bag`: copies renamed files into a BagIt bag (RFC 8493) for
+//! digital preservation, with SHA-256 manifests of the payload and the tag
+//! files, and the pattern and run in `bag-info.txt`.
+//!
+//! ```text
+//! BAG/
+//!   bagit.txt
+//!   bag-info.txt
+//!   manifest-sha256.txt
+//!   tagmanifest-sha256.txt
+//!   data/2023/20230908_101112.JPG
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::verify::{hex, sha256};
+
+const BAGIT_TXT: &str = "BagIt-Version: 1.0\nTag-File-Character-Encoding: UTF-8\n";
+
+/// A bag being filled.
+#[derive(Debug)]
+pub struct Bag {
+    dir: PathBuf,
+    /// Digest and path relative to the bag of each payload file.
+    manifest: Vec<(String, String)>,
+    /// Total size of the payload.
+    bytes: u64,
+}
+
+impl Bag {
+    /// Starts a bag in `dir`, which must not exist or be empty.
+    pub fn create(dir: &Path) -> Result<Bag, String> {
+        let empty = fs::read_dir(dir).map_or(true, |mut entries| entries.next().is_none());
+        if !empty {
+            return Err(format!("{}: exists and is not empty", dir.display()));
+        }
+        fs::create_dir_all(dir.join("data"))
+            .map_err(|e| format!("{}: failed to create bag: {}", dir.display(), e))?;
+        Ok(Bag {
+            dir: dir.to_path_buf(),
+            manifest: Vec::new(),
+            bytes: 0,
+        })
+    }
+
+    /// Copies `file` into the payload as `name`, which may contain `/`.
+    /// Returns the path of the copy.
+    pub fn add(&mut self, file: &Path, name: &str) -> Result<PathBuf, String> {
+        let name = Path::new(name);
+        if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(format!("'{}' is not a path inside the bag", name.display()));
+        }
+        let path = self.dir.join("data").join(name);
+        if path.exists() {
+            return Err(format!("{} exists in the bag", name.display()));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("failed to create directory: {}", e))?;
+        }
+        self.bytes += fs::copy(file, &path).map_err(|e| format!("failed to copy file: {}", e))?;
+        // the copy is hashed, so the manifest vouches for what landed in the bag
+        let digest = sha256(&path).map_err(|e| format!("failed to hash file: {}", e))?;
+        let relative = Path::new("data").join(name);
+        self.manifest
+            .push((digest, relative.to_string_lossy().replace('\\', "/")));
+        Ok(path)
+    }
+
+    /// Writes the tag files, with `info` as extra `bag-info.txt` fields
+    /// after `Bagging-Date`, `Bag-Software-Agent` and `Payload-Oxum`.
+    pub fn finish(self, date: &str, info: &[(&str, String)]) -> io::Result<()> {
+        let mut bag_info = format!(
+            "Bagging-Date: {}\nBag-Software-Agent: exif-rename {}\nPayload-Oxum: {}.{}\n",
+            date,
+            env!("CARGO_PKG_VERSION"),
+            self.bytes,
+            self.manifest.len()
+        );
+        for (label, value) in info {
+            bag_info.push_str(&format!("{}: {}\n", label, value));
+        }
+        let manifest = manifest_lines(&self.manifest);
+
+        let mut tags = Vec::new();
+        for (name, content) in [
+            ("bagit.txt", BAGIT_TXT),
+            ("bag-info.txt", bag_info.as_str()),
+            ("manifest-sha256.txt", manifest.as_str()),
+        ] {
+            fs::write(self.dir.join(name), content)?;
+            tags.push((hex(&Sha256::digest(content)), name.to_string()));
+        }
+        fs::write(
+            self.dir.join("tagmanifest-sha256.txt"),
+            manifest_lines(&tags),
+        )
+    }
+}
+
+/// Manifest lines of digests and paths, with `%`, CR and LF in paths
+/// percent-encoded as RFC 8493 requires.
+fn manifest_lines(entries: &[(String, String)]) -> String {
+    entries
+        .iter()
+        .map(|(digest, path)| {
+            let path = path
+                .replace('%', "%25")
+                .replace('\r', "%0D")
+                .replace('\n', "%0A");
+            format!("{}  {}\n", digest, path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bag() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-bag-{}", std::process::id()));
+        let source = dir.join("IMG_1234.JPG");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&source, "abc").unwrap();
+        let bag_dir = dir.join("bag");
+
+        let mut bag = Bag::create(&bag_dir).unwrap();
+        let copy = bag.add(&source, "2023/50% off.JPG").unwrap();
+        assert_eq!(copy, bag_dir.join("data/2023/50% off.JPG"));
+        assert!(bag.add(&source, "2023/50% off.JPG").is_err());
+        assert!(bag.add(&source, "../escape.JPG").is_err());
+        bag.finish(
+            "2023-09-08",
+            &[("Exif-Rename-Pattern", "{Y}/{f}.{e}".to_string())],
+        )
+        .unwrap();
+
+        let read = |name: &str| fs::read_to_string(bag_dir.join(name)).unwrap();
+        assert_eq!(read("bagit.txt"), BAGIT_TXT);
+        assert_eq!(
+            read("manifest-sha256.txt"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  \
+             data/2023/50%25 off.JPG\n"
+        );
+        let info = read("bag-info.txt");
+        assert!(info.starts_with("Bagging-Date: 2023-09-08\n"));
+        assert!(info.contains("\nPayload-Oxum: 3.1\nExif-Rename-Pattern: {Y}/{f}.{e}\n"));
+        assert_eq!(read("tagmanifest-sha256.txt").lines().count(), 3);
+        assert!(Bag::create(&bag_dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! `--collision-window 2s`: `{burst}` suffixes for files captured within a
+//! few seconds of each other, such as scanned or shot bursts.
+//!
+//! Files whose capture times, including sub-seconds, are at most the window
+//! apart from the previous one form a burst. Members are lettered `a`, `b`,
+//! ... by capture time, then by input order, so repeated runs over the same
+//! files give the same names.
+
+use std::str::FromStr;
+
+use chrono::{NaiveDateTime, TimeDelta};
+
+use crate::Vars;
+
+/// Largest gap between consecutive captures of a burst.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window(pub TimeDelta);
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid window '{}', expected e.g. 2s or 500ms", s);
+        let (value, millis) = match s.strip_suffix("ms") {
+            Some(value) => (value, 1),
+            None => (s.strip_suffix('s').unwrap_or(s), 1000),
+        };
+        let value: i64 = value.trim().parse().map_err(|_| invalid())?;
+        if value < 0 {
+            return Err(invalid());
+        }
+        Ok(Window(TimeDelta::milliseconds(value * millis)))
+    }
+}
+
+/// Returns the capture time of a file at `date`, refined by the
+/// sub-seconds exiftool reports separately.
+pub fn capture_time(date: NaiveDateTime, vars: &Vars) -> NaiveDateTime {
+    let subsec = ["SubSecTimeOriginal", "SubSecTimeDigitized", "SubSecTime"]
+        .iter()
+        .find_map(|tag| vars.get(*tag))
+        .and_then(|s| subsec_millis(s));
+    date + TimeDelta::milliseconds(subsec.unwrap_or(0))
+}
+
+/// `5` -> 500ms, `042` -> 42ms: sub-seconds are the digits after the point.
+fn subsec_millis(s: &str) -> Option<i64> {
+    let digits: String = s.trim().chars().take(3).collect();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let millis: i64 = digits.parse().ok()?;
+    Some(millis * 10i64.pow(3 - digits.len() as u32))
+}
+
+/// Assigns the burst suffix of each file given by index and capture time.
+/// Files outside of a burst get an empty suffix.
+pub fn assign(mut times: Vec<(usize, NaiveDateTime)>, window: Window) -> Vec<(usize, String)> {
+    times.sort_by_key(|&(i, time)| (time, i));
+    let mut suffixes = Vec::new();
+    let mut start = 0;
+    for end in 1..=times.len() {
+        let split = end == times.len() || times[end].1 - times[end - 1].1 > window.0;
+        if !split {
+            continue;
+        }
+        let burst = &times[start..end];
+        for (n, &(i, _)) in burst.iter().enumerate() {
+            let suffix = if burst.len() > 1 {
+                suffix(n)
+            } else {
+                String::new()
+            };
+            suffixes.push((i, suffix));
+        }
+        start = end;
+    }
+    suffixes
+}
+
+/// `0` -> `a`, `25` -> `z`, `26` -> `aa`, ...
+pub fn suffix(mut n: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push(b'a' + (n % 26) as u8);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    letters.iter().rev().map(|&b| b as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(h: u32, m: u32, s: u32, ms: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 9, 8)
+            .unwrap()
+            .and_hms_milli_opt(h, m, s, ms)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_window() {
+        let window: Window = "2s".parse().unwrap();
+        assert_eq!(window.0, TimeDelta::seconds(2));
+        assert_eq!(
+            "500ms".parse::<Window>().unwrap().0,
+            TimeDelta::milliseconds(500)
+        );
+        assert_eq!("3".parse::<Window>().unwrap().0, TimeDelta::seconds(3));
+        assert!("2m".parse::<Window>().is_err());
+        assert!("-1s".parse::<Window>().is_err());
+    }
+
+    #[test]
+    fn test_capture_time() {
+        let mut vars = Vars::new();
+        assert_eq!(capture_time(at(10, 0, 0, 0), &vars), at(10, 0, 0, 0));
+        vars.insert("SubSecTimeOriginal".to_string(), "05".to_string());
+        assert_eq!(capture_time(at(10, 0, 0, 0), &vars), at(10, 0, 0, 50));
+        vars.insert("SubSecTimeOriginal".to_string(), "1234".to_string());
+        assert_eq!(capture_time(at(10, 0, 0, 0), &vars), at(10, 0, 0, 123));
+    }
+
+    #[test]
+    fn test_assign() {
+        let times = vec![
+            (0, at(10, 0, 1, 0)),
+            (1, at(10, 0, 0, 500)),
+            (2, at(10, 0, 0, 0)),
+            (3, at(10, 0, 10, 0)),
+            (4, at(10, 0, 3, 0)),
+            (5, at(10, 0, 3, 0)),
+        ];
+        let mut suffixes = assign(times, "2s".parse().unwrap());
+        suffixes.sort();
+        let suffixes: Vec<_> = suffixes.into_iter().map(|(_, s)| s).collect();
+        assert_eq!(suffixes, vec!["c", "b", "a", "", "d", "e"]);
+    }
+
+    #[test]
+    fn test_suffix() {
+        assert_eq!(suffix(0), "a");
+        assert_eq!(suffix(25), "z");
+        assert_eq!(suffix(26), "aa");
+        assert_eq!(suffix(27), "ab");
+        assert_eq!(suffix(26 + 26 * 26), "aaa");
+    }
+}
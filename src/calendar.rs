@@ -0,0 +1,362 @@
+//! `--calendar events.ics`: `{event}`, the summary of the calendar event a
+//! file was captured during, e.g. `Jane's Birthday` or `Team Offsite`.
+//!
+//! Events with a UTC time (`20230908T010000Z`) are matched against the
+//! capture time converted to UTC, other times against the local capture
+//! time; time zones named with `TZID` are taken as local. All-day events
+//! cover their whole days. Recurring events repeat by `FREQ`, `INTERVAL`,
+//! `COUNT` and `UNTIL`; other recurrence rules aren't supported and such
+//! events only match their first occurrence. When events overlap, the
+//! shortest wins, so a party during a trip names its photos.
+
+use std::fs;
+
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, TimeDelta};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calendar(Vec<Event>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Event {
+    summary: String,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    /// True if the times are UTC, else local.
+    utc: bool,
+    repeat: Option<Repeat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Repeat {
+    freq: Freq,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Calendar {
+    /// Reads the iCalendar file at `path`, for clap.
+    pub fn load(path: &str) -> Result<Calendar, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("failed to read: {}", e))?;
+        let calendar = parse(&source);
+        if calendar.0.is_empty() {
+            return Err("no events in the calendar".to_string());
+        }
+        Ok(calendar)
+    }
+}
+
+/// Returns the summary of the shortest event of `calendars` at the capture
+/// time `local`, or `utc` for events in UTC.
+pub fn event(
+    calendars: &[Calendar],
+    local: NaiveDateTime,
+    utc: Option<NaiveDateTime>,
+) -> Option<&str> {
+    calendars
+        .iter()
+        .flat_map(|calendar| &calendar.0)
+        .filter(|event| match (event.utc, utc) {
+            (true, Some(utc)) => event.covers(utc),
+            (true, None) => false,
+            (false, _) => event.covers(local),
+        })
+        .min_by_key(|event| event.end - event.start)
+        .map(|event| event.summary.as_str())
+}
+
+impl Event {
+    fn covers(&self, time: NaiveDateTime) -> bool {
+        let Some(repeat) = self.repeat else {
+            return self.start <= time && time < self.end;
+        };
+        if time < self.start {
+            return false;
+        }
+        // the occurrence starting last before `time`, and the one before it
+        // in case occurrences are longer than the period
+        let k = repeat.freq.periods_between(self.start, time) / repeat.interval;
+        (k.saturating_sub(1)..=k).any(|k| {
+            let Some(start) = repeat.nth(self.start, k) else {
+                return false;
+            };
+            let within = repeat.count.is_none_or(|count| k < count)
+                && repeat.until.is_none_or(|until| start <= until);
+            within && start <= time && time < start + (self.end - self.start)
+        })
+    }
+}
+
+impl Repeat {
+    /// Start of the `k`th occurrence of an event starting at `start`.
+    fn nth(&self, start: NaiveDateTime, k: u32) -> Option<NaiveDateTime> {
+        let n = k.checked_mul(self.interval)?;
+        match self.freq {
+            Freq::Daily => start.checked_add_days(Days::new(n as u64)),
+            Freq::Weekly => start.checked_add_days(Days::new(n as u64 * 7)),
+            Freq::Monthly => start.checked_add_months(Months::new(n)),
+            Freq::Yearly => start.checked_add_months(Months::new(n.checked_mul(12)?)),
+        }
+    }
+}
+
+impl Freq {
+    /// Whole periods from `start` to the later `time`, at most one too many.
+    fn periods_between(self, start: NaiveDateTime, time: NaiveDateTime) -> u32 {
+        let days = (time - start).num_days();
+        let months = (time.year() - start.year()) * 12 + time.month() as i32 - start.month() as i32;
+        let periods = match self {
+            Freq::Daily => days,
+            Freq::Weekly => days / 7,
+            Freq::Monthly => months as i64,
+            Freq::Yearly => months as i64 / 12,
+        };
+        periods.clamp(0, u32::MAX as i64) as u32
+    }
+}
+
+/// Parses the `VEVENT`s of an iCalendar document. Events without a
+/// summary or start are skipped.
+fn parse(source: &str) -> Calendar {
+    let mut events = Vec::new();
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut in_event = false;
+    for line in unfold(source) {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match (name.to_ascii_uppercase().as_str(), value.trim()) {
+            ("BEGIN", "VEVENT") => {
+                in_event = true;
+                fields.clear();
+            }
+            ("END", "VEVENT") => {
+                in_event = false;
+                events.extend(event_from(&fields));
+            }
+            _ if in_event => fields.push((name.to_string(), value.to_string())),
+            _ => {}
+        }
+    }
+    Calendar(events)
+}
+
+/// Joins folded lines: a line starting with a space or tab continues the
+/// previous one.
+fn unfold(source: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in source.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn event_from(fields: &[(String, String)]) -> Option<Event> {
+    // property names may carry parameters, e.g. `DTSTART;TZID=Europe/Paris`
+    let field = |name: &str| {
+        fields.iter().find(|(key, _)| {
+            let key = key.split(';').next().unwrap_or_default();
+            key.eq_ignore_ascii_case(name)
+        })
+    };
+    let summary = unescape(field("SUMMARY")?.1.trim());
+    let (start, utc, all_day) = parse_time(&field("DTSTART")?.1)?;
+    let end = match (field("DTEND"), field("DURATION")) {
+        (Some((_, end)), _) => parse_time(end)?.0,
+        (None, Some((_, duration))) => start + parse_duration(duration)?,
+        (None, None) if all_day => start + TimeDelta::days(1),
+        (None, None) => start,
+    };
+    let repeat = field("RRULE").and_then(|(_, rule)| parse_rule(rule));
+    (!summary.is_empty() && end >= start).then_some(Event {
+        summary,
+        start,
+        end,
+        utc,
+        repeat,
+    })
+}
+
+/// Parses `20230908T100000`, `20230908T010000Z` or the all-day `20230908`,
+/// returning the time, whether it is UTC and whether it is all-day.
+fn parse_time(value: &str) -> Option<(NaiveDateTime, bool, bool)> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Some((date.and_hms_opt(0, 0, 0)?, false, true));
+    }
+    let (value, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some((time, utc, false))
+}
+
+/// Parses durations such as `PT1H30M`, `P2D` or `P1W`.
+fn parse_duration(value: &str) -> Option<TimeDelta> {
+    let value = value.trim().strip_prefix('P')?;
+    let mut total = TimeDelta::zero();
+    let mut number = String::new();
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {}
+            'W' | 'D' | 'H' | 'M' | 'S' => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                total += match c {
+                    'W' => TimeDelta::weeks(n),
+                    'D' => TimeDelta::days(n),
+                    'H' => TimeDelta::hours(n),
+                    'M' => TimeDelta::minutes(n),
+                    _ => TimeDelta::seconds(n),
+                };
+            }
+            _ => return None,
+        }
+    }
+    Some(total)
+}
+
+/// Parses an `RRULE` such as `FREQ=YEARLY` or
+/// `FREQ=WEEKLY;INTERVAL=2;COUNT=10`. Rules with `BY...` parts select days
+/// this can't follow and give `None`.
+fn parse_rule(rule: &str) -> Option<Repeat> {
+    let mut repeat = Repeat {
+        freq: Freq::Yearly,
+        interval: 1,
+        count: None,
+        until: None,
+    };
+    let mut freq = None;
+    for part in rule.trim().split(';') {
+        let (key, value) = part.split_once('=')?;
+        match key.to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                freq = Some(match value.to_ascii_uppercase().as_str() {
+                    "DAILY" => Freq::Daily,
+                    "WEEKLY" => Freq::Weekly,
+                    "MONTHLY" => Freq::Monthly,
+                    "YEARLY" => Freq::Yearly,
+                    _ => return None,
+                })
+            }
+            "INTERVAL" => repeat.interval = value.parse().ok().filter(|i| *i > 0)?,
+            "COUNT" => repeat.count = Some(value.parse().ok()?),
+            "UNTIL" => repeat.until = Some(parse_time(value)?.0),
+            "WKST" => {}
+            _ => return None,
+        }
+    }
+    repeat.freq = freq?;
+    Some(repeat)
+}
+
+/// Unescapes iCalendar text: `\,`, `\;`, `\\` and `\n`.
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push(' '),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICS: &str = "BEGIN:VCALENDAR\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:Team Offsite\r\n\
+DTSTART;TZID=Asia/Seoul:20230908T090000\r\n\
+DTEND;TZID=Asia/Seoul:20230908T180000\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:Lunch\\, with\r\n \x20the team\r\n\
+DTSTART:20230908T030000Z\r\n\
+DURATION:PT1H30M\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:Jane's Birthday\r\n\
+DTSTART;VALUE=DATE:19900215\r\n\
+DTEND;VALUE=DATE:19900216\r\n\
+RRULE:FREQ=YEARLY\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:Standup\r\n\
+DTSTART:20230904T093000\r\n\
+DTEND:20230904T094500\r\n\
+RRULE:FREQ=WEEKLY;INTERVAL=1;COUNT=2\r\n\
+END:VEVENT\r\n\
+END:VCALENDAR\r\n";
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d)
+            .unwrap()
+            .and_hms_opt(h, mi, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let calendar = parse(ICS);
+        assert_eq!(calendar.0.len(), 4);
+        assert_eq!(calendar.0[1].summary, "Lunch, with the team");
+        assert_eq!(calendar.0[1].end, at(2023, 9, 8, 4, 30));
+        assert!(calendar.0[1].utc);
+        assert_eq!(calendar.0[2].end, at(1990, 2, 16, 0, 0));
+    }
+
+    #[test]
+    fn test_event() {
+        let calendars = [parse(ICS)];
+        let local = |time| event(&calendars, time, Some(time - TimeDelta::hours(9)));
+        assert_eq!(local(at(2023, 9, 8, 10, 0)), Some("Team Offsite"));
+        assert_eq!(local(at(2023, 9, 8, 12, 30)), Some("Lunch, with the team"));
+        assert_eq!(
+            event(&calendars, at(2023, 9, 8, 12, 30), None),
+            Some("Team Offsite")
+        );
+        assert_eq!(local(at(2023, 9, 8, 19, 0)), None);
+        assert_eq!(local(at(2024, 2, 15, 20, 0)), Some("Jane's Birthday"));
+        assert_eq!(local(at(1989, 2, 15, 20, 0)), None);
+        assert_eq!(local(at(2023, 9, 11, 9, 40)), Some("Standup"));
+        assert_eq!(local(at(2023, 9, 18, 9, 40)), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("PT1H30M"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("P1W2D"), Some(TimeDelta::days(9)));
+        assert_eq!(parse_duration("1H"), None);
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let rule = parse_rule("FREQ=MONTHLY;INTERVAL=3;UNTIL=20240101T000000Z").unwrap();
+        assert_eq!((rule.freq, rule.interval), (Freq::Monthly, 3));
+        assert_eq!(rule.until, Some(at(2024, 1, 1, 0, 0)));
+        assert_eq!(parse_rule("FREQ=WEEKLY;BYDAY=MO,WE"), None);
+        assert_eq!(parse_rule("INTERVAL=2"), None);
+    }
+}
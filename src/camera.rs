@@ -0,0 +1,218 @@
+//! Camera identity for multi-camera shoots: per-camera clock offsets
+//! (`--sync-offsets`) and short labels (`--cam-map`), and the clock offset
+//! of a whole run (`--shift`).
+
+use std::str::FromStr;
+
+use chrono::TimeDelta;
+
+use crate::Vars;
+
+/// Tags identifying a camera body, in order of preference.
+const SERIAL_TAGS: &[&str] = &[
+    "SerialNumber",
+    "BodySerialNumber",
+    "InternalSerialNumber",
+    "CameraSerialNumber",
+];
+
+/// Clock offset of the camera with the given serial number or model, e.g.
+/// `SERIAL123=+00:00:42`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncOffset {
+    pub camera: String,
+    pub offset: TimeDelta,
+}
+
+impl FromStr for SyncOffset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (camera, offset) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected SERIAL=OFFSET or MODEL=OFFSET, got '{}'", s))?;
+        let offset = parse_clock_offset(offset)
+            .ok_or_else(|| format!("invalid offset '{}', expected e.g. +00:00:42", offset))?;
+        Ok(SyncOffset {
+            camera: camera.trim().to_string(),
+            offset,
+        })
+    }
+}
+
+/// `--shift`: clock offset of every file of a run, e.g. `+1h30m`, `-37s`
+/// or `+01:30:00`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shift(pub TimeDelta);
+
+impl FromStr for Shift {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid shift '{}', expected e.g. +1h30m, -37s or +01:30:00",
+                s
+            )
+        };
+        if let Some(offset) = parse_clock_offset(s) {
+            return Ok(Shift(offset));
+        }
+        let s = s.trim();
+        let (sign, mut rest) = match s.as_bytes().first() {
+            Some(b'-') => (-1, &s[1..]),
+            Some(b'+') => (1, &s[1..]),
+            _ => (1, s),
+        };
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+        let mut seconds = 0;
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(invalid)?;
+            let value: i64 = rest[..digits].parse().map_err(|_| invalid())?;
+            let unit = match rest[digits..].chars().next() {
+                Some('d') => 86400,
+                Some('h') => 3600,
+                Some('m') => 60,
+                Some('s') => 1,
+                _ => return Err(invalid()),
+            };
+            seconds += value * unit;
+            rest = &rest[digits + 1..];
+        }
+        Ok(Shift(TimeDelta::seconds(sign * seconds)))
+    }
+}
+
+/// Short label for a camera serial number or model, e.g. `SERIAL123=A`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CamLabel {
+    pub camera: String,
+    pub label: String,
+}
+
+impl FromStr for CamLabel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((camera, label)) if !camera.trim().is_empty() => Ok(CamLabel {
+                camera: camera.trim().to_string(),
+                label: label.trim().to_string(),
+            }),
+            _ => Err(format!("expected SERIAL=LABEL or MODEL=LABEL, got '{}'", s)),
+        }
+    }
+}
+
+/// Parses `+HH:MM:SS` or `-HH:MM[:SS]`.
+pub fn parse_clock_offset(s: &str) -> Option<TimeDelta> {
+    let s = s.trim();
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => (1, s),
+    };
+    let parts = rest
+        .split(':')
+        .map(|p| p.parse::<i64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let seconds = match parts[..] {
+        [h, m] => h * 3600 + m * 60,
+        [h, m, s] => h * 3600 + m * 60 + s,
+        _ => return None,
+    };
+    Some(TimeDelta::seconds(sign * seconds))
+}
+
+/// Returns the offset configured for the camera that shot the file, by
+/// serial number first and model second.
+pub fn camera_offset(offsets: &[SyncOffset], exif: &Vars) -> TimeDelta {
+    SERIAL_TAGS
+        .iter()
+        .chain(&["Model"])
+        .filter_map(|tag| exif.get(*tag))
+        .find_map(|id| offsets.iter().find(|o| &o.camera == id))
+        .map(|o| o.offset)
+        .unwrap_or_default()
+}
+
+/// Returns the label of the camera that shot the file. Serial numbers take
+/// precedence over the model so two identical bodies can be told apart.
+pub fn camera_label<'a>(labels: &'a [CamLabel], exif: &Vars) -> Option<&'a str> {
+    SERIAL_TAGS
+        .iter()
+        .chain(&["Model"])
+        .filter_map(|tag| exif.get(*tag))
+        .find_map(|id| labels.iter().find(|l| &l.camera == id))
+        .map(|l| l.label.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sync_offset() {
+        let o: SyncOffset = "SERIAL123=+00:00:42".parse().unwrap();
+        assert_eq!(o.camera, "SERIAL123");
+        assert_eq!(o.offset, TimeDelta::seconds(42));
+        let o: SyncOffset = "X=-01:30".parse().unwrap();
+        assert_eq!(o.offset, TimeDelta::seconds(-5400));
+        assert!("SERIAL123".parse::<SyncOffset>().is_err());
+        assert!("S=+1h".parse::<SyncOffset>().is_err());
+    }
+
+    #[test]
+    fn test_parse_shift() {
+        let shift = |s: &str| s.parse::<Shift>().map(|s| s.0.num_seconds());
+        assert_eq!(shift("+1h30m"), Ok(5400));
+        assert_eq!(shift("-37s"), Ok(-37));
+        assert_eq!(shift("1d2h"), Ok(93600));
+        assert_eq!(shift("-01:30"), Ok(-5400));
+        assert!(shift("+").is_err());
+        assert!(shift("1h30").is_err());
+        assert!(shift("1x").is_err());
+        assert!(shift("h").is_err());
+    }
+
+    #[test]
+    fn test_camera_offset() {
+        let offsets = vec![
+            "A1=+00:00:42".parse().unwrap(),
+            "B2=-00:01:00".parse().unwrap(),
+        ];
+        let mut exif = Vars::new();
+        assert_eq!(camera_offset(&offsets, &exif), TimeDelta::zero());
+        exif.insert("BodySerialNumber".to_string(), "B2".to_string());
+        assert_eq!(camera_offset(&offsets, &exif), TimeDelta::seconds(-60));
+
+        let offsets: Vec<SyncOffset> = vec![
+            "NIKON D750=-00:01:12".parse().unwrap(),
+            "B2=+00:00:05".parse().unwrap(),
+        ];
+        exif.insert("Model".to_string(), "NIKON D750".to_string());
+        assert_eq!(camera_offset(&offsets, &exif), TimeDelta::seconds(5));
+        exif.remove("BodySerialNumber");
+        assert_eq!(camera_offset(&offsets, &exif), TimeDelta::seconds(-72));
+    }
+
+    #[test]
+    fn test_camera_label() {
+        let labels: Vec<CamLabel> = ["NIKON D750=A", "SERIAL2=C", "Canon EOS R5=B"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let mut exif = Vars::new();
+        exif.insert("Model".to_string(), "NIKON D750".to_string());
+        assert_eq!(camera_label(&labels, &exif), Some("A"));
+        exif.insert("SerialNumber".to_string(), "SERIAL2".to_string());
+        assert_eq!(camera_label(&labels, &exif), Some("C"));
+        exif.insert("Model".to_string(), "X100V".to_string());
+        exif.remove("SerialNumber");
+        assert_eq!(camera_label(&labels, &exif), None);
+    }
+}
@@ -0,0 +1,130 @@
+//! `{city}`: the place a file was captured, from the `City` tag or, for
+//! positions only known by coordinates, the nearest entry of a city list
+//! given with `--cities`.
+//!
+//! The list uses the GeoNames format (`cities15000.txt` and friends from
+//! <https://download.geonames.org/export/dump/>): tab-separated, with the
+//! name in the second and the coordinates in the fifth and sixth column.
+//! Lines of just `name<TAB>lat<TAB>lon` are accepted too, for personal
+//! places such as home.
+
+use std::fs;
+
+use crate::Vars;
+
+/// Farthest a city is matched from, in kilometers.
+const MAX_DISTANCE_KM: f64 = 50.0;
+
+/// Tags exiftool fills with the city, in order of preference.
+const CITY_TAGS: &[&str] = &["City", "GeolocationCity"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cities(Vec<(String, f64, f64)>);
+
+impl Cities {
+    /// Reads the city list at `path`, for clap.
+    pub fn load(path: &str) -> Result<Cities, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("failed to read: {}", e))?;
+        let cities = parse(&source);
+        if cities.0.is_empty() {
+            return Err("no cities in the list".to_string());
+        }
+        Ok(cities)
+    }
+
+    /// Returns the city nearest to `(lat, lon)`, if any is close enough.
+    pub fn nearest(&self, (lat, lon): (f64, f64)) -> Option<&str> {
+        self.0
+            .iter()
+            .map(|(name, city_lat, city_lon)| {
+                (name, distance_km((lat, lon), (*city_lat, *city_lon)))
+            })
+            .filter(|(_, distance)| *distance <= MAX_DISTANCE_KM)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Returns the city of a file: its `City` tag, or the city nearest to its
+/// `coordinates`.
+pub fn city<'a>(
+    exif: &'a Vars,
+    coordinates: Option<(f64, f64)>,
+    cities: Option<&'a Cities>,
+) -> Option<&'a str> {
+    let tagged = CITY_TAGS
+        .iter()
+        .filter_map(|tag| exif.get(*tag))
+        .map(|city| city.trim())
+        .find(|city| !city.is_empty());
+    tagged.or_else(|| cities?.nearest(coordinates?))
+}
+
+fn parse(source: &str) -> Cities {
+    let cities = source
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let (name, lat, lon) = match fields[..] {
+                [name, lat, lon] => (name, lat, lon),
+                [_, name, _, _, lat, lon, ..] => (name, lat, lon),
+                _ => return None,
+            };
+            let name = name.trim();
+            let (lat, lon) = (lat.trim().parse().ok()?, lon.trim().parse().ok()?);
+            (!name.is_empty()).then(|| (name.to_string(), lat, lon))
+        })
+        .collect();
+    Cities(cities)
+}
+
+/// Great-circle distance by the haversine formula.
+fn distance_km((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+    let (dlat, dlon) = ((lat2 - lat1).to_radians(), (lon2 - lon1).to_radians());
+    let a = (dlat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CITIES: &str = "1835848\tSeoul\tSeoul\tSeul\t37.566\t126.9784\tP\tPPLC\tKR\n\
+                          1838524\tBusan\tBusan\t\t35.1028\t129.0403\tP\tPPLA\tKR\n\
+                          Home\t37.4\t127.1\n\
+                          # comment\n";
+
+    #[test]
+    fn test_nearest() {
+        let cities = parse(CITIES);
+        assert_eq!(cities.0.len(), 3);
+        assert_eq!(cities.nearest((37.55, 126.99)), Some("Seoul"));
+        assert_eq!(cities.nearest((37.41, 127.09)), Some("Home"));
+        assert_eq!(cities.nearest((35.1, 129.0)), Some("Busan"));
+        assert_eq!(cities.nearest((33.5, 126.5)), None);
+    }
+
+    #[test]
+    fn test_city() {
+        let cities = parse(CITIES);
+        let mut exif = Vars::new();
+        assert_eq!(
+            city(&exif, Some((37.55, 126.99)), Some(&cities)),
+            Some("Seoul")
+        );
+        assert_eq!(city(&exif, None, Some(&cities)), None);
+        exif.insert("City".to_string(), "Incheon".to_string());
+        assert_eq!(
+            city(&exif, Some((37.55, 126.99)), Some(&cities)),
+            Some("Incheon")
+        );
+    }
+
+    #[test]
+    fn test_distance() {
+        let km = distance_km((37.566, 126.9784), (35.1028, 129.0403));
+        assert!((km - 330.0).abs() < 1.0, "{}", km);
+    }
+}
@@ -0,0 +1,857 @@
+//! Config file, `~/.config/exif-rename/config.toml` unless `--config` is given.
+//!
+//! ```toml
+//! pattern = "{Y}{m}{D}_{t}.{e}"
+//! filters = ["!is-pano"]
+//! include = ["*.jpg", "*.heic"]
+//! on-conflict = "number"
+//!
+//! [aliases]
+//! camera = "Model"
+//!
+//! [model-offsets]
+//! "NIKON D750" = "-00:01:12"
+//!
+//! [presets]
+//! travel = "{Y}-{m}-{D}_{t}_{T2}.{e}"
+//!
+//! [profiles.work]
+//! pattern = "{Y}-{m}-{D}_{t}_{T2}.{e}"
+//! target = "/mnt/archive/work"
+//! hooks = ["chmod 444 \"$EXIF_RENAME_TARGET\""]
+//! age-buckets = "30d,1y"
+//! size-tiers = "50M,2G"
+//!
+//! [[rules]]
+//! ext = ["mov"]
+//! pattern = "videos/{Y}{m}{D}_{t}.{e}"
+//! ```
+//!
+//! A profile selected with `--profile` overrides the top-level settings it sets.
+//!
+//! Patterns can include shared fragments from `[macros]` with `${name}`, so
+//! profiles keep a consistent convention that is changed in one place.
+//! Profile macros override top-level macros of the same name.
+//!
+//! The file is validated as a whole at startup: unknown keys, invalid
+//! patterns and filters are reported with their line and a suggestion
+//! instead of being ignored.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{NaiveDate, TimeDelta};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::age::AgeBuckets;
+use crate::camera;
+use crate::conflict::OnConflict;
+use crate::filter::Filter;
+use crate::pattern::Pattern;
+use crate::rules::{Action, Rule};
+use crate::size::SizeTiers;
+
+/// Every key the config accepts, used for did-you-mean suggestions.
+const KEYS: &[&str] = &[
+    "pattern",
+    "dir-pattern",
+    "filters",
+    "include",
+    "exclude",
+    "on-conflict",
+    "target",
+    "exiftool",
+    "hooks",
+    "age-buckets",
+    "size-tiers",
+    "rules",
+    "macros",
+    "aliases",
+    "model-offsets",
+    "presets",
+    "profiles",
+];
+
+/// Every key a rule accepts.
+const RULE_KEYS: &[&str] = &[
+    "ext",
+    "filters",
+    "after",
+    "before",
+    "pattern",
+    "skip",
+    "quarantine",
+    "copy-to",
+];
+
+#[derive(Debug, Default)]
+pub struct Config {
+    path: PathBuf,
+    pub settings: Settings,
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+/// Settings that can be given at the top level or per profile.
+#[derive(Debug, Default, Clone)]
+pub struct Settings {
+    pub pattern: Option<Pattern>,
+    pub dir_pattern: Option<Pattern>,
+    pub filters: Vec<Filter>,
+    /// Globs file names must match one of.
+    pub include: Vec<String>,
+    /// Globs of file names to leave alone.
+    pub exclude: Vec<String>,
+    pub on_conflict: Option<OnConflict>,
+    /// Directory renamed files are moved into.
+    pub target: Option<PathBuf>,
+    /// exiftool run by `--use-exiftool`, unless `--exiftool-path` is given.
+    pub exiftool: Option<PathBuf>,
+    /// Shell commands run after each rename.
+    pub hooks: Vec<String>,
+    pub age_buckets: Option<AgeBuckets>,
+    pub size_tiers: Option<SizeTiers>,
+    pub rules: Vec<Rule>,
+    /// Variables named after exif tags, e.g. `camera` for `Model`.
+    pub aliases: BTreeMap<String, String>,
+    /// Clock offsets by camera model, after those of `--sync-offsets`.
+    pub model_offsets: BTreeMap<String, TimeDelta>,
+    /// Patterns selected by name with `--preset`.
+    pub presets: BTreeMap<String, Pattern>,
+}
+
+impl Settings {
+    /// Returns the pattern of preset `name`.
+    pub fn preset(&self, name: &str) -> Result<&Pattern, String> {
+        self.presets.get(name).ok_or_else(|| {
+            let mut message = format!("unknown preset '{}'", name);
+            if let Some(similar) = suggest(name, self.presets.keys().map(String::as_str)) {
+                message.push_str(&format!(", did you mean '{}'?", similar));
+            }
+            message
+        })
+    }
+}
+
+/// Top-level config and profiles share the same keys; only the top level
+/// may have `profiles`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSettings {
+    pattern: Option<Spanned<String>>,
+    #[serde(rename = "dir-pattern")]
+    dir_pattern: Option<Spanned<String>>,
+    #[serde(default)]
+    filters: Vec<Spanned<String>>,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(rename = "on-conflict")]
+    on_conflict: Option<Spanned<String>>,
+    target: Option<PathBuf>,
+    exiftool: Option<PathBuf>,
+    #[serde(default)]
+    hooks: Vec<String>,
+    #[serde(rename = "age-buckets")]
+    age_buckets: Option<Spanned<String>>,
+    #[serde(rename = "size-tiers")]
+    size_tiers: Option<Spanned<String>>,
+    #[serde(default)]
+    rules: Vec<Spanned<RawRule>>,
+    #[serde(default)]
+    macros: BTreeMap<String, Spanned<String>>,
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+    #[serde(default, rename = "model-offsets")]
+    model_offsets: BTreeMap<String, Spanned<String>>,
+    #[serde(default)]
+    presets: BTreeMap<String, Spanned<String>>,
+    profiles: Option<Spanned<BTreeMap<String, RawSettings>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    #[serde(default)]
+    ext: Vec<String>,
+    #[serde(default)]
+    filters: Vec<Spanned<String>>,
+    after: Option<Spanned<String>>,
+    before: Option<Spanned<String>>,
+    pattern: Option<Spanned<String>>,
+    #[serde(default)]
+    skip: bool,
+    quarantine: Option<PathBuf>,
+    #[serde(rename = "copy-to")]
+    copy_to: Option<PathBuf>,
+}
+
+impl Config {
+    /// Returns the top-level settings overridden by `profile`, if given.
+    pub fn resolve(&self, profile: Option<&str>) -> Result<Settings, ConfigError> {
+        let Some(name) = profile else {
+            return Ok(self.settings.clone());
+        };
+        let profile = self.profiles.get(name).ok_or_else(|| ConfigError {
+            path: self.path.clone(),
+            position: None,
+            message: format!("unknown profile '{}'", name),
+            help: suggest(name, self.profiles.keys().map(String::as_str))
+                .map(|p| format!("did you mean `{}`?", p)),
+        })?;
+
+        let top = &self.settings;
+        fn pick<T: Clone>(values: &[T], fallback: &[T]) -> Vec<T> {
+            if values.is_empty() { fallback } else { values }.to_vec()
+        }
+        let mut aliases = top.aliases.clone();
+        aliases.extend(profile.aliases.clone());
+        let mut model_offsets = top.model_offsets.clone();
+        model_offsets.extend(profile.model_offsets.clone());
+        let mut presets = top.presets.clone();
+        presets.extend(profile.presets.clone());
+        Ok(Settings {
+            pattern: profile.pattern.clone().or_else(|| top.pattern.clone()),
+            dir_pattern: profile
+                .dir_pattern
+                .clone()
+                .or_else(|| top.dir_pattern.clone()),
+            filters: pick(&profile.filters, &top.filters),
+            include: pick(&profile.include, &top.include),
+            exclude: pick(&profile.exclude, &top.exclude),
+            on_conflict: profile.on_conflict.or(top.on_conflict),
+            target: profile.target.clone().or_else(|| top.target.clone()),
+            exiftool: profile.exiftool.clone().or_else(|| top.exiftool.clone()),
+            hooks: pick(&profile.hooks, &top.hooks),
+            age_buckets: profile
+                .age_buckets
+                .clone()
+                .or_else(|| top.age_buckets.clone()),
+            size_tiers: profile
+                .size_tiers
+                .clone()
+                .or_else(|| top.size_tiers.clone()),
+            rules: pick(&profile.rules, &top.rules),
+            aliases,
+            model_offsets,
+            presets,
+        })
+    }
+}
+
+/// A config problem with the location it was found at.
+#[derive(Debug)]
+pub struct ConfigError {
+    path: PathBuf,
+    /// 1-based line and column, if known.
+    position: Option<(usize, usize)>,
+    message: String,
+    help: Option<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some((line, col)) => write!(f, "{}:{}:{}: ", self.path.display(), line, col)?,
+            None => write!(f, "{}: ", self.path.display())?,
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(help) = &self.help {
+            write!(f, "\n  help: {}", help)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Returns `$XDG_CONFIG_HOME/exif-rename/config.toml`, falling back to
+/// `~/.config/exif-rename/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some(base.join("exif-rename").join("config.toml"))
+}
+
+/// Loads the config at `path`. If `path` is `None`, the default config is
+/// used when it exists.
+pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+    match fs::read_to_string(&path) {
+        Ok(source) => parse(&path, &source),
+        Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => Ok(Config {
+            path,
+            ..Config::default()
+        }),
+        Err(e) => Err(ConfigError {
+            path,
+            position: None,
+            message: e.to_string(),
+            help: None,
+        }),
+    }
+}
+
+/// Parses and validates config `source` read from `path`.
+pub fn parse(path: &Path, source: &str) -> Result<Config, ConfigError> {
+    let error = |offset: Option<usize>, message: String, help: Option<String>| ConfigError {
+        path: path.to_path_buf(),
+        position: offset.map(|o| line_col(source, o)),
+        message,
+        help,
+    };
+
+    let mut raw: RawSettings = toml::from_str(source).map_err(|e| {
+        let start = e.span().map(|s| s.start);
+        let keys = KEYS.iter().chain(RULE_KEYS).copied();
+        let help = e
+            .span()
+            .filter(|_| e.message().starts_with("unknown field"))
+            .and_then(|s| suggest(source[s].trim_matches(['"', '\'']), keys))
+            .map(|key| format!("did you mean `{}`?", key));
+        error(start, e.message().trim().to_string(), help)
+    })?;
+
+    let at = |(offset, message)| error(Some(offset), message, None);
+    let settings = |raw: &RawSettings, macros: &Macros| {
+        Ok(Settings {
+            pattern: raw
+                .pattern
+                .as_ref()
+                .map(|pattern| parse_pattern(pattern, macros))
+                .transpose()
+                .map_err(at)?,
+            dir_pattern: raw
+                .dir_pattern
+                .as_ref()
+                .map(|pattern| parse_pattern(pattern, macros))
+                .transpose()
+                .map_err(at)?,
+            filters: raw
+                .filters
+                .iter()
+                .map(parse_value)
+                .collect::<Result<_, _>>()
+                .map_err(at)?,
+            include: raw.include.clone(),
+            exclude: raw.exclude.clone(),
+            on_conflict: raw
+                .on_conflict
+                .as_ref()
+                .map(parse_value)
+                .transpose()
+                .map_err(at)?,
+            target: raw.target.clone(),
+            exiftool: raw.exiftool.clone(),
+            hooks: raw.hooks.clone(),
+            age_buckets: raw
+                .age_buckets
+                .as_ref()
+                .map(parse_value)
+                .transpose()
+                .map_err(at)?,
+            size_tiers: raw
+                .size_tiers
+                .as_ref()
+                .map(parse_value)
+                .transpose()
+                .map_err(at)?,
+            rules: raw
+                .rules
+                .iter()
+                .map(|rule| parse_rule(rule, macros))
+                .collect::<Result<_, _>>()
+                .map_err(at)?,
+            aliases: raw.aliases.clone(),
+            model_offsets: raw
+                .model_offsets
+                .iter()
+                .map(|(model, offset)| Ok((model.clone(), parse_offset(offset)?)))
+                .collect::<Result<_, _>>()
+                .map_err(at)?,
+            presets: raw
+                .presets
+                .iter()
+                .map(|(name, pattern)| Ok((name.clone(), parse_pattern(pattern, macros)?)))
+                .collect::<Result<_, _>>()
+                .map_err(at)?,
+        })
+    };
+
+    let macros: Macros = raw.macros.iter().map(|(k, v)| (k.as_str(), v)).collect();
+    let top = settings(&raw, &macros)?;
+    let profiles = raw
+        .profiles
+        .take()
+        .map(Spanned::into_inner)
+        .unwrap_or_default()
+        .iter()
+        .map(|(name, profile)| {
+            if let Some(nested) = &profile.profiles {
+                let message = format!("profile '{}' can't have profiles", name);
+                return Err(error(Some(nested.span().start), message, None));
+            }
+            let mut macros = macros.clone();
+            macros.extend(profile.macros.iter().map(|(k, v)| (k.as_str(), v)));
+            Ok((name.clone(), settings(profile, &macros)?))
+        })
+        .collect::<Result<_, ConfigError>>()?;
+
+    Ok(Config {
+        path: path.to_path_buf(),
+        settings: top,
+        profiles,
+    })
+}
+
+/// A parse error at a byte offset of the config source.
+type SpannedError = (usize, String);
+
+/// `[macros]` in scope of a pattern, by name.
+type Macros<'a> = BTreeMap<&'a str, &'a Spanned<String>>;
+
+fn parse_pattern(value: &Spanned<String>, macros: &Macros) -> Result<Pattern, SpannedError> {
+    // +1 skips the opening quote
+    let start = value.span().start + 1;
+    let source = expand(value.get_ref(), macros, &mut Vec::new())
+        .map_err(|(offset, message)| (start + offset, message))?;
+    Pattern::parse(&source).map_err(|e| {
+        // positions in an expanded pattern don't match the config source
+        let offset = if source == *value.get_ref() {
+            e.span.start
+        } else {
+            0
+        };
+        (start + offset, e.to_string())
+    })
+}
+
+/// Replaces `${name}` in `text` with the expanded macro. `$${` is a literal
+/// `${`. `stack` holds the macros being expanded, to detect cycles. Errors
+/// are at a byte offset of `text`.
+fn expand(text: &str, macros: &Macros, stack: &mut Vec<String>) -> Result<String, SpannedError> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find("${") {
+        let offset = text.len() - rest.len() + i;
+        if rest[..i].ends_with('$') {
+            out.push_str(&rest[..i - 1]);
+            out.push_str("${");
+            rest = &rest[i + 2..];
+            continue;
+        }
+        out.push_str(&rest[..i]);
+        let Some(end) = rest[i..].find('}') else {
+            return Err((offset, "unclosed '${'".to_string()));
+        };
+        let name = &rest[i + 2..i + end];
+        let Some(value) = macros.get(name) else {
+            let mut message = format!("unknown macro '{}'", name);
+            if let Some(similar) = suggest(name, macros.keys().copied()) {
+                message.push_str(&format!(", did you mean '{}'?", similar));
+            }
+            return Err((offset, message));
+        };
+        if stack.iter().any(|m| m == name) {
+            let message = format!("macro '{}' includes itself", name);
+            return Err((offset, message));
+        }
+        stack.push(name.to_string());
+        // errors inside the macro point at its use
+        let expanded = expand(value.get_ref(), macros, stack).map_err(|(_, m)| (offset, m))?;
+        stack.pop();
+        out.push_str(&expanded);
+        rest = &rest[i + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn parse_value<T: FromStr<Err = String>>(value: &Spanned<String>) -> Result<T, SpannedError> {
+    value.get_ref().parse().map_err(|e| (value.span().start, e))
+}
+
+fn parse_offset(value: &Spanned<String>) -> Result<TimeDelta, SpannedError> {
+    camera::parse_clock_offset(value.get_ref()).ok_or_else(|| {
+        let message = format!(
+            "invalid offset '{}', expected e.g. -00:01:12",
+            value.get_ref()
+        );
+        (value.span().start, message)
+    })
+}
+
+fn parse_date(value: &Spanned<String>) -> Result<NaiveDate, SpannedError> {
+    NaiveDate::parse_from_str(value.get_ref(), "%Y-%m-%d").map_err(|_| {
+        let message = format!("invalid date '{}', expected YYYY-MM-DD", value.get_ref());
+        (value.span().start, message)
+    })
+}
+
+fn parse_rule(raw: &Spanned<RawRule>, macros: &Macros) -> Result<Rule, SpannedError> {
+    let rule = raw.get_ref();
+    let mut actions = Vec::new();
+    if let Some(pattern) = &rule.pattern {
+        actions.push(Action::Rename(parse_pattern(pattern, macros)?));
+    }
+    if rule.skip {
+        actions.push(Action::Skip);
+    }
+    if let Some(dir) = &rule.quarantine {
+        actions.push(Action::Quarantine(dir.clone()));
+    }
+    if let Some(dir) = &rule.copy_to {
+        actions.push(Action::CopyTo(dir.clone()));
+    }
+    if actions.len() != 1 {
+        let message =
+            "rule needs exactly one of `pattern`, `skip`, `quarantine` or `copy-to`".to_string();
+        return Err((raw.span().start, message));
+    }
+
+    Ok(Rule {
+        extensions: rule
+            .ext
+            .iter()
+            .map(|e| e.trim_start_matches('.').to_lowercase())
+            .collect(),
+        filters: rule
+            .filters
+            .iter()
+            .map(parse_value)
+            .collect::<Result<_, _>>()?,
+        after: rule.after.as_ref().map(parse_date).transpose()?,
+        before: rule.before.as_ref().map(parse_date).transpose()?,
+        action: actions.remove(0),
+    })
+}
+
+/// Returns the candidate closest to `key`, if any is close enough.
+fn suggest<'a>(key: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|k| (strsim::jaro_winkler(key, k), k))
+        .filter(|(score, _)| *score > 0.8)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, k)| k)
+}
+
+fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err(source: &str) -> String {
+        parse(Path::new("config.toml"), source)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse() {
+        let source = "pattern = \"{Y}{m}{D}_{t}.{e}\"\nfilters = [\"!is-pano\"]\n";
+        let config = parse(Path::new("config.toml"), source).unwrap();
+        let settings = config.resolve(None).unwrap();
+        assert_eq!(settings.pattern.unwrap().to_string(), "{Y}{m}{D}_{t}.{e}");
+        assert_eq!(settings.filters.len(), 1);
+        assert_eq!(settings.dir_pattern, None);
+
+        let source = "dir-pattern = \"{Y}/{m}\"\n";
+        let config = parse(Path::new("config.toml"), source).unwrap();
+        let dir_pattern = config.resolve(None).unwrap().dir_pattern.unwrap();
+        assert_eq!(dir_pattern.to_string(), "{Y}/{m}");
+    }
+
+    #[test]
+    fn test_profiles() {
+        let source = r#"
+pattern = "{Y}{m}{D}_{t}.{e}"
+filters = ["!is-pano"]
+hooks = ["sync"]
+exiftool = "/opt/exiftool/exiftool"
+
+[profiles.work]
+target = "/archive/work"
+
+[profiles.personal]
+pattern = "{Y}/{m}/{t}.{e}"
+filters = ["Model=iPhone 14"]
+"#;
+        let config = parse(Path::new("config.toml"), source).unwrap();
+
+        let work = config.resolve(Some("work")).unwrap();
+        assert_eq!(work.pattern.unwrap().to_string(), "{Y}{m}{D}_{t}.{e}");
+        assert_eq!(work.target, Some(PathBuf::from("/archive/work")));
+        assert_eq!(work.hooks, vec!["sync"]);
+
+        let personal = config.resolve(Some("personal")).unwrap();
+        assert_eq!(personal.pattern.unwrap().to_string(), "{Y}/{m}/{t}.{e}");
+        assert_eq!(personal.filters, vec!["Model=iPhone 14".parse().unwrap()]);
+        assert_eq!(personal.target, None);
+        assert_eq!(
+            personal.exiftool,
+            Some(PathBuf::from("/opt/exiftool/exiftool"))
+        );
+
+        let err = config.resolve(Some("wrok")).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "config.toml: unknown profile 'wrok'\n  help: did you mean `work`?"
+        );
+
+        let err = parse_err("[profiles.work]\npattern = \"{Y\"\n");
+        assert!(
+            err.starts_with("config.toml:2:12: invalid pattern"),
+            "{}",
+            err
+        );
+        let err = parse_err("[profiles.work]\nage-buckets = \"1y,6m\"\n");
+        assert!(
+            err.starts_with("config.toml:2:15: age thresholds must be ascending"),
+            "{}",
+            err
+        );
+        let err = parse_err("[profiles.work.profiles.home]\n");
+        assert!(
+            err.contains("profile 'work' can't have profiles"),
+            "{}",
+            err
+        );
+        let err = parse_err("[profiles.work]\ntraget = \"/tmp\"\n");
+        assert!(err.ends_with("did you mean `target`?"), "{}", err);
+    }
+
+    #[test]
+    fn test_defaults() {
+        let source = r#"
+include = ["*.jpg"]
+on-conflict = "number"
+
+[aliases]
+camera = "Model"
+lens = "LensModel"
+
+[profiles.video]
+include = ["*.mov", "*.mp4"]
+on-conflict = "skip"
+
+[profiles.video.aliases]
+camera = "Make"
+
+[model-offsets]
+"NIKON D750" = "-00:01:12"
+"#;
+        let config = parse(Path::new("config.toml"), source).unwrap();
+        let settings = config.resolve(None).unwrap();
+        assert_eq!(settings.include, vec!["*.jpg"]);
+        assert_eq!(settings.on_conflict, Some(OnConflict::Number));
+        assert_eq!(settings.aliases["camera"], "Model");
+
+        let video = config.resolve(Some("video")).unwrap();
+        assert_eq!(video.include, vec!["*.mov", "*.mp4"]);
+        assert_eq!(video.on_conflict, Some(OnConflict::Skip));
+        assert_eq!(video.aliases["camera"], "Make");
+        assert_eq!(video.aliases["lens"], "LensModel");
+        assert_eq!(video.model_offsets["NIKON D750"], TimeDelta::seconds(-72));
+
+        let err = parse_err("[model-offsets]\n\"X\" = \"1h\"\n");
+        assert!(
+            err.starts_with("config.toml:2:7: invalid offset '1h'"),
+            "{}",
+            err
+        );
+
+        let err = parse_err("on-conflict = \"rename\"\n");
+        assert!(
+            err.starts_with("config.toml:1:15: invalid strategy 'rename'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_presets() {
+        let source = r#"
+[presets]
+travel = "${date}_{T2}.{e}"
+event = "{Y}-{m}-{D}/{t}.{e}"
+
+[macros]
+date = "{Y}-{m}-{D}_{t}"
+
+[profiles.work.presets]
+event = "{Y}{m}{D}_{t}.{e}"
+"#;
+        let config = parse(Path::new("config.toml"), source).unwrap();
+        let settings = config.resolve(None).unwrap();
+        let travel = settings.preset("travel").unwrap();
+        assert_eq!(travel.to_string(), "{Y}-{m}-{D}_{t}_{T2}.{e}");
+        assert_eq!(
+            settings.preset("travle").unwrap_err(),
+            "unknown preset 'travle', did you mean 'travel'?"
+        );
+        let work = config.resolve(Some("work")).unwrap();
+        assert_eq!(
+            work.preset("event").unwrap().to_string(),
+            "{Y}{m}{D}_{t}.{e}"
+        );
+        assert!(work.preset("travel").is_ok());
+
+        let err = parse_err("[presets]\ntravel = \"{Y\"\n");
+        assert!(
+            err.starts_with("config.toml:2:11: invalid pattern"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let source = r#"
+pattern = "{Y}{m}{D}_{t}.{e}"
+
+[[rules]]
+ext = [".MOV", "mp4"]
+pattern = "videos/{t}.{e}"
+
+[[rules]]
+filters = ["Model=iPhone 6"]
+after = "2014-01-01"
+before = "2015-01-01"
+quarantine = "check"
+"#;
+        let config = parse(Path::new("config.toml"), source).unwrap();
+        let rules = config.resolve(None).unwrap().rules;
+        assert_eq!(rules[0].extensions, vec!["mov", "mp4"]);
+        assert_eq!(
+            rules[0].action,
+            Action::Rename(Pattern::parse("videos/{t}.{e}").unwrap())
+        );
+        assert_eq!(rules[1].after, NaiveDate::from_ymd_opt(2014, 1, 1));
+        assert_eq!(rules[1].action, Action::Quarantine(PathBuf::from("check")));
+
+        let err = parse_err("[[rules]]\next = [\"jpg\"]\n");
+        assert!(
+            err.starts_with("config.toml:1:1: rule needs exactly one"),
+            "{}",
+            err
+        );
+        let err = parse_err("[[rules]]\nskip = true\ncopy-to = \"x\"\n");
+        assert!(
+            err.starts_with("config.toml:1:1: rule needs exactly one"),
+            "{}",
+            err
+        );
+        let err = parse_err("[[rules]]\nskip = true\nbefore = \"2015\"\n");
+        assert!(
+            err.starts_with("config.toml:3:10: invalid date '2015'"),
+            "{}",
+            err
+        );
+        let err = parse_err("[[rules]]\nskip = true\nextension = [\"jpg\"]\n");
+        assert!(err.ends_with("did you mean `ext`?"), "{}", err);
+    }
+
+    #[test]
+    fn test_macros() {
+        let source = r#"
+pattern = "${date}.{e}"
+
+[macros]
+date = "{Y}{m}{D}_${time}"
+time = "{t}"
+
+[profiles.work]
+pattern = "${date}_{T2}.{e}"
+dir-pattern = "$${Y}"
+
+[profiles.home]
+pattern = "${date}.{e}"
+
+[profiles.home.macros]
+time = "{H}h"
+
+[[rules]]
+ext = ["mov"]
+pattern = "videos/${date}.{e}"
+"#;
+        let config = parse(Path::new("config.toml"), source).unwrap();
+        let settings = config.resolve(None).unwrap();
+        assert_eq!(settings.pattern.unwrap().to_string(), "{Y}{m}{D}_{t}.{e}");
+        assert_eq!(
+            settings.rules[0].action,
+            Action::Rename(Pattern::parse("videos/{Y}{m}{D}_{t}.{e}").unwrap())
+        );
+        let work = config.resolve(Some("work")).unwrap();
+        assert_eq!(work.pattern.unwrap().to_string(), "{Y}{m}{D}_{t}_{T2}.{e}");
+        assert_eq!(work.dir_pattern.unwrap().to_string(), "${Y}");
+        let home = config.resolve(Some("home")).unwrap();
+        assert_eq!(home.pattern.unwrap().to_string(), "{Y}{m}{D}_{H}h.{e}");
+
+        let err = parse_err("pattern = \"{Y}${dat}\"\n[macros]\ndate = \"{Y}\"\n");
+        assert_eq!(
+            err,
+            "config.toml:1:15: unknown macro 'dat', did you mean 'date'?"
+        );
+        let err = parse_err("pattern = \"${a}\"\n[macros]\na = \"${b}\"\nb = \"${a}\"\n");
+        assert_eq!(err, "config.toml:1:12: macro 'a' includes itself");
+        let err = parse_err("pattern = \"{Y}${date\"\n");
+        assert_eq!(err, "config.toml:1:15: unclosed '${'");
+        let err = parse_err("pattern = \"${date}\"\n[macros]\ndate = \"{Y\"\n");
+        assert!(
+            err.starts_with("config.toml:1:12: invalid pattern: unclosed '{'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let err = parse_err("filters = []\npatern = \"{Y}\"\n");
+        assert!(
+            err.starts_with("config.toml:2:1: unknown field `patern`"),
+            "{}",
+            err
+        );
+        assert!(err.ends_with("help: did you mean `pattern`?"), "{}", err);
+
+        let err = parse_err("colour = 1\n");
+        assert!(!err.contains("help"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let err = parse_err("\n\npattern = \"{Y}{m\"\n");
+        assert!(
+            err.starts_with("config.toml:3:15: invalid pattern: unclosed '{'"),
+            "{}",
+            err
+        );
+
+        let err = parse_err("filters = [\"is-pano\", \"pano\"]\n");
+        assert_eq!(err, "config.toml:1:23: unknown filter 'pano'");
+
+        let err = parse_err("pattern = 1\n");
+        assert!(err.starts_with("config.toml:1:11: invalid type"), "{}", err);
+    }
+}
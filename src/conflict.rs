@@ -0,0 +1,127 @@
+//! `--on-conflict`: what to do when the new name of a file is taken, by an
+//! existing file or by another file of the same run.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::suffix::SuffixFormat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OnConflict {
+    /// Fail the file.
+    #[default]
+    Error,
+    /// Leave the file untouched.
+    Skip,
+    /// Replace the file with the new name.
+    Overwrite,
+    /// Append `-1`, `-2`, ... to the new name until it is free, or numbers
+    /// in another `--suffix-format`.
+    Number,
+}
+
+impl FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(OnConflict::Error),
+            "skip" => Ok(OnConflict::Skip),
+            "overwrite" => Ok(OnConflict::Overwrite),
+            "number" => Ok(OnConflict::Number),
+            _ => Err(format!(
+                "invalid strategy '{}', expected error, skip, overwrite or number",
+                s
+            )),
+        }
+    }
+}
+
+/// Returns where `source` can go instead of `target`, or `None` to skip
+/// it. `taken` holds the targets of earlier files of the run, which count
+/// as existing so that dry runs see the same conflicts.
+pub fn resolve(
+    source: &Path,
+    target: PathBuf,
+    on_conflict: OnConflict,
+    suffix: &SuffixFormat,
+    taken: &HashSet<PathBuf>,
+) -> Result<Option<PathBuf>, String> {
+    let is_taken = |path: &Path| {
+        // a name differing only in case can be the source itself on
+        // case-insensitive filesystems
+        let is_source = matches!(
+            (fs::canonicalize(source), fs::canonicalize(path)),
+            (Ok(a), Ok(b)) if a == b
+        );
+        taken.contains(path) || (path.exists() && !is_source)
+    };
+    if !is_taken(&target) {
+        return Ok(Some(target));
+    }
+    match on_conflict {
+        OnConflict::Error => Err(format!(
+            "{} already exists, see --on-conflict",
+            target.display()
+        )),
+        OnConflict::Skip => Ok(None),
+        OnConflict::Overwrite => Ok(Some(target)),
+        OnConflict::Number => {
+            let stem = target.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = target.extension().map(|e| e.to_string_lossy());
+            let mut numbered = (1..).map(|n| {
+                let name = match &ext {
+                    Some(ext) => format!("{}{}.{}", stem, suffix.format(n), ext),
+                    None => format!("{}{}", stem, suffix.format(n)),
+                };
+                target.with_file_name(name)
+            });
+            Ok(numbered.find(|path| !is_taken(path)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-conflict-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("IMG_1.jpg");
+        let target = dir.join("20230908.jpg");
+        fs::write(&source, "").unwrap();
+        fs::write(&target, "").unwrap();
+        let mut taken = HashSet::new();
+        taken.insert(dir.join("20230908-1.jpg"));
+        let dash = SuffixFormat::new("-");
+        let resolve = |on_conflict| resolve(&source, target.clone(), on_conflict, &dash, &taken);
+
+        assert!(resolve(OnConflict::Error)
+            .unwrap_err()
+            .ends_with("20230908.jpg already exists, see --on-conflict"));
+        assert_eq!(resolve(OnConflict::Skip), Ok(None));
+        assert_eq!(resolve(OnConflict::Overwrite), Ok(Some(target.clone())));
+        assert_eq!(
+            resolve(OnConflict::Number),
+            Ok(Some(dir.join("20230908-2.jpg")))
+        );
+        let copy = " (copy {n})".parse().unwrap();
+        assert_eq!(
+            super::resolve(&source, target.clone(), OnConflict::Number, &copy, &taken),
+            Ok(Some(dir.join("20230908 (copy 1).jpg")))
+        );
+        let free = dir.join("free.jpg");
+        assert_eq!(
+            super::resolve(&source, free.clone(), OnConflict::Error, &dash, &taken),
+            Ok(Some(free))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!("number".parse(), Ok(OnConflict::Number));
+        assert!("rename".parse::<OnConflict>().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Optional transcoding step (`--convert heic:jpg`) applied while renaming.
+//!
+//! Built with the `convert` feature. HEIC/HEIF decoding additionally needs
+//! the `heic` feature, which links against the system `libheif`.
+
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Once;
+
+use image::{DynamicImage, ImageReader};
+
+/// Source and target extensions of a conversion, e.g. `heic:jpg`.
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for Conversion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok(Conversion {
+                from: from.to_lowercase(),
+                to: to.to_lowercase(),
+            }),
+            _ => Err(format!("expected FROM:TO (e.g. heic:jpg), got '{}'", s)),
+        }
+    }
+}
+
+impl Conversion {
+    /// Returns true if `path` has the source extension (case-insensitive).
+    pub fn matches(&self, path: &Path) -> bool {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase() == self.from)
+            .unwrap_or(false)
+    }
+}
+
+/// Decodes `src` and writes it to `dst`, picking the format from the
+/// extension of `dst`.
+pub fn convert(src: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+    let img = decode(src)?;
+    let is_jpeg = dst
+        .extension()
+        .map(|ext| {
+            matches!(
+                ext.to_string_lossy().to_lowercase().as_str(),
+                "jpg" | "jpeg"
+            )
+        })
+        .unwrap_or(false);
+    // JPEG has no alpha channel
+    let img = if is_jpeg {
+        DynamicImage::ImageRgb8(img.to_rgb8())
+    } else {
+        img
+    };
+    img.save(dst)?;
+    Ok(())
+}
+
+/// Decodes the image at `path`, guessing the format from its contents.
+pub fn decode(path: &Path) -> Result<DynamicImage, Box<dyn Error>> {
+    register_decoders();
+    Ok(ImageReader::open(path)?.with_guessed_format()?.decode()?)
+}
+
+fn register_decoders() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        #[cfg(feature = "heic")]
+        libheif_rs::integration::image::register_all_decoding_hooks();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_conversion() {
+        let c: Conversion = "HEIC:jpg".parse().unwrap();
+        assert_eq!((c.from.as_str(), c.to.as_str()), ("heic", "jpg"));
+        assert!(c.matches(Path::new("IMG_0001.HEIC")));
+        assert!(!c.matches(Path::new("IMG_0001.jpg")));
+        assert!("heic".parse::<Conversion>().is_err());
+    }
+
+    #[test]
+    fn test_convert() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("src.png");
+        let dst = dir.join("dst.jpg");
+        DynamicImage::new_rgba8(4, 4).save(&src).unwrap();
+
+        convert(&src, &dst).unwrap();
+        assert_eq!(image::open(&dst).unwrap().width(), 4);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
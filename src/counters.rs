@@ -0,0 +1,87 @@
+//! `--counters`: the last value `{n}` took in each scope, so a later import
+//! continues the numbering instead of starting over and colliding.
+//!
+//! One `VALUE<TAB>SCOPE` per line. The scope is empty for a whole run,
+//! else the capture day with `--counter-per-day` and the directory with
+//! `--counter-per-dir`, separated by a tab.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Counters(BTreeMap<String, u64>);
+
+impl Counters {
+    /// Reads the counters at `path`. A missing file has none.
+    pub fn load(path: &Path) -> io::Result<Counters> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Counters::default()),
+            Err(e) => return Err(e),
+        };
+        let mut counters = Counters::default();
+        for (i, line) in source.lines().enumerate().filter(|(_, l)| !l.is_empty()) {
+            let (value, scope) = line.split_once('\t').unwrap_or((line, ""));
+            let value = value.parse().map_err(|_| {
+                let message = format!("line {}: invalid counter '{}'", i + 1, value);
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            })?;
+            counters.0.insert(scope.to_string(), value);
+        }
+        Ok(counters)
+    }
+
+    /// Writes the counters to `path`, replacing it whole so an interrupted
+    /// write leaves the previous values.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let lines: String = self
+            .0
+            .iter()
+            .map(|(scope, value)| format!("{}\t{}\n", value, scope))
+            .collect();
+        let mut tmp = path.as_os_str().to_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, lines)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// The last value taken in `scope`, if any.
+    pub fn last(&self, scope: &str) -> Option<u64> {
+        self.0.get(scope).copied()
+    }
+
+    /// Records that `n` was taken in `scope`, keeping the highest value.
+    pub fn record(&mut self, scope: String, n: u64) {
+        let last = self.0.entry(scope).or_insert(n);
+        *last = (*last).max(n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let path =
+            std::env::temp_dir().join(format!("exif-rename-counters-{}", std::process::id()));
+        let mut counters = Counters::load(&path).unwrap();
+        assert_eq!(counters.last(""), None);
+
+        counters.record(String::new(), 214);
+        counters.record("2023-09-08\tDCIM/100CANON".to_string(), 3);
+        counters.record("2023-09-08\tDCIM/100CANON".to_string(), 2);
+        counters.save(&path).unwrap();
+        let loaded = Counters::load(&path).unwrap();
+        assert_eq!(loaded, counters);
+        assert_eq!(loaded.last(""), Some(214));
+        assert_eq!(loaded.last("2023-09-08\tDCIM/100CANON"), Some(3));
+
+        fs::write(&path, "12\t2023-09-08\nmany\t2023-09-09\n").unwrap();
+        let err = Counters::load(&path).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid counter 'many'");
+        fs::remove_file(&path).unwrap();
+    }
+}
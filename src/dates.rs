@@ -0,0 +1,365 @@
+//! Candidate capture dates from every source a file has, to catch files
+//! whose sources disagree: a camera clock never set, a scan dated by its
+//! scanner, or a name edited by hand.
+//!
+//! `--date-report` lists the candidates of each file next to the chosen
+//! date, and `--max-date-disagreement 1d` holds back files with a candidate
+//! further off than that, for review.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use regex::Regex;
+
+use crate::{parse_datetime, Vars};
+
+/// A date and time in a file name, e.g. `IMG_20230908_101112` or
+/// `2023-09-08 10.11.12`, or just the date.
+static NAME_DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?:^|\D)((?:19|20)\d\d)[-_.]?(\d\d)[-_.]?(\d\d)(?:[-_ T.]?(\d\d)[-_.:h]?(\d\d)[-_.:m]?(\d\d))?(?:\D|$)",
+    )
+    .unwrap()
+});
+
+/// A date in an XMP sidecar, as an attribute or an element.
+static XMP_DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?:exif:DateTimeOriginal|xmp:CreateDate|photoshop:DateCreated)(?:="|>)([^"<]+)"#)
+        .unwrap()
+});
+
+/// A date a file might have been captured at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// Where the date comes from, e.g. `file name`.
+    pub source: &'static str,
+    pub date: NaiveDateTime,
+    /// True if the source has no time of day.
+    pub date_only: bool,
+}
+
+impl Candidate {
+    /// How far the candidate is from `chosen`. Dates without a time are
+    /// off by whole days only.
+    pub fn delta(&self, chosen: NaiveDateTime) -> TimeDelta {
+        if self.date_only {
+            TimeDelta::days((self.date.date() - chosen.date()).num_days())
+        } else {
+            self.date - chosen
+        }
+    }
+}
+
+/// Largest difference allowed between candidates and the chosen date.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Disagreement(pub TimeDelta);
+
+impl FromStr for Disagreement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid duration '{}', expected e.g. 90s, 30m, 12h or 1d",
+                s
+            )
+        };
+        let s = s.trim();
+        let unit = s.chars().last().ok_or_else(invalid)?;
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 7 * 86400,
+            _ => return Err(invalid()),
+        };
+        let value: i64 = s[..s.len() - 1].parse().map_err(|_| invalid())?;
+        if value < 0 {
+            return Err(invalid());
+        }
+        Ok(Disagreement(TimeDelta::seconds(value * seconds)))
+    }
+}
+
+/// `--date-source`: tags to take the capture date from, tried in order,
+/// e.g. `DateTimeOriginal>CreateDate>MediaCreateDate>mtime`. `mtime` is
+/// the file modification time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateSource(Vec<String>);
+
+impl FromStr for DateSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sources: Vec<String> = s.split(['>', ',']).map(|t| t.trim().to_string()).collect();
+        let valid = |t: &String| {
+            !t.is_empty() && t.chars().all(|c| c.is_alphanumeric() || "_-/".contains(c))
+        };
+        if let Some(invalid) = sources.iter().find(|t| !valid(t)) {
+            return Err(format!(
+                "invalid date source '{}' in '{}', expected tag names such as \
+                 DateTimeOriginal>CreateDate>mtime",
+                invalid, s
+            ));
+        }
+        Ok(DateSource(sources))
+    }
+}
+
+impl DateSource {
+    /// Returns the date of the first source with one, with the confidence
+    /// the built-in choice has: `high` for a date and time, `low` for a
+    /// date alone or the modification time.
+    pub fn date(&self, exif: &Vars) -> Option<(NaiveDateTime, &'static str)> {
+        self.0.iter().find_map(|source| {
+            if source == "mtime" {
+                let mtime = ["FileModifyDate", "FileModificationDate/Time"]
+                    .iter()
+                    .find_map(|tag| exif.get(*tag).and_then(|s| parse_datetime(s)))?;
+                return Some((mtime, "low"));
+            }
+            // plain exiftool output spells `DateTimeOriginal` `Date/TimeOriginal`
+            let value = exif.get(source).or_else(|| {
+                exif.iter()
+                    .find(|(tag, _)| tag.replace('/', "") == *source)
+                    .map(|(_, value)| value)
+            })?;
+            if let Some(dt) = parse_datetime(value) {
+                return Some((dt, "high"));
+            }
+            let date = NaiveDate::parse_from_str(value.get(..10)?, "%Y:%m:%d").ok()?;
+            Some((date.and_time(NaiveTime::MIN), "low"))
+        })
+    }
+}
+
+/// Collects the candidate dates of `file` with tags `exif`: the tags, the
+/// file name (read back with `--from-pattern` if given as `recovered`),
+/// the modification and creation times and an XMP sidecar.
+pub fn candidates(
+    exif_date: Option<NaiveDateTime>,
+    exif: &Vars,
+    file: Option<&str>,
+    recovered: Option<NaiveDateTime>,
+    btime: Option<NaiveDateTime>,
+) -> Vec<Candidate> {
+    let exact = |source, date: Option<NaiveDateTime>| {
+        date.map(|date| Candidate {
+            source,
+            date,
+            date_only: false,
+        })
+    };
+    let name = file
+        .map(|f| f.to_string())
+        .or_else(|| exif.get("FileName").cloned());
+    let name_date = match recovered {
+        Some(date) => exact("file name", Some(date)),
+        None => name.as_deref().and_then(name_date),
+    };
+    let mtime = file
+        .and_then(|f| fs::metadata(f).and_then(|m| m.modified()).ok())
+        .map(|t| DateTime::<Local>::from(t).naive_local())
+        .or_else(|| exif.get("FileModifyDate").and_then(|s| parse_datetime(s)));
+    let sidecar = file.and_then(sidecar_date);
+
+    [
+        exact("exif", exif_date),
+        name_date,
+        exact("modification time", mtime),
+        exact("creation time", btime),
+        exact("sidecar", sidecar),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Returns the candidate furthest from `chosen` if it is more than `max`
+/// off.
+pub fn disagreement(
+    candidates: &[Candidate],
+    chosen: NaiveDateTime,
+    max: Disagreement,
+) -> Option<&Candidate> {
+    candidates
+        .iter()
+        .filter(|c| c.delta(chosen).abs() > max.0)
+        .max_by_key(|c| c.delta(chosen).abs())
+}
+
+/// Formats a delta as `+3d 2h`, `-45m` or `+12s`, to the two largest units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Delta(pub TimeDelta);
+
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.num_seconds();
+        if secs == 0 {
+            return write!(f, "0");
+        }
+        let sign = if secs < 0 { '-' } else { '+' };
+        let secs = secs.unsigned_abs();
+        let parts = [
+            (secs / 86400, 'd'),
+            (secs / 3600 % 24, 'h'),
+            (secs / 60 % 60, 'm'),
+            (secs % 60, 's'),
+        ];
+        let first = parts.iter().position(|(n, _)| *n > 0).unwrap_or(3);
+        let shown: Vec<String> = parts[first..(first + 2).min(4)]
+            .iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, unit)| format!("{}{}", n, unit))
+            .collect();
+        write!(f, "{}{}", sign, shown.join(" "))
+    }
+}
+
+fn name_date(path: &str) -> Option<Candidate> {
+    let stem = Path::new(path).file_stem()?.to_string_lossy().to_string();
+    let caps = NAME_DATE.captures(&stem)?;
+    let number = |i: usize| caps.get(i).and_then(|m| m.as_str().parse::<u32>().ok());
+    let date = NaiveDate::from_ymd_opt(caps[1].parse().ok()?, number(2)?, number(3)?)?;
+    let time = match (number(4), number(5), number(6)) {
+        (Some(h), Some(m), Some(s)) => Some(date.and_hms_opt(h, m, s)?),
+        _ => None,
+    };
+    Some(Candidate {
+        source: "file name",
+        date: time.unwrap_or(date.and_hms_opt(0, 0, 0)?),
+        date_only: time.is_none(),
+    })
+}
+
+/// Reads the capture date of the XMP sidecar of `file`, `IMG_1234.xmp` or
+/// `IMG_1234.JPG.xmp`.
+fn sidecar_date(file: &str) -> Option<NaiveDateTime> {
+    let path = Path::new(file);
+    let mut appended = path.as_os_str().to_os_string();
+    appended.push(".xmp");
+    let xmp = [path.with_extension("xmp"), appended.into()]
+        .into_iter()
+        .find_map(|sidecar| fs::read_to_string(sidecar).ok())?;
+    let value = XMP_DATE.captures(&xmp)?.get(1)?.as_str().to_string();
+    NaiveDateTime::parse_from_str(value.get(..19)?, "%Y-%m-%dT%H:%M:%S").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::exif_vars;
+
+    fn at(d: u32, h: u32, m: u32, s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 9, d)
+            .unwrap()
+            .and_hms_opt(h, m, s)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_name_date() {
+        let date = |name| name_date(name).map(|c| (c.date, c.date_only));
+        assert_eq!(
+            date("IMG_20230908_101112.jpg"),
+            Some((at(8, 10, 11, 12), false))
+        );
+        assert_eq!(
+            date("dir/2023-09-08 10.11.12.jpg"),
+            Some((at(8, 10, 11, 12), false))
+        );
+        assert_eq!(date("Scan 2023-09-08.tif"), Some((at(8, 0, 0, 0), true)));
+        assert_eq!(date("IMG_1234.JPG"), None);
+        assert_eq!(date("20231399.jpg"), None);
+        assert_eq!(date("120230908.jpg"), None);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-dates-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("IMG_20230907.JPG");
+        fs::write(&file, "").unwrap();
+        fs::write(
+            dir.join("IMG_20230907.xmp"),
+            r#"<rdf:Description xmp:CreateDate="2023-09-08T10:11:00+09:00"/>"#,
+        )
+        .unwrap();
+        let candidates = candidates(
+            Some(at(8, 10, 11, 12)),
+            &Vars::new(),
+            file.to_str(),
+            None,
+            None,
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        let sources: Vec<_> = candidates.iter().map(|c| c.source).collect();
+        assert_eq!(
+            sources,
+            vec!["exif", "file name", "modification time", "sidecar"]
+        );
+        assert_eq!(candidates[3].date, at(8, 10, 11, 0));
+        assert_eq!(candidates[1].delta(at(8, 10, 11, 12)), TimeDelta::days(-1));
+
+        let max = "1d".parse().unwrap();
+        let chosen = at(8, 10, 11, 12);
+        assert_eq!(disagreement(&candidates[..2], chosen, max), None);
+        let max = "12h".parse().unwrap();
+        let off = disagreement(&candidates[..2], chosen, max).unwrap();
+        assert_eq!(off.source, "file name");
+    }
+
+    #[test]
+    fn test_delta() {
+        let delta = |secs| Delta(TimeDelta::seconds(secs)).to_string();
+        assert_eq!(delta(0), "0");
+        assert_eq!(delta(12), "+12s");
+        assert_eq!(delta(-45 * 60), "-45m");
+        assert_eq!(delta(3 * 86400 + 2 * 3600 + 5), "+3d 2h");
+        assert_eq!(delta(86400 + 30), "+1d");
+    }
+
+    #[test]
+    fn test_date_source() {
+        let source: DateSource = "DateTimeOriginal > CreateDate>MediaCreateDate>mtime"
+            .parse()
+            .unwrap();
+        let date = |dump: &str| {
+            let (dt, confidence) = source.date(&exif_vars(dump))?;
+            Some((dt.to_string(), confidence))
+        };
+        let expect = |dt: &str, confidence| Some((dt.to_string(), confidence));
+        assert_eq!(
+            date("Create Date : 2023:09:08 10:11:12\nDate/Time Original : 2023:09:08 09:00:00\n"),
+            expect("2023-09-08 09:00:00", "high")
+        );
+        assert_eq!(
+            date("Media Create Date : 2023:09:08\nCreate Date : 0000:00:00 00:00:00\n"),
+            expect("2023-09-08 00:00:00", "low")
+        );
+        assert_eq!(
+            date("FileModifyDate : 2023:09:08 10:11:12+02:00\n"),
+            expect("2023-09-08 10:11:12", "low")
+        );
+        assert_eq!(date("Model : Scanner\n"), None);
+        assert!("CreateDate>>mtime".parse::<DateSource>().is_err());
+        assert!("Create Date".parse::<DateSource>().is_err());
+    }
+
+    #[test]
+    fn test_disagreement() {
+        assert_eq!("1d".parse::<Disagreement>().unwrap().0, TimeDelta::days(1));
+        assert_eq!(
+            "90s".parse::<Disagreement>().unwrap().0,
+            TimeDelta::seconds(90)
+        );
+        assert!("1y".parse::<Disagreement>().is_err());
+        assert!("d".parse::<Disagreement>().is_err());
+    }
+}
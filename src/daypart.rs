@@ -0,0 +1,160 @@
+//! `{daypart}` classification of a capture time.
+//!
+//! Without coordinates the day is split by the clock. With GPS coordinates
+//! sunrise and sunset are computed for the capture date (NOAA approximation)
+//! so "night" really means the sun was below the horizon.
+
+use std::f64::consts::PI;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+
+const MINUTES_PER_DAY: i32 = 24 * 60;
+
+/// Length of the "evening" part before sunset, in minutes.
+const EVENING_MINUTES: i32 = 120;
+
+/// Returns `morning`, `afternoon`, `evening` or `night` for a local capture time.
+///
+/// `utc_offset` is the offset of the capture time in minutes
+/// (`OffsetTimeOriginal`). If it's unknown, local solar time derived from the
+/// longitude is assumed.
+pub fn daypart(
+    local: NaiveDateTime,
+    utc_offset: Option<i32>,
+    coordinates: Option<(f64, f64)>,
+) -> &'static str {
+    let minute = (local.hour() * 60 + local.minute()) as i32;
+
+    let Some((lat, lon)) = coordinates else {
+        return clock_daypart(minute);
+    };
+    let offset = utc_offset.unwrap_or_else(|| (lon * 4.0).round() as i32);
+
+    match sun_events(local.ordinal(), lat, lon) {
+        SunEvents::PolarNight => "night",
+        SunEvents::PolarDay => clock_daypart(minute),
+        SunEvents::Normal { sunrise, sunset } => {
+            let sunrise = (sunrise + offset).rem_euclid(MINUTES_PER_DAY);
+            let sunset = (sunset + offset).rem_euclid(MINUTES_PER_DAY);
+            let day_length = (sunset - sunrise).rem_euclid(MINUTES_PER_DAY);
+            let since_sunrise = (minute - sunrise).rem_euclid(MINUTES_PER_DAY);
+
+            if since_sunrise >= day_length {
+                "night"
+            } else if day_length - since_sunrise <= EVENING_MINUTES {
+                "evening"
+            } else if since_sunrise < day_length / 2 {
+                "morning"
+            } else {
+                "afternoon"
+            }
+        }
+    }
+}
+
+/// Parses exif offsets like `+09:00` into minutes.
+pub fn parse_offset(s: &str) -> Option<i32> {
+    let s = s.trim();
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':')?;
+    Some(sign * (hours.parse::<i32>().ok()? * 60 + minutes.parse::<i32>().ok()?))
+}
+
+fn clock_daypart(minute: i32) -> &'static str {
+    match minute / 60 {
+        5..=11 => "morning",
+        12..=16 => "afternoon",
+        17..=20 => "evening",
+        _ => "night",
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SunEvents {
+    /// Sunrise and sunset in minutes since UTC midnight.
+    Normal {
+        sunrise: i32,
+        sunset: i32,
+    },
+    PolarDay,
+    PolarNight,
+}
+
+fn sun_events(day_of_year: u32, lat: f64, lon: f64) -> SunEvents {
+    let gamma = 2.0 * PI / 365.0 * (day_of_year as f64 - 1.0);
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let decl = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    let lat = lat.to_radians();
+    let cos_ha = 90.833_f64.to_radians().cos() / (lat.cos() * decl.cos()) - lat.tan() * decl.tan();
+    if cos_ha > 1.0 {
+        return SunEvents::PolarNight;
+    }
+    if cos_ha < -1.0 {
+        return SunEvents::PolarDay;
+    }
+
+    let ha = cos_ha.acos().to_degrees();
+    SunEvents::Normal {
+        sunrise: (720.0 - 4.0 * (lon + ha) - eqtime).round() as i32,
+        sunset: (720.0 - 4.0 * (lon - ha) - eqtime).round() as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    const SEOUL: Option<(f64, f64)> = Some((37.5665, 126.978));
+
+    #[test]
+    fn test_clock_daypart() {
+        assert_eq!(daypart(at("2023-09-08 06:00"), None, None), "morning");
+        assert_eq!(daypart(at("2023-09-08 13:00"), None, None), "afternoon");
+        assert_eq!(daypart(at("2023-09-08 18:00"), None, None), "evening");
+        assert_eq!(daypart(at("2023-09-08 23:30"), None, None), "night");
+    }
+
+    #[test]
+    fn test_sun_daypart() {
+        // Seoul: sunrise ~06:05, sunset ~18:55 KST in early September.
+        let kst = Some(9 * 60);
+        assert_eq!(daypart(at("2023-09-08 05:50"), kst, SEOUL), "night");
+        assert_eq!(daypart(at("2023-09-08 06:30"), kst, SEOUL), "morning");
+        assert_eq!(daypart(at("2023-09-08 14:00"), kst, SEOUL), "afternoon");
+        assert_eq!(daypart(at("2023-09-08 18:00"), kst, SEOUL), "evening");
+        assert_eq!(daypart(at("2023-09-08 19:30"), kst, SEOUL), "night");
+        // Winter evenings are dark much earlier.
+        assert_eq!(daypart(at("2023-12-21 18:00"), kst, SEOUL), "night");
+    }
+
+    #[test]
+    fn test_polar() {
+        let tromso = Some((69.65, 18.96));
+        assert_eq!(daypart(at("2023-12-21 12:00"), None, tromso), "night");
+        assert_eq!(daypart(at("2023-06-21 13:00"), None, tromso), "afternoon");
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(parse_offset("+09:00"), Some(540));
+        assert_eq!(parse_offset("-05:30"), Some(-330));
+        assert_eq!(parse_offset("09:00"), None);
+    }
+}
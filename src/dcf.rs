@@ -0,0 +1,98 @@
+//! `--dcf`: keeps new names within the Design rule for Camera File system,
+//! so a renamed card still plays back in the camera and on older devices.
+//!
+//! ```text
+//! DCIM/100CANON/DSC_0001.JPG
+//! ```
+//!
+//! A file name is 4 free characters, a file number from 0001 to 9999 and a
+//! 3-character extension. A directory under `DCIM` is a number from 100 to
+//! 999 and 5 free characters. Free characters are `A`-`Z`, `0`-`9` and `_`.
+
+use std::path::{self, Component, Path};
+
+/// Returns true for the characters DCF allows in names.
+fn is_free(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'
+}
+
+/// Returns true if `name` is a DCF file name, e.g. `DSC_0001.JPG`.
+fn is_file_name(name: &str) -> bool {
+    let Some((stem, ext)) = name.split_once('.') else {
+        return false;
+    };
+    stem.len() == 8
+        && ext.len() == 3
+        && stem[..4].chars().all(is_free)
+        && stem[4..].chars().all(|c| c.is_ascii_digit())
+        && &stem[4..] != "0000"
+        && ext.chars().all(is_free)
+}
+
+/// Returns true if `name` is a DCF directory name, e.g. `100CANON`.
+fn is_dir_name(name: &str) -> bool {
+    name.len() == 8
+        && name[..3]
+            .parse::<u16>()
+            .is_ok_and(|n| (100..=999).contains(&n))
+        && name[3..].chars().all(is_free)
+}
+
+/// Checks that `path` has a DCF file name and, if it is under a `DCIM`
+/// directory, that it is directly in a DCF directory of it.
+pub fn check(path: &Path) -> Result<(), String> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if !name.is_ascii() || !is_file_name(&name) {
+        return Err(format!(
+            "'{}' is not a DCF name, expected 4 letters, digits or '_', a number 0001-9999 \
+             and an extension, e.g. DSC_0001.JPG",
+            name
+        ));
+    }
+    let path = path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let names: Vec<_> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    let Some(dcim) = names.iter().rposition(|name| name == "DCIM") else {
+        return Ok(());
+    };
+    match &names[dcim + 1..] {
+        [dir, _] if dir.is_ascii() && is_dir_name(dir) => Ok(()),
+        [dir, _] => Err(format!(
+            "'{}' is not a DCF directory, expected a number 100-999 and 5 letters, digits \
+             or '_', e.g. 100CANON",
+            dir
+        )),
+        _ => Err(
+            "a file under DCIM must be directly in a directory such as DCIM/100CANON".to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert!(check(Path::new("DSC_0001.JPG")).is_ok());
+        assert!(check(Path::new("/media/card/DCIM/100CANON/IMG_9999.MOV")).is_ok());
+        assert!(check(Path::new("DCIM/999_____/ABCD1234.THM")).is_ok());
+
+        assert!(check(Path::new("dsc_0001.jpg")).is_err());
+        assert!(check(Path::new("DSC_0000.JPG")).is_err());
+        assert!(check(Path::new("DSC_00001.JPG")).is_err());
+        assert!(check(Path::new("DSC-0001.JPG")).is_err());
+        assert!(check(Path::new("DSC_0001.JPEG")).is_err());
+        assert!(check(Path::new("DSC_0001-1.JPG")).is_err());
+        assert!(check(Path::new("DSCÉ0001.JPG")).is_err());
+        assert!(check(Path::new("DCIM/099CANON/DSC_0001.JPG")).is_err());
+        assert!(check(Path::new("DCIM/100canon/DSC_0001.JPG")).is_err());
+        assert!(check(Path::new("DCIM/DSC_0001.JPG")).is_err());
+        assert!(check(Path::new("DCIM/100CANON/2023/DSC_0001.JPG")).is_err());
+    }
+}
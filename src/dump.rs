@@ -0,0 +1,110 @@
+//! Reading `exiftool` text dumps and making their values filename-safe.
+//!
+//! The module only depends on `std` so fuzz targets can include it directly.
+
+use std::collections::HashMap;
+
+/// Parses `exiftool` output lines such as `Create Date : 2023:09:08 10:11:12`
+/// into a map keyed by the tag name without spaces (`CreateDate`).
+///
+/// Group prefixes written by `exiftool -G` (`[EXIF] Make : Canon`) are
+/// dropped. Names with a slash such as `Date/Time Original` are also
+/// available under the tag name (`DateTimeOriginal`).
+pub fn exif_vars(dump: &str) -> HashMap<String, String> {
+    let mut vars: HashMap<String, String> = dump
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim(), value.trim().to_string()))
+        .map(|(key, value)| {
+            let key = match key.strip_prefix('[').and_then(|k| k.split_once(']')) {
+                Some((_, key)) => key,
+                None => key,
+            };
+            (key.replace(' ', ""), value)
+        })
+        .filter(|(key, _)| !key.is_empty())
+        .collect();
+    let aliases: Vec<(String, String)> = vars
+        .iter()
+        .filter(|(key, _)| key.contains('/'))
+        .map(|(key, value)| (key.replace('/', ""), value.clone()))
+        .collect();
+    for (key, value) in aliases {
+        vars.entry(key).or_insert(value);
+    }
+    vars
+}
+
+/// Makes free text usable inside a filename: characters that are invalid
+/// on common filesystems are dropped, control characters become spaces,
+/// whitespace is collapsed and the result is cut to `max_len` characters.
+pub fn sanitize_text(s: &str, max_len: usize) -> String {
+    let cleaned: String = s
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .filter(|c| !r#"<>:"/\|?*"#.contains(*c))
+        .collect();
+    let collapsed = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let truncated: String = collapsed.chars().take(max_len).collect();
+    truncated.trim_end_matches([' ', '.']).to_string()
+}
+
+/// Bidi formatting characters, which can make a filename display in a
+/// different order than it sorts and opens, e.g. `IMG_\u{202E}gpj.exe`.
+const BIDI: &[char] = &[
+    '\u{061C}', '\u{200E}', '\u{200F}', '\u{202A}', '\u{202B}', '\u{202C}', '\u{202D}', '\u{202E}',
+    '\u{2066}', '\u{2067}', '\u{2068}', '\u{2069}',
+];
+
+/// Replaces control characters with spaces and removes bidi formatting
+/// characters.
+pub fn strip_invisible(s: &str) -> String {
+    s.chars()
+        .filter(|c| !BIDI.contains(c))
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+/// Returns true if `c` is removed or replaced by `strip_invisible`.
+pub fn is_invisible(c: char) -> bool {
+    c.is_control() || BIDI.contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exif_vars() {
+        let vars = exif_vars("File Name : IMG_1234.JPG\nCreate Date : 2023:09:08 10:11:12\n");
+        assert_eq!(vars["FileName"], "IMG_1234.JPG");
+        assert_eq!(vars["CreateDate"], "2023:09:08 10:11:12");
+        assert!(exif_vars("======== IMG_1234.JPG\n: x\n").is_empty());
+
+        let vars =
+            exif_vars("[EXIF]  Make : Canon\n[ExifIFD] Date/Time Original : 2023:09:08 10:11:12\n");
+        assert_eq!(vars["Make"], "Canon");
+        assert_eq!(vars["Date/TimeOriginal"], "2023:09:08 10:11:12");
+        assert_eq!(vars["DateTimeOriginal"], "2023:09:08 10:11:12");
+    }
+
+    #[test]
+    fn test_sanitize_text() {
+        assert_eq!(
+            sanitize_text("  Sunset at\tHaeundae: day 2 ", 64),
+            "Sunset at Haeundae day 2"
+        );
+        assert_eq!(sanitize_text("a/b\\c?", 64), "abc");
+        assert_eq!(sanitize_text("Lorem ipsum dolor", 6), "Lorem");
+        assert_eq!(sanitize_text("Fin...", 64), "Fin");
+        assert_eq!(sanitize_text("a\0b\r\nc", 64), "a b c");
+    }
+
+    #[test]
+    fn test_strip_invisible() {
+        assert_eq!(strip_invisible("IMG_\u{202E}gpj.exe"), "IMG_gpj.exe");
+        assert_eq!(strip_invisible("Canon\nEOS\u{0}R5"), "Canon EOS R5");
+        assert_eq!(strip_invisible("서울 \u{2067}x\u{2069}"), "서울 x");
+        assert!(!strip_invisible("a\u{200F}\t").chars().any(is_invisible));
+    }
+}
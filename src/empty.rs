@@ -0,0 +1,47 @@
+//! `--on-empty`: what to do when a run has nothing to rename because no file
+//! was found or filters excluded them all, which for a scheduled import is
+//! more often a source drive that isn't mounted than a quiet day.
+
+use std::str::FromStr;
+
+/// Exit status of a run with nothing to do under `--on-empty status`.
+pub const EXIT_NOTHING_TO_DO: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OnEmpty {
+    /// Exit successfully without a word.
+    Ignore,
+    /// Say so and exit with `EXIT_NOTHING_TO_DO`.
+    #[default]
+    Status,
+    /// Fail like a run with failed files.
+    Error,
+}
+
+impl FromStr for OnEmpty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(OnEmpty::Ignore),
+            "status" => Ok(OnEmpty::Status),
+            "error" => Ok(OnEmpty::Error),
+            _ => Err(format!(
+                "invalid action '{}', expected ignore, status or error",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_empty() {
+        assert_eq!("error".parse(), Ok(OnEmpty::Error));
+        assert_eq!(OnEmpty::default(), OnEmpty::Status);
+        assert!("fail".parse::<OnEmpty>().is_err());
+    }
+}
@@ -0,0 +1,434 @@
+//! Reading EXIF tags directly from JPEG and TIFF-based raw files (DNG, CR2,
+//! NEF, ARW, ...), so no exiftool dump is needed for them.
+//!
+//! Tags are named and printed the way exiftool prints them, e.g.
+//! `FocalLength` = `24.0 mm`, so patterns and normalizers work the same with
+//! either source. Only the tags the tool uses are read.
+//!
+//! The module only depends on `std` so fuzz targets can include it directly.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes read from the start of a file; EXIF data comes first in the
+/// formats supported.
+const READ_LIMIT: u64 = 4 << 20;
+
+/// IFDs followed from IFD0.
+const EXIF_IFD: u16 = 0x8769;
+const GPS_IFD: u16 = 0x8825;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Ifd {
+    Main,
+    Exif,
+    Gps,
+}
+
+/// How a tag value is printed.
+#[derive(Clone, Copy)]
+enum Print {
+    Text,
+    Integer,
+    /// Rational with this many decimals.
+    Decimal(usize),
+    /// Rational as `1/250` when below a second.
+    Exposure,
+    /// Signed rational with a sign and at most two decimals.
+    Signed,
+    /// Rational followed by ` mm`.
+    Millimeters,
+    /// Integer followed by ` mm`.
+    IntegerMillimeters,
+    /// Three rationals as decimal degrees.
+    Degrees,
+    /// Three rationals as `HH:MM:SS`.
+    Time,
+    /// Hemisphere letter as `North`, `South`, `East` or `West`.
+    Hemisphere,
+    /// Orientation as described by exiftool, e.g. `Rotate 90 CW`.
+    Orientation,
+}
+
+const TAGS: &[(Ifd, u16, &str, Print)] = &[
+    (Ifd::Main, 0x010F, "Make", Print::Text),
+    (Ifd::Main, 0x0110, "Model", Print::Text),
+    (Ifd::Main, 0x0112, "Orientation", Print::Orientation),
+    (Ifd::Main, 0x0131, "Software", Print::Text),
+    (Ifd::Main, 0x0132, "ModifyDate", Print::Text),
+    (Ifd::Main, 0x013B, "Artist", Print::Text),
+    (Ifd::Exif, 0x829A, "ExposureTime", Print::Exposure),
+    (Ifd::Exif, 0x829D, "FNumber", Print::Decimal(1)),
+    (Ifd::Exif, 0x8827, "ISO", Print::Integer),
+    (Ifd::Exif, 0x9003, "DateTimeOriginal", Print::Text),
+    (Ifd::Exif, 0x9004, "CreateDate", Print::Text),
+    (Ifd::Exif, 0x9010, "OffsetTime", Print::Text),
+    (Ifd::Exif, 0x9011, "OffsetTimeOriginal", Print::Text),
+    (Ifd::Exif, 0x9012, "OffsetTimeDigitized", Print::Text),
+    (Ifd::Exif, 0x9204, "ExposureCompensation", Print::Signed),
+    (Ifd::Exif, 0x920A, "FocalLength", Print::Millimeters),
+    (Ifd::Exif, 0x9290, "SubSecTime", Print::Text),
+    (Ifd::Exif, 0x9291, "SubSecTimeOriginal", Print::Text),
+    (Ifd::Exif, 0x9292, "SubSecTimeDigitized", Print::Text),
+    (
+        Ifd::Exif,
+        0xA405,
+        "FocalLengthIn35mmFormat",
+        Print::IntegerMillimeters,
+    ),
+    (Ifd::Exif, 0xA002, "ExifImageWidth", Print::Integer),
+    (Ifd::Exif, 0xA003, "ExifImageHeight", Print::Integer),
+    (Ifd::Exif, 0xA420, "ImageUniqueID", Print::Text),
+    (Ifd::Exif, 0xA431, "SerialNumber", Print::Text),
+    (Ifd::Exif, 0xA433, "LensMake", Print::Text),
+    (Ifd::Exif, 0xA434, "LensModel", Print::Text),
+    (Ifd::Gps, 0x0001, "GPSLatitudeRef", Print::Hemisphere),
+    (Ifd::Gps, 0x0002, "GPSLatitude", Print::Degrees),
+    (Ifd::Gps, 0x0003, "GPSLongitudeRef", Print::Hemisphere),
+    (Ifd::Gps, 0x0004, "GPSLongitude", Print::Degrees),
+    (Ifd::Gps, 0x0007, "GPSTimeStamp", Print::Time),
+    (Ifd::Gps, 0x001D, "GPSDateStamp", Print::Text),
+];
+
+/// Reads the EXIF tags of the file at `path`. Files in other formats have
+/// none.
+pub fn read(path: &Path) -> io::Result<HashMap<String, String>> {
+    let mut data = Vec::new();
+    File::open(path)?.take(READ_LIMIT).read_to_end(&mut data)?;
+    Ok(parse(&data))
+}
+
+/// Parses the EXIF tags of a JPEG or TIFF file.
+pub fn parse(data: &[u8]) -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    let tiff = if data.starts_with(&[0xFF, 0xD8]) {
+        jpeg_exif(data)
+    } else {
+        Some(data)
+    };
+    if let Some(tiff) = tiff {
+        if let Some(tiff) = Tiff::new(tiff) {
+            tiff.read_tags(&mut vars);
+        }
+    }
+    vars
+}
+
+/// Returns the TIFF structure in the APP1 segment of a JPEG.
+fn jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = 2;
+    loop {
+        let marker = *data.get(pos..pos + 2)?.get(1)?;
+        if data[pos] != 0xFF || marker == 0xDA || marker == 0xD9 {
+            // start of scan or end of image: no more metadata
+            return None;
+        }
+        let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        let segment = data.get(pos + 4..(pos + 2).checked_add(len)?)?;
+        if marker == 0xE1 {
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                return Some(tiff);
+            }
+        }
+        pos += 2 + len;
+    }
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Tiff<'a>> {
+        let little_endian = match data.get(..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+        Some(Tiff {
+            data,
+            little_endian,
+        })
+    }
+
+    fn u16(&self, pos: usize) -> Option<u16> {
+        let b = self.data.get(pos..pos.checked_add(2)?)?;
+        let b = [b[0], b[1]];
+        Some(if self.little_endian {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    }
+
+    fn u32(&self, pos: usize) -> Option<u32> {
+        let b = self.data.get(pos..pos.checked_add(4)?)?;
+        let b = [b[0], b[1], b[2], b[3]];
+        Some(if self.little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn read_tags(&self, vars: &mut HashMap<String, String>) {
+        let Some(ifd0) = self.u32(4) else {
+            return;
+        };
+        let entries = self.entries(ifd0 as usize);
+        for entry in &entries {
+            match entry.tag {
+                EXIF_IFD => self.read_ifd(Ifd::Exif, self.offset(entry), vars),
+                GPS_IFD => self.read_ifd(Ifd::Gps, self.offset(entry), vars),
+                _ => {}
+            }
+        }
+        self.insert(Ifd::Main, &entries, vars);
+    }
+
+    fn read_ifd(&self, ifd: Ifd, pos: Option<usize>, vars: &mut HashMap<String, String>) {
+        if let Some(pos) = pos {
+            self.insert(ifd, &self.entries(pos), vars);
+        }
+    }
+
+    fn insert(&self, ifd: Ifd, entries: &[Entry], vars: &mut HashMap<String, String>) {
+        for entry in entries {
+            let known = TAGS
+                .iter()
+                .find(|(i, tag, ..)| *i == ifd && *tag == entry.tag);
+            if let Some((_, _, name, print)) = known {
+                if let Some(value) = self.print(entry, *print) {
+                    vars.insert(name.to_string(), value);
+                }
+            }
+        }
+    }
+
+    fn entries(&self, pos: usize) -> Vec<Entry> {
+        let count = self.u16(pos).unwrap_or(0) as usize;
+        (0..count)
+            .map_while(|i| {
+                let pos = pos.checked_add(2 + i * 12)?;
+                Some(Entry {
+                    tag: self.u16(pos)?,
+                    kind: self.u16(pos + 2)?,
+                    count: self.u32(pos + 4)? as usize,
+                    value_pos: pos + 8,
+                })
+            })
+            .collect()
+    }
+
+    /// The value of a LONG entry used as an offset.
+    fn offset(&self, entry: &Entry) -> Option<usize> {
+        self.u32(entry.value_pos).map(|offset| offset as usize)
+    }
+
+    /// Returns where the value of `entry` starts: inline if it fits in four
+    /// bytes, at an offset otherwise.
+    fn value_start(&self, entry: &Entry) -> Option<usize> {
+        let size = match entry.kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 => 4,
+            5 | 10 => 8,
+            _ => return None,
+        };
+        if entry.count.checked_mul(size)? <= 4 {
+            Some(entry.value_pos)
+        } else {
+            self.offset(entry)
+        }
+    }
+
+    fn integer(&self, entry: &Entry) -> Option<u32> {
+        let start = self.value_start(entry)?;
+        match entry.kind {
+            3 => self.u16(start).map(u32::from),
+            4 => self.u32(start),
+            _ => None,
+        }
+    }
+
+    fn rational(&self, entry: &Entry, index: usize) -> Option<(f64, u32, u32)> {
+        if !matches!(entry.kind, 5 | 10) || index >= entry.count {
+            return None;
+        }
+        let pos = self.value_start(entry)?.checked_add(index * 8)?;
+        let (num, den) = (self.u32(pos)?, self.u32(pos + 4)?);
+        let value = if entry.kind == 10 {
+            num as i32 as f64 / den as i32 as f64
+        } else {
+            num as f64 / den as f64
+        };
+        value.is_finite().then_some((value, num, den))
+    }
+
+    fn text(&self, entry: &Entry) -> Option<String> {
+        if entry.kind != 2 {
+            return None;
+        }
+        let start = self.value_start(entry)?;
+        let bytes = self.data.get(start..start.checked_add(entry.count)?)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        let text = String::from_utf8_lossy(&bytes[..end]).trim().to_string();
+        (!text.is_empty()).then_some(text)
+    }
+
+    fn print(&self, entry: &Entry, print: Print) -> Option<String> {
+        let value = match print {
+            Print::Text => self.text(entry)?,
+            Print::Integer => self.integer(entry)?.to_string(),
+            Print::Decimal(places) => format!("{:.*}", places, self.rational(entry, 0)?.0),
+            Print::Exposure => {
+                let (secs, num, den) = self.rational(entry, 0)?;
+                if num == 1 && den > 1 {
+                    format!("1/{}", den)
+                } else if secs > 0.0 && secs < 0.25001 {
+                    format!("1/{}", (0.5 + 1.0 / secs) as u64)
+                } else {
+                    format!("{}", secs)
+                }
+            }
+            Print::Signed => {
+                let (ev, ..) = self.rational(entry, 0)?;
+                let s = format!("{:+.2}", ev);
+                let s = s.trim_end_matches('0').trim_end_matches('.');
+                if s == "+" || s == "-" {
+                    "0".to_string()
+                } else {
+                    s.to_string()
+                }
+            }
+            Print::Millimeters => format!("{:.1} mm", self.rational(entry, 0)?.0),
+            Print::IntegerMillimeters => format!("{} mm", self.integer(entry)?),
+            Print::Degrees => {
+                let part = |i| self.rational(entry, i).map(|(v, ..)| v);
+                let degrees =
+                    part(0)? + part(1).unwrap_or(0.0) / 60.0 + part(2).unwrap_or(0.0) / 3600.0;
+                format!("{:.6}", degrees)
+            }
+            Print::Time => {
+                let part = |i| self.rational(entry, i).map(|(v, ..)| v);
+                let secs = part(2).unwrap_or(0.0);
+                format!(
+                    "{:02}:{:02}:{:02}",
+                    part(0)? as u32,
+                    part(1)? as u32,
+                    secs as u32
+                )
+            }
+            Print::Hemisphere => match self.text(entry)?.as_str() {
+                "N" => "North".to_string(),
+                "S" => "South".to_string(),
+                "E" => "East".to_string(),
+                "W" => "West".to_string(),
+                _ => return None,
+            },
+            Print::Orientation => match self.integer(entry)? {
+                1 => "Horizontal (normal)",
+                2 => "Mirror horizontal",
+                3 => "Rotate 180",
+                4 => "Mirror vertical",
+                5 => "Mirror horizontal and rotate 270 CW",
+                6 => "Rotate 90 CW",
+                7 => "Mirror horizontal and rotate 90 CW",
+                8 => "Rotate 270 CW",
+                _ => return None,
+            }
+            .to_string(),
+        };
+        Some(value)
+    }
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: usize,
+    /// Position of the value or offset field.
+    value_pos: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a little-endian TIFF with IFD0 holding `Model` and a pointer
+    /// to an Exif IFD, and a GPS IFD.
+    fn tiff() -> Vec<u8> {
+        let mut data = b"II*\0".to_vec();
+        data.extend(8u32.to_le_bytes());
+        let entry = |data: &mut Vec<u8>, tag: u16, kind: u16, count: u32, value: u32| {
+            data.extend(tag.to_le_bytes());
+            data.extend(kind.to_le_bytes());
+            data.extend(count.to_le_bytes());
+            data.extend(value.to_le_bytes());
+        };
+
+        // IFD0 at 8: 4 entries, ends at 8 + 2 + 48 + 4 = 62
+        data.extend(4u16.to_le_bytes());
+        entry(&mut data, 0x0110, 2, 4, u32::from_le_bytes(*b"X5\0\0"));
+        entry(&mut data, 0x0112, 3, 1, 6);
+        entry(&mut data, EXIF_IFD, 4, 1, 62);
+        entry(&mut data, GPS_IFD, 4, 1, 132);
+        data.extend(0u32.to_le_bytes());
+
+        // Exif IFD at 62: 4 entries, ends at 62 + 2 + 48 + 4 = 116
+        data.extend(4u16.to_le_bytes());
+        entry(&mut data, 0x9003, 2, 20, 172);
+        entry(&mut data, 0x829A, 5, 1, 116);
+        entry(&mut data, 0xA405, 3, 1, 26);
+        entry(&mut data, 0x9204, 10, 1, 124);
+        data.extend(0u32.to_le_bytes());
+        // rationals at 116 and 124
+        data.extend([1u32, 250].iter().flat_map(|v| v.to_le_bytes()));
+        data.extend([(-2i32) as u32, 3].iter().flat_map(|v| v.to_le_bytes()));
+
+        // GPS IFD at 132: 2 entries, ends at 132 + 2 + 24 + 4 = 162
+        data.extend(2u16.to_le_bytes());
+        entry(&mut data, 0x0001, 2, 2, u32::from_le_bytes(*b"S\0\0\0"));
+        entry(&mut data, 0x0002, 5, 3, 212);
+        data.extend(0u32.to_le_bytes());
+        data.resize(172, 0);
+        data.extend(b"2023:09:08 10:11:12\0");
+        data.resize(212, 0);
+        for (num, den) in [(37, 1), (33, 1), (5940, 100)] {
+            data.extend((num as u32).to_le_bytes());
+            data.extend((den as u32).to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_tiff() {
+        let vars = parse(&tiff());
+        assert_eq!(vars["Model"], "X5");
+        assert_eq!(vars["Orientation"], "Rotate 90 CW");
+        assert_eq!(vars["DateTimeOriginal"], "2023:09:08 10:11:12");
+        assert_eq!(vars["ExposureTime"], "1/250");
+        assert_eq!(vars["FocalLengthIn35mmFormat"], "26 mm");
+        assert_eq!(vars["ExposureCompensation"], "-0.67");
+        assert_eq!(vars["GPSLatitudeRef"], "South");
+        assert_eq!(vars["GPSLatitude"], "37.566500");
+    }
+
+    #[test]
+    fn test_parse_jpeg() {
+        let tiff = tiff();
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0, 0xFF, 0xE1];
+        jpeg.extend(((tiff.len() + 8) as u16).to_be_bytes());
+        jpeg.extend(b"Exif\0\0");
+        jpeg.extend(&tiff);
+        jpeg.extend([0xFF, 0xDA]);
+        assert_eq!(parse(&jpeg)["Model"], "X5");
+
+        assert!(parse(b"").is_empty());
+        assert!(parse(&[0xFF, 0xD8, 0xFF, 0xE1, 0xFF, 0xFF]).is_empty());
+        assert!(parse(&tiff[..60]).contains_key("Model"));
+    }
+}
@@ -0,0 +1,82 @@
+//! `--filter` expressions deciding which files get renamed.
+
+use std::str::FromStr;
+
+use crate::Vars;
+
+/// A single filter. Prefixing an expression with `!` negates it.
+///
+/// | expression   | matches                                      |
+/// |--------------|----------------------------------------------|
+/// | `is-pano`    | panorama/360° images (XMP GPano tags)        |
+/// | `NAME=VALUE` | variable or tag `NAME` equals `VALUE`        |
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    IsPano,
+    Equals(String, String),
+    Not(Box<Filter>),
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(rest) = s.strip_prefix('!') {
+            return Ok(Filter::Not(Box::new(rest.parse()?)));
+        }
+        if s == "is-pano" {
+            return Ok(Filter::IsPano);
+        }
+        match s.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => Ok(Filter::Equals(
+                name.trim().to_string(),
+                value.trim().to_string(),
+            )),
+            _ => Err(format!("unknown filter '{}'", s)),
+        }
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, vars: &Vars) -> bool {
+        match self {
+            Filter::IsPano => is_pano(vars),
+            Filter::Equals(name, value) => vars.get(name).is_some_and(|v| v == value),
+            Filter::Not(filter) => !filter.matches(vars),
+        }
+    }
+}
+
+/// Returns true for images carrying Google Photo Sphere (GPano) metadata.
+pub fn is_pano(vars: &Vars) -> bool {
+    let projection = vars.get("ProjectionType").is_some_and(|p| !p.is_empty());
+    let viewer = vars
+        .get("UsePanoramaViewer")
+        .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+    projection || viewer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let mut vars = Vars::new();
+        vars.insert("Model".to_string(), "iPhone 14".to_string());
+
+        let pano: Filter = "is-pano".parse().unwrap();
+        let not_pano: Filter = "!is-pano".parse().unwrap();
+        assert!(!pano.matches(&vars));
+        assert!(not_pano.matches(&vars));
+
+        vars.insert("ProjectionType".to_string(), "equirectangular".to_string());
+        assert!(pano.matches(&vars));
+
+        let model: Filter = "Model = iPhone 14".parse().unwrap();
+        assert!(model.matches(&vars));
+        assert!(!"Model=Pixel".parse::<Filter>().unwrap().matches(&vars));
+        assert!("pano".parse::<Filter>().is_err());
+    }
+}
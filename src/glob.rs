@@ -0,0 +1,188 @@
+//! Expanding glob patterns given as files, e.g. `"*.jpg"` or
+//! `"2023-*/IMG_????.JPG"`, for shells that don't (Windows) and for
+//! quoted patterns too long for the command line.
+//!
+//! `*` matches any characters but a path separator, `?` one character and
+//! `[abc]`, `[a-z]` or `[!a-z]` one of a set. Wildcards may appear in any
+//! path component, split as the platform does (`\` and `/`, and drive
+//! prefixes on Windows); hidden files only match patterns starting with `.`.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Returns true if `arg` contains a wildcard and is expanded.
+pub fn is_glob(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+/// Returns the files and directories matching `pattern`, sorted by path.
+/// Arguments without wildcards are returned as they are, whether they
+/// exist or not.
+pub fn expand(pattern: &str) -> Vec<String> {
+    if !is_glob(pattern) {
+        return vec![pattern.to_string()];
+    }
+    let mut paths = vec![PathBuf::new()];
+    let components: Vec<Component> = Path::new(pattern).components().collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        let mut next = Vec::new();
+        for path in &paths {
+            // prefixes, the root, `.` and `..` are carried through as they are
+            let Component::Normal(name) = component else {
+                next.push(path.join(component));
+                continue;
+            };
+            let component = name.to_string_lossy();
+            if !is_glob(&component) {
+                next.push(path.join(name));
+                continue;
+            }
+            let dir = if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path.as_path()
+            };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') && !component.starts_with('.') {
+                    continue;
+                }
+                let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+                if matches(&component, &name) && (last || is_dir) {
+                    next.push(path.join(&name));
+                }
+            }
+        }
+        paths = next;
+    }
+    let mut files: Vec<String> = paths
+        .into_iter()
+        .filter(|path| path.exists())
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Returns true if the file name of `path` matches `pattern`, ignoring
+/// case, for `--include` and `--exclude`.
+pub fn matches_name(pattern: &str, path: &str) -> bool {
+    let name = Path::new(path).file_name().unwrap_or_default();
+    matches(
+        &pattern.to_lowercase(),
+        &name.to_string_lossy().to_lowercase(),
+    )
+}
+
+/// Matches a single path component against a pattern.
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_at(&pattern, &name)
+}
+
+fn matches_at(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| matches_at(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && matches_at(&pattern[1..], &name[1..]),
+        Some('[') => match (set(&pattern[1..]), name.first()) {
+            (Some((len, matcher)), Some(&c)) => {
+                matcher(c) && matches_at(&pattern[len + 1..], &name[1..])
+            }
+            // an unclosed `[` is literal
+            (None, Some('[')) => matches_at(&pattern[1..], &name[1..]),
+            _ => false,
+        },
+        Some(&c) => name.first() == Some(&c) && matches_at(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Parses a character set after its `[`, returning the length up to and
+/// including its `]` and whether a character is in it.
+fn set(pattern: &[char]) -> Option<(usize, impl Fn(char) -> bool)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let start = negated as usize;
+    // a `]` right after the opening bracket is part of the set
+    let end = start + 1 + pattern.get(start + 1..)?.iter().position(|&c| c == ']')?;
+    let items = pattern[start..end].to_vec();
+    let matcher = move |c: char| {
+        let mut found = false;
+        let mut i = 0;
+        while i < items.len() {
+            if i + 2 < items.len() && items[i + 1] == '-' {
+                found |= items[i] <= c && c <= items[i + 2];
+                i += 3;
+            } else {
+                found |= items[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    };
+    Some((end + 1, matcher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*.jpg", "IMG_1234.jpg"));
+        assert!(!matches("*.jpg", "IMG_1234.JPG"));
+        assert!(matches("IMG_????.*", "IMG_1234.JPG"));
+        assert!(!matches("IMG_????.*", "IMG_123.JPG"));
+        assert!(matches("[!.]*[0-9].[jJ]*", "IMG_1.jpeg"));
+        assert!(!matches("[!I]*", "IMG_1.jpeg"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("a[b", "a[b"));
+        assert!(matches("*", ""));
+        assert!(matches_name("*.jpg", "2023/IMG_1234.JPG"));
+        assert!(!matches_name("2023*", "2023/IMG_1234.JPG"));
+    }
+
+    #[test]
+    fn test_expand() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-glob-{}", std::process::id()));
+        for file in ["a/1.jpg", "a/2.JPG", "b/3.jpg", "b/.4.jpg", "c.jpg/5.txt"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "").unwrap();
+        }
+        let root = dir.to_str().unwrap();
+        let expand = |pattern: &str| -> Vec<String> {
+            expand(&format!("{}/{}", root, pattern))
+                .into_iter()
+                .map(|f| f[root.len() + 1..].to_string())
+                .collect()
+        };
+        assert_eq!(expand("*/*.jpg"), vec!["a/1.jpg", "b/3.jpg"]);
+        assert_eq!(expand("a/*"), vec!["a/1.jpg", "a/2.JPG"]);
+        assert_eq!(expand("b/.*"), vec!["b/.4.jpg"]);
+        assert_eq!(expand("*.jpg"), vec!["c.jpg"]);
+        assert_eq!(expand("?/missing*"), Vec::<String>::new());
+        assert_eq!(expand("./a//*.JPG"), vec!["a/2.JPG"]);
+        assert_eq!(expand("b/../a/1.*"), vec!["b/../a/1.jpg"]);
+        assert_eq!(super::expand("plain.jpg"), vec!["plain.jpg"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_expand_windows() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-glob-win-{}", std::process::id()));
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::write(dir.join("a").join("1.jpg"), "").unwrap();
+        // temp_dir() has a drive prefix and backslash separators
+        let root = dir.to_str().unwrap();
+        let expected = vec![dir.join("a").join("1.jpg").to_string_lossy().to_string()];
+        assert_eq!(expand(&format!("{}\\?\\*.jpg", root)), expected);
+        assert_eq!(expand(&format!("{}/a/*.jpg", root)), expected);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! `--gpx track.gpx`: positions for cameras without GPS, by correlating
+//! capture times with a GPX track the way `exiftool -geotag` does.
+//!
+//! Track points are UTC, capture times local: they are converted with the
+//! `OffsetTimeOriginal` or `OffsetTime` tag, or the system time zone.
+//! Positions between two points are interpolated linearly; captures more
+//! than [`MAX_GAP`] away from the track get none.
+
+use std::fs;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta, TimeZone};
+
+use crate::daypart;
+use crate::Vars;
+
+/// Largest time between a capture and the track points it is placed
+/// between, or the nearest one outside the track.
+const MAX_GAP: TimeDelta = TimeDelta::minutes(30);
+
+/// Track points ordered by time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track(Vec<Point>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Point {
+    time: NaiveDateTime,
+    lat: f64,
+    lon: f64,
+}
+
+impl Track {
+    /// Reads the GPX file at `path`, for clap.
+    pub fn load(path: &str) -> Result<Track, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("failed to read: {}", e))?;
+        let track = parse(&source);
+        if track.0.is_empty() {
+            return Err("no timed track points".to_string());
+        }
+        Ok(track)
+    }
+
+    /// Returns the position at `utc`.
+    pub fn locate(&self, utc: NaiveDateTime) -> Option<(f64, f64)> {
+        let points = &self.0;
+        let after = points.partition_point(|p| p.time < utc);
+        match (after.checked_sub(1).map(|i| points[i]), points.get(after)) {
+            (Some(a), Some(b)) if b.time - a.time <= MAX_GAP * 2 => {
+                if b.time == a.time {
+                    return Some((b.lat, b.lon));
+                }
+                let f = (utc - a.time).as_seconds_f64() / (b.time - a.time).as_seconds_f64();
+                Some((a.lat + (b.lat - a.lat) * f, a.lon + (b.lon - a.lon) * f))
+            }
+            (a, b) => [a, b.copied()]
+                .into_iter()
+                .flatten()
+                .filter(|p| (p.time - utc).abs() <= MAX_GAP)
+                .min_by_key(|p| (p.time - utc).abs())
+                .map(|p| (p.lat, p.lon)),
+        }
+    }
+}
+
+/// Converts a local capture time to UTC with the offset tags of `exif`,
+/// or the system time zone.
+pub fn to_utc(date: NaiveDateTime, exif: &Vars) -> Option<NaiveDateTime> {
+    let offset = ["OffsetTimeOriginal", "OffsetTime"]
+        .iter()
+        .find_map(|tag| exif.get(*tag).and_then(|s| daypart::parse_offset(s)));
+    match offset {
+        Some(minutes) => Some(date - TimeDelta::minutes(minutes as i64)),
+        None => Local
+            .from_local_datetime(&date)
+            .earliest()
+            .map(|dt| dt.naive_utc()),
+    }
+}
+
+/// Adds a position to `exif` as the GPS tags exiftool prints.
+pub fn insert(exif: &mut Vars, (lat, lon): (f64, f64)) {
+    let tags = [
+        ("GPSLatitude", lat, "North", "South"),
+        ("GPSLongitude", lon, "East", "West"),
+    ];
+    for (tag, value, positive, negative) in tags {
+        let reference = if value < 0.0 { negative } else { positive };
+        exif.insert(tag.to_string(), format!("{:.6}", value.abs()));
+        exif.insert(format!("{}Ref", tag), reference.to_string());
+    }
+}
+
+/// Parses the timed `<trkpt>` elements of a GPX document. Points without
+/// a time or position are skipped.
+fn parse(source: &str) -> Track {
+    let mut points: Vec<Point> = source
+        .split("<trkpt")
+        .skip(1)
+        .filter_map(|element| {
+            let element = element.split("</trkpt>").next()?;
+            let (tag, body) = element.split_once('>')?;
+            let time = body.split_once("<time>")?.1.split_once("</time>")?.0;
+            Some(Point {
+                time: DateTime::parse_from_rfc3339(time.trim()).ok()?.naive_utc(),
+                lat: attribute(tag, "lat")?.parse().ok()?,
+                lon: attribute(tag, "lon")?.parse().ok()?,
+            })
+        })
+        .collect();
+    points.sort_by_key(|p| p.time);
+    Track(points)
+}
+
+/// Returns the value of the attribute `name` in the inside of a start tag.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    loop {
+        let (key, value) = rest.split_once('=')?;
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let (value, tail) = value[1..].split_once(quote)?;
+        if key.trim() == name {
+            return Some(value);
+        }
+        rest = tail;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    const GPX: &str = r#"<?xml version="1.0"?>
+<gpx version="1.1" creator="test">
+  <trk><trkseg>
+    <trkpt lat="37.0" lon="127.0"><ele>10</ele><time>2023-09-08T01:00:00Z</time></trkpt>
+    <trkpt lon='127.2' lat='37.2'><time>2023-09-08T01:10:00Z</time></trkpt>
+    <trkpt lat="40.0" lon="130.0"></trkpt>
+    <trkpt lat="38.0" lon="128.0"><time>2023-09-08T03:00:00+00:00</time></trkpt>
+  </trkseg></trk>
+</gpx>"#;
+
+    fn at(h: u32, m: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 9, 8)
+            .unwrap()
+            .and_hms_opt(h, m, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let track = parse(GPX);
+        assert_eq!(track.0.len(), 3);
+        assert_eq!((track.0[1].lat, track.0[1].lon), (37.2, 127.2));
+        assert_eq!(track.0[2].time, at(3, 0));
+    }
+
+    #[test]
+    fn test_locate() {
+        let track = parse(GPX);
+        let (lat, lon) = track.locate(at(1, 5)).unwrap();
+        assert!((lat - 37.1).abs() < 1e-9 && (lon - 127.1).abs() < 1e-9);
+        assert_eq!(track.locate(at(0, 40)), Some((37.0, 127.0)));
+        assert_eq!(track.locate(at(1, 30)), Some((37.2, 127.2)));
+        assert_eq!(track.locate(at(2, 0)), None);
+        assert_eq!(track.locate(at(3, 20)), Some((38.0, 128.0)));
+        assert_eq!(track.locate(at(4, 0)), None);
+    }
+
+    #[test]
+    fn test_to_utc() {
+        let mut exif = Vars::new();
+        exif.insert("OffsetTimeOriginal".to_string(), "+09:00".to_string());
+        assert_eq!(to_utc(at(10, 5), &exif), Some(at(1, 5)));
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut exif = Vars::new();
+        insert(&mut exif, (-33.8688, 151.2093));
+        assert_eq!(exif["GPSLatitude"], "33.868800");
+        assert_eq!(exif["GPSLatitudeRef"], "South");
+        assert_eq!(exif["GPSLongitudeRef"], "East");
+    }
+}
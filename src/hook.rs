@@ -0,0 +1,41 @@
+//! Shell commands run after a file is renamed.
+//!
+//! Hooks get the old and new path in `EXIF_RENAME_SOURCE` and
+//! `EXIF_RENAME_TARGET`, and run with `sh -c` (`cmd /C` on Windows).
+
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+pub fn run(hook: &str, source: &Path, target: &Path) -> io::Result<ExitStatus> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    Command::new(shell)
+        .arg(flag)
+        .arg(hook)
+        .env("EXIF_RENAME_SOURCE", source)
+        .env("EXIF_RENAME_TARGET", target)
+        .status()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let status = run(
+            r#"test "$EXIF_RENAME_SOURCE" = a.jpg && test "$EXIF_RENAME_TARGET" = b.jpg"#,
+            Path::new("a.jpg"),
+            Path::new("b.jpg"),
+        )
+        .unwrap();
+        assert!(status.success());
+        assert!(!run("exit 3", Path::new("a"), Path::new("b"))
+            .unwrap()
+            .success());
+    }
+}
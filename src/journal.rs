@@ -0,0 +1,753 @@
+//! `--journal FILE`: a record of every rename and copy, for undoing them.
+//!
+//! One entry per line, tab-separated: state (`pending` or `done`), action
+//! (`rename` or `copy`), source and target path, and the time the run
+//! started. Done entries add the size and modification time of the target
+//! and, for copies, of the source when the entry was committed. Tabs,
+//! newlines and backslashes in paths are escaped.
+//!
+//! An entry is appended as `pending` before the file is touched, and the
+//! same line with `done` after; each record is synced before going on.
+//! Opening the journal settles pending records left by a crash against the
+//! filesystem and, if anything changed, compacts the journal to one line
+//! per entry through a synced temporary file.
+//!
+//! `exif-rename undo` reverses the entries of the last run, those sharing
+//! the time of the last entry, or of the run picked with `--run`. The time
+//! is the run ID.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use chrono::NaiveDateTime;
+
+/// Milliseconds tell apart runs started within the same second, e.g. by a
+/// script looping over folders.
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f";
+
+/// Returns the ID of the run started at `time`, as written in journals.
+pub fn run_id(time: NaiveDateTime) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Rename,
+    Copy,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub action: Action,
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// False while the file operation may not have happened yet.
+    pub done: bool,
+    /// When the run started, shared by the entries of a run.
+    pub time: NaiveDateTime,
+    /// The target as it was when the entry was done.
+    pub target_stamp: Option<Stamp>,
+    /// The source of a copy as it was when the entry was done.
+    pub source_stamp: Option<Stamp>,
+}
+
+/// What a file looked like: its size and modification time, in nanoseconds
+/// since the epoch. A file with the same stamp is taken for the same file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    pub size: u64,
+    pub modified: u128,
+}
+
+impl Stamp {
+    /// Returns the stamp of the file at `path`, if it can be read.
+    pub fn of(path: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            size: metadata.len(),
+            modified: modified.as_nanos(),
+        })
+    }
+
+    /// Parses `SIZE,MODIFIED` as written in journals.
+    fn parse(s: &str) -> Option<Stamp> {
+        let (size, modified) = s.split_once(',')?;
+        Some(Stamp {
+            size: size.parse().ok()?,
+            modified: modified.parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Stamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.size, self.modified)
+    }
+}
+
+impl Entry {
+    /// Records the stamps of the files the entry left behind.
+    fn stamp(&mut self) {
+        self.target_stamp = Stamp::of(&self.target);
+        if self.action == Action::Copy {
+            self.source_stamp = Stamp::of(&self.source);
+        }
+    }
+
+    /// Returns true if `other` records the same operation of the same run.
+    fn is(&self, other: &Entry) -> bool {
+        self.action == other.action
+            && self.source == other.source
+            && self.target == other.target
+            && self.time == other.time
+    }
+}
+
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    entries: Vec<Entry>,
+    /// When the current run started.
+    time: NaiveDateTime,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, which may not exist yet, for a run
+    /// started at `time`, and settles entries left pending by an
+    /// interrupted run.
+    pub fn open(path: &Path, time: NaiveDateTime) -> io::Result<Journal> {
+        let source = read(path)?;
+        let journal = Journal {
+            path: path.to_path_buf(),
+            entries: reconcile(parse(&source)?),
+            time,
+        };
+        if format(&journal.entries) != source {
+            journal.save()?;
+        }
+        Ok(journal)
+    }
+
+    /// Records that `source` is about to be renamed or copied to `target`.
+    pub fn begin(&mut self, action: Action, source: &Path, target: &Path) -> io::Result<()> {
+        self.entries.push(Entry {
+            action,
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+            done: false,
+            time: self.time,
+            target_stamp: None,
+            source_stamp: None,
+        });
+        self.append()
+    }
+
+    /// Marks the entry recorded last as done, stamping its files.
+    pub fn commit(&mut self) -> io::Result<()> {
+        match self.entries.last_mut() {
+            Some(entry) => {
+                entry.done = true;
+                entry.stamp();
+            }
+            None => return Ok(()),
+        }
+        self.append()
+    }
+
+    /// Appends the entry recorded last and syncs it.
+    fn append(&self) -> io::Result<()> {
+        let created = !self.path.exists();
+        let mut out = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        out.write_all(format(&self.entries[self.entries.len() - 1..]).as_bytes())?;
+        out.sync_data()?;
+        if created {
+            sync_dir(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Reverses the renames and copies of the run with ID `run`, or of the
+    /// last run, newest first, and drops them from the journal. Only
+    /// entries passing `select` are undone, the others stay. Renamed files
+    /// are moved back, copies removed. Entries failing to undo are kept, so
+    /// undoing can be retried; with `dry_run` nothing is touched.
+    pub fn undo(
+        &mut self,
+        run: Option<&str>,
+        select: impl Fn(&Entry) -> bool,
+        dry_run: bool,
+    ) -> io::Result<Vec<(Entry, Result<(), String>)>> {
+        let time = match run {
+            Some(id) => self
+                .entries
+                .iter()
+                .find(|entry| run_id(entry.time) == id)
+                .map(|entry| entry.time)
+                .ok_or_else(|| {
+                    let message = format!("no run '{}' in the journal", id);
+                    io::Error::new(io::ErrorKind::NotFound, message)
+                })?,
+            None => match self.entries.last() {
+                Some(last) => last.time,
+                None => return Ok(Vec::new()),
+            },
+        };
+        let batch: Vec<usize> = (0..self.entries.len())
+            .filter(|&i| self.entries[i].time == time && select(&self.entries[i]))
+            .collect();
+        let mut results = Vec::new();
+        let mut reversed = HashSet::new();
+        // what undoing did so far, so dry runs see the files it would move
+        let mut undone: HashMap<PathBuf, bool> = HashMap::new();
+        for &i in batch.iter().rev() {
+            let entry = &self.entries[i];
+            let exists = |path: &Path| undone.get(path).copied().unwrap_or(path.exists());
+            let mut result = check(entry, exists);
+            if result.is_ok() && !dry_run {
+                result = reverse(entry);
+            }
+            if result.is_ok() {
+                undone.insert(entry.target.clone(), false);
+                if entry.action == Action::Rename {
+                    undone.insert(entry.source.clone(), true);
+                }
+                reversed.insert(i);
+            }
+            results.push((entry.clone(), result));
+        }
+        if !dry_run {
+            let mut i = 0;
+            self.entries.retain(|_| {
+                i += 1;
+                !reversed.contains(&(i - 1))
+            });
+            self.save()?;
+        }
+        Ok(results)
+    }
+
+    /// Replaces the journal file atomically.
+    fn save(&self) -> io::Result<()> {
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp = self.path.with_file_name(tmp_name);
+        {
+            let mut out = File::create(&tmp)?;
+            out.write_all(format(&self.entries).as_bytes())?;
+            out.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        sync_dir(&self.path)
+    }
+}
+
+/// Returns an error if `entry` can't be reversed, given whether paths
+/// exist.
+fn check(entry: &Entry, exists: impl Fn(&Path) -> bool) -> Result<(), String> {
+    if !exists(&entry.target) {
+        return Err(format!("{} no longer exists", entry.target.display()));
+    }
+    match entry.action {
+        // a case-only rename on a case-insensitive filesystem
+        Action::Rename
+            if exists(&entry.source)
+                && fs::canonicalize(&entry.source).ok() != fs::canonicalize(&entry.target).ok() =>
+        {
+            Err(format!(
+                "{} exists, not moving {} back",
+                entry.source.display(),
+                entry.target.display()
+            ))
+        }
+        // the copy might be all that is left
+        Action::Copy if !exists(&entry.source) => Err(format!(
+            "{} no longer exists, keeping the copy",
+            entry.source.display()
+        )),
+        Action::Rename | Action::Copy => Ok(()),
+    }
+}
+
+/// Moves a renamed file back or removes a copy.
+fn reverse(entry: &Entry) -> Result<(), String> {
+    match entry.action {
+        Action::Rename => fs::rename(&entry.target, &entry.source)
+            .map_err(|e| format!("failed to rename file: {}", e)),
+        Action::Copy => {
+            fs::remove_file(&entry.target).map_err(|e| format!("failed to remove copy: {}", e))
+        }
+    }
+}
+
+/// Reads the entries of the journal at `path` as they are, without
+/// settling pending ones. A missing journal has no entries.
+pub fn load(path: &Path) -> io::Result<Vec<Entry>> {
+    parse(&read(path)?)
+}
+
+/// Reads the journal at `path`, empty if it doesn't exist.
+fn read(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(source) => Ok(source),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Files handled by earlier runs, for `--since-last-run`: the new paths of
+/// renamed and copied files and the originals of copies, with their stamps
+/// from the latest run that handled them.
+#[derive(Debug, Default)]
+pub struct Processed(HashMap<PathBuf, Stamp>);
+
+impl Processed {
+    pub fn new(entries: &[Entry]) -> Processed {
+        let paths = entries
+            .iter()
+            .filter(|entry| entry.done)
+            .flat_map(|entry| {
+                let copied = (entry.action == Action::Copy).then_some(&entry.source);
+                [
+                    Some(&entry.target).zip(entry.target_stamp),
+                    copied.zip(entry.source_stamp),
+                ]
+                .into_iter()
+                .flatten()
+            })
+            .filter_map(|(path, stamp)| Some((fs::canonicalize(path).ok()?, stamp)));
+        Processed(paths.collect())
+    }
+
+    /// Returns true if `file` was handled by an earlier run and still has
+    /// the size and modification time it had then. Otherwise it was
+    /// replaced or changed, e.g. a new arrival under an old name.
+    pub fn contains(&self, file: &Path) -> bool {
+        fs::canonicalize(file)
+            .ok()
+            .and_then(|path| self.0.get(&path))
+            .is_some_and(|&stamp| Stamp::of(file) == Some(stamp))
+    }
+}
+
+/// Makes the creation or rename of the journal durable. Directories can't
+/// be opened for syncing on Windows, where renames are durable once they
+/// return.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    if cfg!(windows) {
+        return Ok(());
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+/// Keeps pending entries whose operation evidently happened, marking them
+/// done, and drops the others.
+fn reconcile(entries: Vec<Entry>) -> Vec<Entry> {
+    entries
+        .into_iter()
+        .filter_map(|mut entry| {
+            if !entry.done {
+                let happened = match entry.action {
+                    Action::Rename => !entry.source.exists() && entry.target.exists(),
+                    Action::Copy => entry.target.exists(),
+                };
+                if !happened {
+                    return None;
+                }
+                entry.done = true;
+                entry.stamp();
+            }
+            Some(entry)
+        })
+        .collect()
+}
+
+fn format(entries: &[Entry]) -> String {
+    entries
+        .iter()
+        .map(|entry| {
+            let stamps = match (entry.target_stamp, entry.source_stamp) {
+                (target, Some(source)) => format!(
+                    "\t{}\t{}",
+                    target.map(|s| s.to_string()).unwrap_or_default(),
+                    source
+                ),
+                (Some(target), None) => format!("\t{}", target),
+                (None, None) => String::new(),
+            };
+            format!(
+                "{}\t{}\t{}\t{}\t{}{}\n",
+                if entry.done { "done" } else { "pending" },
+                match entry.action {
+                    Action::Rename => "rename",
+                    Action::Copy => "copy",
+                },
+                escape(&entry.source.to_string_lossy()),
+                escape(&entry.target.to_string_lossy()),
+                run_id(entry.time),
+                stamps,
+            )
+        })
+        .collect()
+}
+
+/// Parses the journal. A `done` record settles the pending record of the
+/// same entry. A malformed last line without a newline is an append cut
+/// short by a crash and is dropped: either the file wasn't touched yet, or
+/// the entry stays pending until it is reconciled.
+fn parse(source: &str) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let count = source.lines().count();
+    for (n, line) in source.lines().enumerate() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let entry = match fields[..] {
+            [state, action, source, target, time, ref stamps @ ..] if stamps.len() <= 2 => {
+                let time = NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok();
+                // an empty stamp is a file that couldn't be read
+                let stamps: Option<Vec<Option<Stamp>>> = stamps
+                    .iter()
+                    .map(|s| match *s {
+                        "" => Some(None),
+                        s => Stamp::parse(s).map(Some),
+                    })
+                    .collect();
+                let done = match state {
+                    "done" => Some(true),
+                    "pending" => Some(false),
+                    _ => None,
+                };
+                let action = match action {
+                    "rename" => Some(Action::Rename),
+                    "copy" => Some(Action::Copy),
+                    _ => None,
+                };
+                done.zip(action)
+                    .zip(time)
+                    .zip(stamps)
+                    .map(|(((done, action), time), stamps)| Entry {
+                        action,
+                        source: PathBuf::from(unescape(source)),
+                        target: PathBuf::from(unescape(target)),
+                        done,
+                        time,
+                        target_stamp: stamps.first().copied().flatten(),
+                        source_stamp: stamps.get(1).copied().flatten(),
+                    })
+            }
+            _ => None,
+        };
+        match entry {
+            Some(entry) if entry.done => {
+                let pending = entries
+                    .iter_mut()
+                    .rev()
+                    .find(|e: &&mut Entry| !e.done && e.is(&entry));
+                match pending {
+                    Some(pending) => *pending = entry,
+                    None => entries.push(entry),
+                }
+            }
+            Some(entry) => entries.push(entry),
+            None if n + 1 == count && !source.ends_with('\n') => {}
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid journal entry on line {}", n + 1),
+                ))
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-journal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("journal.tsv");
+        let (a, b) = (dir.join("a\tb.jpg"), dir.join("new\\name.jpg"));
+        fs::write(&a, "").unwrap();
+
+        let mut journal = Journal::open(&path, NaiveDateTime::default()).unwrap();
+        journal.begin(Action::Rename, &a, &b).unwrap();
+        fs::rename(&a, &b).unwrap();
+        journal.commit().unwrap();
+        journal.begin(Action::Copy, &b, &a).unwrap();
+        let records: Vec<_> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| line.split('\t').take(2).collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(records, ["pending rename", "done rename", "pending copy"]);
+
+        // the copy never happened
+        let journal = Journal::open(&path, NaiveDateTime::default()).unwrap();
+        let expected = Entry {
+            action: Action::Rename,
+            source: a.clone(),
+            target: b.clone(),
+            done: true,
+            time: NaiveDateTime::default(),
+            target_stamp: Stamp::of(&b),
+            source_stamp: None,
+        };
+        assert!(expected.target_stamp.is_some());
+        assert_eq!(journal.entries, vec![expected]);
+        // compacted to the settled entry
+        assert_eq!(fs::read_to_string(&path).unwrap(), format(&journal.entries));
+        assert!(!dir.join("journal.tsv.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let dir =
+            std::env::temp_dir().join(format!("exif-rename-reconcile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        fs::write(&b, "").unwrap();
+        let entry = |action, source: &Path, target: &Path| Entry {
+            action,
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+            done: false,
+            time: NaiveDateTime::default(),
+            target_stamp: None,
+            source_stamp: None,
+        };
+
+        let entries = reconcile(vec![
+            entry(Action::Rename, &a, &b),
+            entry(Action::Rename, &b, &a),
+            entry(Action::Copy, &a, &b),
+            entry(Action::Copy, &b, &a),
+        ]);
+        fs::remove_dir_all(&dir).unwrap();
+        let kept: Vec<_> = entries.iter().map(|e| (e.action, e.done)).collect();
+        assert_eq!(kept, vec![(Action::Rename, true), (Action::Copy, true)]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let line = "done\tcopy\ta\\\\b\\tc\td\t2023-09-08T10:11:12.000\n";
+        let entries = parse(line).unwrap();
+        assert_eq!(entries[0].source, PathBuf::from("a\\b\tc"));
+        assert_eq!(format(&entries), line);
+        let err = parse(&format!("{}done\trename\ta\tb\n", line)).unwrap_err();
+        assert_eq!(err.to_string(), "invalid journal entry on line 2");
+        assert_eq!(parse(&format!("{}done\tren", line)).unwrap().len(), 1);
+        let entries = parse(&format!("pending{}done{}", &line[4..], &line[4..])).unwrap();
+        assert_eq!(format(&entries), line);
+
+        let line = "done\trename\ta\tb\t2023-09-08T10:11:12.345\n";
+        let entries = parse(line).unwrap();
+        assert_eq!(entries[0].time.to_string(), "2023-09-08 10:11:12.345");
+        assert_eq!(format(&entries), line);
+
+        let stamp = Some(Stamp {
+            size: 2483201,
+            modified: 1694167872345000000,
+        });
+        let line = "done\tcopy\ta\tb\t2023-09-08T10:11:12.345\t2483201,1694167872345000000\t\n";
+        let entries = parse(&format!(
+            "pending\tcopy\ta\tb\t2023-09-08T10:11:12.345\n{}",
+            line
+        ))
+        .unwrap();
+        assert_eq!(
+            (entries[0].target_stamp, entries[0].source_stamp),
+            (stamp, None)
+        );
+        assert_eq!(
+            format(&entries),
+            line.trim_end_matches(['\t', '\n']).to_string() + "\n"
+        );
+        let line = "done\tcopy\ta\tb\t2023-09-08T10:11:12.345\t\t2483201,1694167872345000000\n";
+        let entries = parse(line).unwrap();
+        assert_eq!(
+            (entries[0].target_stamp, entries[0].source_stamp),
+            (None, stamp)
+        );
+        assert_eq!(format(&entries), line);
+        assert!(parse("done\trename\ta\tb\t2023-09-08T10:11:12.345\t12\n").is_err());
+        assert!(parse("done\trename\ta\tb\tyesterday\n").is_err());
+    }
+
+    #[test]
+    fn test_undo() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-undo-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name);
+        for name in ["a.jpg", "b.jpg", "c.jpg"] {
+            fs::write(path(name), "").unwrap();
+        }
+        let first = NaiveDateTime::default();
+        let second = first + chrono::TimeDelta::milliseconds(1);
+        let mut journal = Journal::open(&path("journal.tsv"), first).unwrap();
+        journal
+            .begin(Action::Rename, &path("a.jpg"), &path("1.jpg"))
+            .unwrap();
+        fs::rename(path("a.jpg"), path("1.jpg")).unwrap();
+        journal.commit().unwrap();
+
+        let mut journal = Journal::open(&path("journal.tsv"), second).unwrap();
+        for (action, source, target) in [
+            (Action::Rename, "b.jpg", "2.jpg"),
+            (Action::Copy, "c.jpg", "3.jpg"),
+            (Action::Rename, "c.jpg", "4.jpg"),
+        ] {
+            journal.begin(action, &path(source), &path(target)).unwrap();
+            match action {
+                Action::Rename => fs::rename(path(source), path(target)).unwrap(),
+                Action::Copy => drop(fs::copy(path(source), path(target)).unwrap()),
+            }
+            journal.commit().unwrap();
+        }
+        // b.jpg came back, so 2.jpg can't be moved back
+        fs::write(path("b.jpg"), "").unwrap();
+
+        let undone = |journal: &mut Journal, dry_run| -> Vec<(String, bool)> {
+            let results = journal.undo(None, |_| true, dry_run).unwrap();
+            let name = |e: &Entry| e.target.file_name().unwrap().to_string_lossy().to_string();
+            results.iter().map(|(e, r)| (name(e), r.is_ok())).collect()
+        };
+        let all_ok = vec![
+            ("4.jpg".to_string(), true),
+            ("3.jpg".to_string(), true),
+            ("2.jpg".to_string(), false),
+        ];
+        assert_eq!(undone(&mut journal, true), all_ok);
+        assert!(path("4.jpg").exists());
+        assert_eq!(undone(&mut journal, false), all_ok);
+        assert!(path("c.jpg").exists() && !path("3.jpg").exists() && !path("4.jpg").exists());
+
+        // the failed entry is kept and undone again
+        let journal = Journal::open(&path("journal.tsv"), second).unwrap();
+        let targets: Vec<_> = journal.entries.iter().map(|e| e.target.clone()).collect();
+        assert_eq!(targets, vec![path("1.jpg"), path("2.jpg")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_undo_selected() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-undo-run-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name);
+        let first = NaiveDateTime::default();
+        let second = first + chrono::TimeDelta::milliseconds(1);
+        for (time, source, target) in [
+            (first, "a.jpg", "1.jpg"),
+            (first, "b.jpg", "2_pano.jpg"),
+            (second, "c.jpg", "3.jpg"),
+        ] {
+            fs::write(path(source), "").unwrap();
+            let mut journal = Journal::open(&path("journal.tsv"), time).unwrap();
+            journal
+                .begin(Action::Rename, &path(source), &path(target))
+                .unwrap();
+            fs::rename(path(source), path(target)).unwrap();
+            journal.commit().unwrap();
+        }
+
+        let mut journal = Journal::open(&path("journal.tsv"), second).unwrap();
+        let pano = |e: &Entry| e.target.to_string_lossy().ends_with("_pano.jpg");
+        let run = run_id(first);
+        let results = journal.undo(Some(&run), pano, false).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.target, path("2_pano.jpg"));
+        assert!(path("b.jpg").exists() && path("1.jpg").exists() && path("3.jpg").exists());
+        let targets: Vec<_> = journal.entries.iter().map(|e| e.target.clone()).collect();
+        assert_eq!(targets, vec![path("1.jpg"), path("3.jpg")]);
+
+        let err = journal.undo(Some("2000-01-01T00:00:00.000"), |_| true, true);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "no run '2000-01-01T00:00:00.000' in the journal"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_processed() {
+        let dir =
+            std::env::temp_dir().join(format!("exif-rename-processed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name);
+        for name in ["renamed.jpg", "original.jpg", "copy.jpg", "new.jpg"] {
+            fs::write(path(name), "").unwrap();
+        }
+        // a copy keeping the original's modification time, long before the run
+        File::options()
+            .write(true)
+            .open(path("copy.jpg"))
+            .unwrap()
+            .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(86400))
+            .unwrap();
+        let entry = |action, source: &str, target: &str| {
+            let mut entry = Entry {
+                action,
+                source: path(source),
+                target: path(target),
+                done: true,
+                time: NaiveDateTime::default(),
+                target_stamp: None,
+                source_stamp: None,
+            };
+            entry.stamp();
+            entry
+        };
+        let processed = Processed::new(&[
+            entry(Action::Rename, "IMG_1.jpg", "renamed.jpg"),
+            entry(Action::Copy, "original.jpg", "copy.jpg"),
+            entry(Action::Rename, "IMG_2.jpg", "new.jpg"),
+        ]);
+        // changed after the run, e.g. a new card reusing the name
+        fs::write(path("new.jpg"), "new").unwrap();
+        let contains = |name: &str| processed.contains(&path(name));
+        assert!(contains("renamed.jpg"));
+        assert!(contains("original.jpg"));
+        assert!(contains("copy.jpg"));
+        assert!(!contains("new.jpg"));
+        assert!(!contains("IMG_1.jpg"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
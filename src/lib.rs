@@ -0,0 +1,1090 @@
+//! Renaming image and video files by their metadata.
+//!
+//! [`Renamer`] plans the new names of a batch of files from their tags and
+//! a [`Pattern`], then renames, copies or moves them one by one. The
+//! `exif-rename` binary is a thin command line on top of it.
+//!
+//! ```
+//! use clap::Parser;
+//! use exif_rename::config::Settings;
+//! use exif_rename::{dump, Args, Input, Outcome, Renamer};
+//!
+//! let args = Args::parse_from(["exif-rename", "-p", "{Y}{m}{D}_{t}.{e}"]);
+//! let renamer = Renamer::new(args, Settings::default()).unwrap();
+//! let exif = dump::exif_vars("File Name : IMG_1234.JPG\nCreate Date : 2023:09:08 10:11:12\n");
+//! let plans = renamer.plan(vec![Input { file: None, exif }]);
+//! let plan = plans.into_iter().next().unwrap().unwrap();
+//! assert_eq!(plan.outcome, Outcome::Rename("20230908_101112.JPG".to_string()));
+//! ```
+
+pub mod age;
+pub mod aspect;
+pub mod bag;
+pub mod burst;
+pub mod calendar;
+pub mod camera;
+pub mod city;
+pub mod config;
+pub mod conflict;
+#[cfg(feature = "convert")]
+pub mod convert;
+pub mod counters;
+pub mod dates;
+pub mod daypart;
+pub mod dcf;
+pub mod dump;
+pub mod empty;
+pub mod exif;
+pub mod filter;
+pub mod glob;
+pub mod gpx;
+pub mod hook;
+pub mod journal;
+pub mod matrix;
+pub mod normalize;
+pub mod origin;
+#[cfg(unix)]
+pub mod owner;
+pub mod pattern;
+pub mod plan;
+pub mod preflight;
+pub mod progress;
+pub mod protect;
+pub mod renamer;
+pub mod rules;
+#[cfg(feature = "convert")]
+pub mod sheet;
+pub mod size;
+pub mod skiplist;
+pub mod sort;
+pub mod source;
+pub mod stats;
+pub mod suffix;
+pub mod summary;
+pub mod truncate;
+pub mod tz;
+pub mod unique;
+pub mod verify;
+pub mod walk;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike};
+use clap::{Parser, Subcommand};
+use regex::Regex;
+
+use crate::dates::DateSource;
+use crate::dump::sanitize_text;
+pub use crate::pattern::Pattern;
+pub use crate::plan::{Input, Outcome, Plan, Warning};
+pub use crate::renamer::Renamer;
+pub use crate::summary::Status;
+
+/// Rename image/video files by Exif data from exiftool
+#[derive(Parser, Debug, Clone)]
+#[command(version, about)]
+pub struct Args {
+    /// exiftool output file (`exiftool FILE > exif.txt`), or `-` to read it
+    /// from standard input. Overrides reading the metadata from the file
+    /// itself, which supports JPEG and TIFF-based raw files.
+    #[arg(short, long)]
+    pub exif: Option<String>,
+
+    /// Format of the --exif file: `text`, `json` for the output of
+    /// `exiftool -j` or `csv` for `exiftool -csv`, which may describe several
+    /// files by their path.
+    #[arg(long, value_name = "FORMAT", default_value = "text")]
+    pub exif_format: source::ExifFormat,
+
+    /// Read the metadata of each file by running `exiftool -s` on it, for
+    /// formats the built-in reader doesn't support.
+    #[arg(long, conflicts_with = "exif")]
+    pub use_exiftool: bool,
+
+    /// exiftool to run, if it isn't on the PATH. Implies --use-exiftool.
+    #[arg(long, value_name = "PATH", conflicts_with = "exif")]
+    pub exiftool_path: Option<PathBuf>,
+
+    /// Read the metadata of each file from the exiftool dump next to it, if
+    /// there is one: IMG_1234.JPG.txt or IMG_1234.txt, or the same in .json
+    #[arg(long, conflicts_with = "exif")]
+    pub sidecars: bool,
+
+    /// Extension of the dumps --sidecars looks for, instead of txt and json;
+    /// implies --sidecars. JSON and CSV dumps are read by their extension
+    #[arg(long, value_name = "EXT", conflicts_with = "exif")]
+    pub sidecar_ext: Vec<String>,
+
+    /// Filename pattern. e.g. "{Y}{m}{D}_{t}_{T2}.{e}"
+    /// Defaults to a built-in pattern by file type if the config sets none.
+    /// Given several times, each file gets the first pattern all of whose
+    /// variables it has, or the last one.
+    #[arg(short, long)]
+    pub pattern: Vec<Pattern>,
+
+    /// Use the pattern saved under this name in the config's `[presets]`
+    #[arg(long, value_name = "NAME", conflicts_with = "pattern")]
+    pub preset: Option<String>,
+
+    /// Render variables missing from a file as this text instead of failing
+    /// the file, unless they have a default as in `{T2|unknown}`
+    #[arg(long, value_name = "TEXT")]
+    pub default_value: Option<String>,
+
+    /// Directory pattern, joined with the filename rendered from `--pattern`.
+    /// e.g. "{Y}/{group}"
+    #[arg(long, value_name = "PATTERN")]
+    pub dir_pattern: Option<Pattern>,
+
+    /// Partition files into groups by this pattern, for `{group}` and other
+    /// group variables. e.g. "{Y}-{m}-{D}"
+    #[arg(long, value_name = "PATTERN")]
+    pub group_by: Option<Pattern>,
+
+    /// Letter files captured within this time of each other `a`, `b`, ... in
+    /// `{burst}`, by sub-second or input order. e.g. "2s" or "500ms"
+    #[arg(long, value_name = "DURATION")]
+    pub collision_window: Option<burst::Window>,
+
+    /// Order `{n}` and `{bucket}` number files in: `date` for the capture
+    /// date, `name` for the file name or `mtime` for the modification time
+    #[arg(long, value_name = "ORDER", default_value = "date")]
+    pub sort: sort::SortKey,
+
+    /// First value of the `{n}` counter
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub counter_start: u64,
+
+    /// Increment of the `{n}` counter from one file to the next
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub counter_step: u64,
+
+    /// Restart the `{n}` counter in each directory
+    #[arg(long)]
+    pub counter_per_dir: bool,
+
+    /// Restart the `{n}` counter on each capture day
+    #[arg(long)]
+    pub counter_per_day: bool,
+
+    /// File keeping the last `{n}` of each counter, so the next run
+    /// continues the numbering; created if missing
+    #[arg(long, value_name = "FILE")]
+    pub counters: Option<PathBuf>,
+
+    /// How `{u}` tells apart files that would get the same name: `number`
+    /// for _1, _2, ... or `letter` for a, b, ...
+    #[arg(long, value_name = "STYLE", default_value = "number")]
+    pub unique_style: unique::Style,
+
+    /// How numbers telling apart files with the same name are written, by
+    /// `--on-conflict number` and `{u}`. e.g. "-{n:02}" or " (copy {n})"
+    /// [default: -{n} and _{n}]
+    #[arg(long, value_name = "TEMPLATE")]
+    pub suffix_format: Option<suffix::SuffixFormat>,
+
+    /// Number the first of the files sharing a name in `{u}` too
+    #[arg(long)]
+    pub suffix_first: bool,
+
+    /// Fail if a pattern uses variables depending on the time of the run, so
+    /// the same files always get the same names
+    #[arg(long)]
+    pub deterministic: bool,
+
+    /// Read variables back from the current file name with this pattern, to
+    /// re-render them with `--pattern`. The date read back is used for files
+    /// without a capture date. e.g. "{Y}{m}{D}_{t}_{r}.{e}"
+    #[arg(long, value_name = "PATTERN")]
+    pub from_pattern: Option<Pattern>,
+
+    /// Print every candidate capture date of each file (tags, file name,
+    /// modification and creation time, XMP sidecar) next to the chosen one
+    #[arg(long)]
+    pub date_report: bool,
+
+    /// Hold back files with a candidate date further off the chosen one,
+    /// setting `{date_suspect}` for rules to route them. e.g. "1d" or "12h"
+    #[arg(long, value_name = "DURATION")]
+    pub max_date_disagreement: Option<dates::Disagreement>,
+
+    /// Use the file creation time as the capture date of files without one
+    #[arg(long)]
+    pub btime_fallback: bool,
+
+    /// Tags to take the capture date from, in order, instead of the built-in
+    /// choice; `mtime` is the file modification time.
+    /// e.g. "DateTimeOriginal>CreateDate>MediaCreateDate>mtime"
+    #[arg(long, value_name = "TAGS")]
+    pub date_source: Option<dates::DateSource>,
+
+    /// Time zone to convert capture dates into, as an offset, an IANA name
+    /// or `local`; dates follow their EXIF offset tags, videos without one
+    /// are taken as UTC. e.g. "+09:00" or "Asia/Seoul"
+    #[arg(long, visible_alias = "timezone", value_name = "ZONE")]
+    pub tz: Option<tz::Tz>,
+
+    /// Config file [default: ~/.config/exif-rename/config.toml]
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Age thresholds for `{age_bucket}`. e.g. "30d,6m,2y" [default: 1y,5y]
+    #[arg(long, value_name = "LIST")]
+    pub age_buckets: Option<age::AgeBuckets>,
+
+    /// Size thresholds for `{size_tier}`. e.g. "50M,2G" [default: 10M,100M,1G]
+    #[arg(long, value_name = "LIST")]
+    pub size_tiers: Option<size::SizeTiers>,
+
+    /// Config profile to use
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Files to rename, or glob patterns such as "*.jpg". If omitted, the
+    /// files a JSON or CSV --exif describes, or else only prints the new
+    /// filename.
+    pub files: Vec<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Rename the image and video files in directories given as files, and
+    /// in their subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// How deep `--recursive` goes; 1 is only the files in the directories given
+    #[arg(long, value_name = "N", requires = "recursive")]
+    pub max_depth: Option<usize>,
+
+    /// Files listed here are skipped without warnings or errors
+    #[arg(long, value_name = "FILE")]
+    pub skiplist: Option<PathBuf>,
+
+    /// Record renames and copies in this journal, written crash-safely
+    #[arg(long, value_name = "FILE", global = true)]
+    pub journal: Option<PathBuf>,
+
+    /// Skip files renamed or copied by earlier runs recorded in `--journal`,
+    /// unless modified since, so repeated runs only touch new arrivals
+    #[arg(long, requires = "journal")]
+    pub since_last_run: bool,
+
+    /// Refuse to touch files owned by other users
+    #[cfg(unix)]
+    #[arg(long)]
+    pub require_owner: bool,
+
+    /// Rename read-only and immutable files, and touch files of other users
+    /// despite `--require-owner`
+    #[arg(long)]
+    pub force: bool,
+
+    /// Hash each file before renaming, copying or moving it and again at its
+    /// new path, and stop the run if they differ
+    #[arg(long)]
+    pub paranoid: bool,
+
+    /// Give renamed files and created directories to this owner. e.g. "photos:family"
+    #[cfg(unix)]
+    #[arg(long, value_name = "USER[:GROUP]")]
+    pub chown_to: Option<owner::Owner>,
+
+    /// What to do when the new name is taken: `error`, `skip`, `overwrite`,
+    /// or `number` to append -1, -2, ... [default: error]
+    #[arg(long, value_name = "STRATEGY")]
+    pub on_conflict: Option<conflict::OnConflict>,
+
+    /// What to do when no file is left to rename, e.g. because filters
+    /// excluded them all: `ignore`, `status` to exit with status 3, or
+    /// `error`
+    #[arg(long, value_name = "ACTION", default_value = "status")]
+    pub on_empty: empty::OnEmpty,
+
+    /// Keep new names within DCF rules (DCIM/100CANON/DSC_0001.JPG), so
+    /// cameras can still play the card back: names are uppercased, and
+    /// files whose name breaks the rules fail
+    #[arg(long)]
+    pub dcf: bool,
+
+    /// Print what would be renamed or copied without touching any file
+    #[arg(short = 'n', long, global = true)]
+    pub dry_run: bool,
+
+    /// Print counts of renamed, skipped and failed files, matched rules and
+    /// warnings at the end
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Show how many files have been read on stderr
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Copy files under their new names instead of renaming them, leaving
+    /// the originals untouched
+    #[arg(long)]
+    pub copy: bool,
+
+    /// Copy files under their new names into this directory instead of the
+    /// config target, leaving the originals untouched. Implies --copy
+    #[arg(long, value_name = "DIR")]
+    pub copy_to: Option<PathBuf>,
+
+    /// Keep the original name and run ID of each renamed or copied file in
+    /// its `user.exif-rename.orig` extended attribute, for `restore-names`
+    #[arg(long)]
+    pub keep_origin: bool,
+
+    /// Add files failing to render the pattern to `--skiplist`
+    #[arg(long, requires = "skiplist")]
+    pub update_skiplist: bool,
+
+    /// Only rename files matching all filters. e.g. "is-pano", "!is-pano", "Model=iPhone 14"
+    #[arg(long, value_name = "EXPR")]
+    pub filter: Vec<filter::Filter>,
+
+    /// Only rename files whose name matches one of these globs, ignoring
+    /// case. e.g. "*.jpg"
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Leave files whose name matches one of these globs alone, ignoring
+    /// case. e.g. "*_edit.*"
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// GPX track giving `{lat}`, `{lon}` and `{city}` to files without GPS
+    /// tags, by capture time
+    #[arg(long, value_name = "FILE", value_parser = gpx::Track::load)]
+    pub gpx: Option<gpx::Track>,
+
+    /// iCalendar file whose events name files captured during them in
+    /// `{event}`. Can be given more than once.
+    #[arg(long, value_name = "FILE", value_parser = calendar::Calendar::load)]
+    pub calendar: Vec<calendar::Calendar>,
+
+    /// City list for `{city}` by coordinates, in the GeoNames format
+    /// (e.g. cities15000.txt)
+    #[arg(long, value_name = "FILE", value_parser = city::Cities::load)]
+    pub cities: Option<city::Cities>,
+
+    /// Per-camera clock offsets applied before date variables are computed,
+    /// by serial number or model. e.g. "SERIAL123=+00:00:42,NIKON D750=-00:01:12"
+    #[arg(long, value_name = "CAMERA=OFFSET", value_delimiter = ',')]
+    pub sync_offsets: Vec<camera::SyncOffset>,
+
+    /// Clock offset added to the capture date of every file, on top of
+    /// `--sync-offsets`. e.g. "+1h30m" or "-37s"
+    #[arg(long, value_name = "OFFSET", allow_hyphen_values = true)]
+    pub shift: Option<camera::Shift>,
+
+    /// Labels for `{cam}` by camera serial number or model. e.g. "SERIAL123=A,NIKON D750=B"
+    #[arg(long, value_name = "CAMERA=LABEL", value_delimiter = ',')]
+    pub cam_map: Vec<camera::CamLabel>,
+
+    /// Maximum length of variable values in names, with the truncation style
+    /// `end` (default), `middle` or `vowels`. e.g. "Model=20,LensModel=16:middle"
+    #[arg(long, value_name = "VAR=LEN[:STYLE]", value_delimiter = ',')]
+    pub max_var_len: Vec<truncate::MaxLen>,
+
+    /// Transcode matching files while renaming. e.g. "heic:jpg"
+    #[cfg(feature = "convert")]
+    #[arg(long, value_name = "FROM:TO")]
+    pub convert: Vec<convert::Conversion>,
+
+    /// Directory (relative to the renamed file) to move converted originals into.
+    /// Originals stay next to the converted file if omitted.
+    #[cfg(feature = "convert")]
+    #[arg(long, value_name = "DIR", requires = "convert")]
+    pub originals: Option<String>,
+
+    /// Add a thumbnail of the renamed file to a contact sheet (`index.html`) in this directory.
+    #[cfg(feature = "convert")]
+    #[arg(long, value_name = "DIR")]
+    pub contact_sheet: Option<PathBuf>,
+}
+pub type Vars = HashMap<String, String>;
+
+/// Software names that indicate a file was written by an editor rather than
+/// straight out of the camera.
+const EDITORS: &[&str] = &[
+    "photoshop",
+    "lightroom",
+    "gimp",
+    "capture one",
+    "darktable",
+    "rawtherapee",
+    "affinity",
+    "luminar",
+    "dxo",
+    "acdsee",
+    "snapseed",
+    "picasa",
+    "pixelmator",
+    "paint.net",
+];
+
+/// Trailing digits of a filename stem, for `{r}`.
+static IMAGE_NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d+)\D*$").unwrap());
+
+/// A coordinate as printed by exiftool, see `parse_coordinate`.
+static COORDINATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(-?[\d.]+)(?:\s*deg\s*([\d.]+)'\s*(?:([\d.]+)")?)?\s*([NSEW])?$"#).unwrap()
+});
+
+/// Tags found in every full dump written by exiftool in English. A dump
+/// with many tags but none of these was most likely written with `-lang`.
+const ENGLISH_TAGS: &[&str] = &[
+    "ExifToolVersionNumber",
+    "FileName",
+    "Directory",
+    "FileSize",
+    "FileType",
+    "MIMEType",
+    "CreateDate",
+    "Model",
+];
+
+/// Maximum length of free-text tokens such as `{title}` and `{caption}`.
+const TEXT_MAX_LEN: usize = 64;
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Check patterns before renaming anything
+    Pattern {
+        #[command(subcommand)]
+        command: PatternCommand,
+    },
+    /// Reverse the renames and copies of the last run recorded in a journal
+    Undo {
+        /// Journal to undo from, `--journal` if omitted
+        #[arg(value_name = "JOURNAL")]
+        file: Option<PathBuf>,
+        /// Undo the run with this ID, the time it started as in the journal,
+        /// instead of the last run
+        #[arg(long, value_name = "ID")]
+        run: Option<String>,
+        /// Only undo files whose tags match all filters. e.g. "Model=iPhone 14"
+        #[arg(long, value_name = "EXPR")]
+        filter: Vec<filter::Filter>,
+        /// Only undo files whose new name matches one of these globs,
+        /// ignoring case. e.g. "*_pano.jpg"
+        #[arg(long, value_name = "GLOB")]
+        name: Vec<String>,
+    },
+    /// Rename files back to the names kept by `--keep-origin`
+    RestoreNames {
+        /// Files to restore, or glob patterns such as "*.jpg"
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Print what files hold by camera, month and extension, and which lack
+    /// metadata, without renaming anything
+    Stats {
+        /// Files or directories to scan, or glob patterns such as "*.jpg"
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Copy files into a new BagIt bag, named with the pattern
+    ExportBag {
+        /// Directory of the bag, which must not exist or be empty
+        #[arg(value_name = "BAG")]
+        bag: PathBuf,
+        /// Files to export, or glob patterns such as "*.jpg"
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PatternCommand {
+    /// Print the names `--pattern` and the config give sample files
+    Preview {
+        /// Render against built-in samples: no GPS, no Model, no date,
+        /// midnight, Dec 31, a burst, HEIC and video
+        #[arg(long, required = true)]
+        matrix: bool,
+    },
+}
+/// Adds Downloader Pro style variables derived from exif tags and the filename.
+///
+/// | var       | description                              |
+/// |-----------|------------------------------------------|
+/// | `Y`       | 4-digit year                             |
+/// | `y`       | 2-digit year                             |
+/// | `m`       | month (01-12)                            |
+/// | `D`       | day of month (01-31)                     |
+/// | `H`       | hour (00-23)                             |
+/// | `M`       | minute                                   |
+/// | `S`       | second                                   |
+/// | `t`       | time as `HHMMSS`                         |
+/// | `date_confidence` | `high`, `medium` or `low`, see `best_date` |
+/// | `daypart` | `morning`, `afternoon`, `evening`, `night` |
+/// | `lat`     | GPS latitude in decimal degrees          |
+/// | `lon`     | GPS longitude in decimal degrees         |
+/// | `T2`      | camera model                             |
+/// | `aspect`  | frame shape, see `aspect`                |
+/// | `exposure`, `aperture`, `focal`, ... | see `normalize`  |
+/// | `title`   | XMP `dc:title`, sanitized                |
+/// | `caption` | XMP `dc:description`, sanitized          |
+/// | `software`| `Software` tag, sanitized                |
+/// | `edited`  | `yes` if an editor wrote the file        |
+/// | `projection` | GPano projection, e.g. `equirectangular` |
+/// | `faces`   | number of tagged face regions            |
+/// | `people`  | names of tagged faces joined with `-`    |
+/// | `f`       | original filename without extension      |
+/// | `e`       | original extension                       |
+/// | `r`       | image number (trailing digits of `f`)    |
+///
+/// `time_offset` corrects the camera clock before date variables are computed.
+/// `date_source` replaces the built-in choice of the capture date, see
+/// [`best_date`]. `fallback_date` is used, with low confidence, if the tags have no date,
+/// along with a description of where it comes from.
+fn extend_vars(
+    exif: Vars,
+    file: Option<&str>,
+    time_offset: TimeDelta,
+    date_source: Option<&DateSource>,
+    fallback_date: Option<(NaiveDateTime, &'static str)>,
+    warnings: &mut Vec<Warning>,
+) -> Vars {
+    let mut vars = Vars::new();
+
+    // short dumps may be selective (`exiftool -Title`)
+    if exif.len() >= 10 && !ENGLISH_TAGS.iter().any(|tag| exif.contains_key(*tag)) {
+        warnings.push(Warning::UnknownTags);
+    }
+
+    let coordinates = gps_coordinates(&exif);
+    if let Some((lat, lon)) = coordinates {
+        vars.insert("lat".to_string(), format!("{:.4}", lat));
+        vars.insert("lon".to_string(), format!("{:.4}", lon));
+    }
+
+    let date = match (best_date(&exif, date_source), fallback_date) {
+        (Some((dt, confidence)), _) => {
+            vars.insert("date_confidence".to_string(), confidence.to_string());
+            Some(dt + time_offset)
+        }
+        (None, Some((dt, source))) => {
+            vars.insert("date_confidence".to_string(), "low".to_string());
+            warnings.push(Warning::FallbackDate(source));
+            Some(dt)
+        }
+        (None, None) => {
+            warnings.push(Warning::MissingDate);
+            None
+        }
+    };
+    if let Some(dt) = date {
+        insert_date_vars(&mut vars, "", dt);
+        // for strftime filters, e.g. `{date:%b}`
+        vars.insert("date".to_string(), dt.format("%Y%m%dT%H%M%S").to_string());
+
+        let offset = ["OffsetTimeOriginal", "OffsetTime"]
+            .iter()
+            .find_map(|tag| exif.get(*tag).and_then(|s| daypart::parse_offset(s)));
+        let daypart = daypart::daypart(dt, offset, coordinates);
+        vars.insert("daypart".to_string(), daypart.to_string());
+    }
+
+    if let Some(model) = exif.get("Model") {
+        vars.insert("T2".to_string(), model.clone());
+    }
+    normalize::insert(&exif, &mut vars);
+    if let Some(aspect) = aspect::aspect(&exif) {
+        vars.insert("aspect".to_string(), aspect.to_string());
+    }
+
+    let title = first_tag(&exif, &["Title", "ObjectName", "XPTitle"]);
+    insert_text(&mut vars, warnings, "title", title);
+    let caption = first_tag(
+        &exif,
+        &["Description", "ImageDescription", "Caption-Abstract"],
+    );
+    insert_text(&mut vars, warnings, "caption", caption);
+    let software = first_tag(&exif, &["Software"]);
+    insert_text(&mut vars, warnings, "software", software);
+    let edited = if is_edited(&exif) { "yes" } else { "no" };
+    vars.insert("edited".to_string(), edited.to_string());
+
+    let projection = exif.get("ProjectionType").map(|p| p.to_lowercase());
+    vars.insert("projection".to_string(), projection.unwrap_or_default());
+
+    let people = face_regions(&exif);
+    vars.insert("faces".to_string(), people.len().to_string());
+    vars.insert("people".to_string(), people.join("-"));
+
+    let filename = file
+        .and_then(|f| Path::new(f).file_name())
+        .map(|f| f.to_string_lossy().to_string())
+        .or_else(|| exif.get("FileName").cloned());
+    if let Some(filename) = filename {
+        let path = Path::new(&filename);
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let ext = path
+            .extension()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if let Some(caps) = IMAGE_NUMBER.captures(&stem) {
+            vars.insert("r".to_string(), caps[1].to_string());
+        }
+        vars.insert("f".to_string(), stem);
+        vars.insert("e".to_string(), ext);
+    }
+
+    let mut exif = exif;
+    exif.extend(vars);
+    exif
+}
+
+/// Returns the creation (birth) time of `path` in local time, on platforms
+/// and filesystems that record it.
+fn file_created(path: &str) -> Option<NaiveDateTime> {
+    let created = fs::metadata(path).and_then(|m| m.created()).ok()?;
+    Some(DateTime::<Local>::from(created).naive_local())
+}
+
+/// Returns the capture date corrected by `time_offset`.
+fn capture_date(
+    exif: &Vars,
+    time_offset: TimeDelta,
+    date_source: Option<&DateSource>,
+) -> Option<NaiveDateTime> {
+    let (dt, _) = best_date(exif, date_source)?;
+    Some(dt + time_offset)
+}
+
+/// Returns the best available capture date and how much to trust it:
+///
+/// | confidence | source                                                      |
+/// |------------|-------------------------------------------------------------|
+/// | `high`     | `CreateDate` or `DateTimeOriginal`                          |
+/// | `medium`   | the same without seconds, or the GPS time with a UTC offset |
+/// | `low`      | the GPS time without a UTC offset, or a date without time  |
+///
+/// A `--date-source` chain replaces these rules, see [`DateSource::date`].
+fn best_date(
+    exif: &Vars,
+    date_source: Option<&DateSource>,
+) -> Option<(NaiveDateTime, &'static str)> {
+    if let Some(date_source) = date_source {
+        return date_source.date(exif);
+    }
+    // `exiftool -s` prints `DateTimeOriginal`, plain exiftool `Date/Time Original`
+    const TAGS: &[&str] = &["CreateDate", "DateTimeOriginal", "Date/TimeOriginal"];
+    let tags = || TAGS.iter().filter_map(|tag| exif.get(*tag));
+
+    if let Some(dt) = tags().find_map(|s| parse_datetime(s)) {
+        return Some((dt, "high"));
+    }
+    let no_seconds =
+        |s: &String| NaiveDateTime::parse_from_str(s.get(..16)?, "%Y:%m:%d %H:%M").ok();
+    if let Some(dt) = tags().find_map(no_seconds) {
+        return Some((dt, "medium"));
+    }
+    if let Some(utc) = gps_datetime(exif) {
+        let offset = ["OffsetTimeOriginal", "OffsetTime"]
+            .iter()
+            .find_map(|tag| exif.get(*tag).and_then(|s| daypart::parse_offset(s)));
+        return Some(match offset {
+            Some(minutes) => (utc + TimeDelta::minutes(minutes as i64), "medium"),
+            None => (utc, "low"),
+        });
+    }
+    let date_only = |s: &String| NaiveDate::parse_from_str(s.get(..10)?, "%Y:%m:%d").ok();
+    let date = tags().chain(exif.get("GPSDateStamp")).find_map(date_only)?;
+    Some((date.and_time(NaiveTime::MIN), "low"))
+}
+
+/// Returns the UTC time recorded by the GPS, from the composite
+/// `GPSDateTime` tag or `GPSDateStamp` and `GPSTimeStamp`.
+fn gps_datetime(exif: &Vars) -> Option<NaiveDateTime> {
+    let composite = ["GPSDateTime", "GPSDate/Time"]
+        .iter()
+        .find_map(|tag| exif.get(*tag).and_then(|s| parse_datetime(s)));
+    if let Some(dt) = composite {
+        return Some(dt);
+    }
+    let date = NaiveDate::parse_from_str(exif.get("GPSDateStamp")?, "%Y:%m:%d").ok()?;
+    let time = NaiveTime::parse_from_str(exif.get("GPSTimeStamp")?.get(..8)?, "%H:%M:%S").ok()?;
+    Some(date.and_time(time))
+}
+
+/// Inserts `Y`, `y`, `m`, `D`, `H`, `M`, `S` and `t` for `dt`, each name
+/// prefixed with `prefix`.
+fn insert_date_vars(vars: &mut Vars, prefix: &str, dt: NaiveDateTime) {
+    let mut insert = |name: &str, value: String| vars.insert(format!("{}{}", prefix, name), value);
+    insert("Y", format!("{:04}", dt.year()));
+    insert("y", format!("{:02}", dt.year() % 100));
+    insert("m", format!("{:02}", dt.month()));
+    insert("D", format!("{:02}", dt.day()));
+    insert("H", format!("{:02}", dt.hour()));
+    insert("M", format!("{:02}", dt.minute()));
+    insert("S", format!("{:02}", dt.second()));
+    insert("t", dt.format("%H%M%S").to_string());
+}
+
+/// Returns the value of the first tag present, or an empty string.
+fn first_tag<'a>(exif: &'a Vars, tags: &[&str]) -> &'a str {
+    tags.iter()
+        .find_map(|tag| exif.get(*tag))
+        .map(String::as_str)
+        .unwrap_or_default()
+}
+
+/// Inserts sanitized free text, warning if anything had to be changed.
+fn insert_text(vars: &mut Vars, warnings: &mut Vec<Warning>, name: &str, value: &str) {
+    let text = sanitize_text(value, TEXT_MAX_LEN);
+    if text != value.trim() {
+        warnings.push(Warning::Sanitized(name.to_string()));
+    }
+    vars.insert(name.to_string(), text);
+}
+
+/// Returns true if the file has an XMP edit history or was saved by a
+/// known editing application.
+fn is_edited(exif: &Vars) -> bool {
+    if exif.contains_key("HistoryAction") || exif.contains_key("HistorySoftwareAgent") {
+        return true;
+    }
+    ["Software", "ProcessingSoftware", "CreatorTool"]
+        .iter()
+        .filter_map(|tag| exif.get(*tag))
+        .map(|s| s.to_lowercase())
+        .any(|s| EDITORS.iter().any(|editor| s.contains(editor)))
+}
+
+/// Returns the names of face regions tagged by Lightroom/Picasa (MWG
+/// `RegionName`/`RegionType`) or Windows Photo Gallery (`RegionPersonDisplayName`).
+/// Unnamed faces are returned as empty strings so they still count.
+fn face_regions(exif: &Vars) -> Vec<String> {
+    let split =
+        |s: &String| -> Vec<String> { s.split(", ").map(|v| v.trim().to_string()).collect() };
+
+    if let Some(names) = exif.get("RegionPersonDisplayName") {
+        return split(names);
+    }
+
+    let names = exif.get("RegionName").map(split).unwrap_or_default();
+    match exif.get("RegionType").map(split) {
+        Some(types) => types
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.eq_ignore_ascii_case("face"))
+            .map(|(i, _)| names.get(i).cloned().unwrap_or_default())
+            .collect(),
+        None => names,
+    }
+}
+
+/// Parses exif datetime strings like `2023:09:08 10:11:12`, ignoring
+/// trailing sub-seconds or timezone offsets.
+fn parse_datetime(s: &str) -> Option<NaiveDateTime> {
+    let s = s.get(..19)?;
+    NaiveDateTime::parse_from_str(s, "%Y:%m:%d %H:%M:%S").ok()
+}
+
+/// Returns `(latitude, longitude)` in decimal degrees from the GPS tags.
+fn gps_coordinates(exif: &Vars) -> Option<(f64, f64)> {
+    let separate = || {
+        let lat = parse_coordinate(exif.get("GPSLatitude")?, exif.get("GPSLatitudeRef"))?;
+        let lon = parse_coordinate(exif.get("GPSLongitude")?, exif.get("GPSLongitudeRef"))?;
+        Some((lat, lon))
+    };
+    separate().or_else(|| parse_position(exif.get("GPSPosition")?))
+}
+
+/// Parses the composite `GPSPosition` tag, printed as
+/// `37 deg 33' 59.40" N, 126 deg 58' 40.80" E` or, with `exiftool -n`, as
+/// `37.5665 126.978`.
+fn parse_position(value: &str) -> Option<(f64, f64)> {
+    let (lat, lon) = value
+        .split_once(',')
+        .or_else(|| value.trim().split_once(char::is_whitespace))?;
+    Some((parse_coordinate(lat, None)?, parse_coordinate(lon, None)?))
+}
+
+/// Parses a coordinate printed either as `37 deg 33' 59.40" N` or as a
+/// plain decimal (`exiftool -n`). The hemisphere comes from the value
+/// suffix or, when missing, from the matching `Ref` tag.
+fn parse_coordinate(value: &str, reference: Option<&String>) -> Option<f64> {
+    let caps = COORDINATE.captures(value.trim())?;
+    let mut deg: f64 = caps[1].parse().ok()?;
+    if let Some(min) = caps.get(2) {
+        deg += min.as_str().parse::<f64>().ok()? / 60.0;
+    }
+    if let Some(sec) = caps.get(3) {
+        deg += sec.as_str().parse::<f64>().ok()? / 3600.0;
+    }
+
+    let hemisphere = caps
+        .get(4)
+        .map(|m| m.as_str())
+        .or_else(|| reference.and_then(|r| r.get(..1)));
+    if matches!(hemisphere, Some("S") | Some("W")) {
+        deg = -deg.abs();
+    }
+    Some(deg)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::exif_vars;
+
+    fn extend(dump: &str) -> Vars {
+        extend_vars(
+            exif_vars(dump),
+            None,
+            TimeDelta::zero(),
+            None,
+            None,
+            &mut Vec::new(),
+        )
+    }
+
+    const DUMP: &str = "\
+File Name                       : IMG_1234.JPG
+Model                           : Canon EOS R5
+Create Date                     : 2023:09:08 10:11:12
+GPS Latitude                    : 37 deg 33' 59.40\" N
+GPS Longitude                   : 126 deg 58' 40.80\" E
+";
+
+    #[test]
+    fn test_extend_vars() {
+        let vars = extend(DUMP);
+        let pattern = Pattern::parse("{Y}{m}{D}_{t}_{T2}_{r}.{e}").unwrap();
+        let name = pattern.render(&vars).unwrap();
+        assert_eq!(name, "20230908_101112_Canon EOS R5_1234.JPG");
+        assert_eq!(vars["daypart"], "morning");
+        let pattern = Pattern::parse("{date:%Y-%m-%d_%b}").unwrap();
+        assert_eq!(pattern.render(&vars).unwrap(), "2023-09-08_Sep");
+
+        let mut warnings = Vec::new();
+        let vars = extend_vars(
+            exif_vars(DUMP),
+            None,
+            TimeDelta::seconds(50),
+            None,
+            None,
+            &mut warnings,
+        );
+        assert_eq!(vars["t"], "101202");
+        assert!(warnings.is_empty());
+
+        let mut warnings = Vec::new();
+        extend_vars(
+            exif_vars("Title : a/b\n"),
+            None,
+            TimeDelta::zero(),
+            None,
+            None,
+            &mut warnings,
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::MissingDate,
+                Warning::Sanitized("title".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_date_vars() {
+        let mut vars = Vars::new();
+        let dt = parse_datetime("2024:01:02 03:04:05").unwrap();
+        insert_date_vars(&mut vars, "import_", dt);
+        assert_eq!(vars["import_Y"], "2024");
+        assert_eq!(vars["import_t"], "030405");
+        assert!(!vars.contains_key("Y"));
+    }
+
+    #[test]
+    fn test_face_regions() {
+        let vars = extend("Region Name : Alice, Bob, Tree\nRegion Type : Face, Face, Focus\n");
+        assert_eq!(vars["faces"], "2");
+        assert_eq!(vars["people"], "Alice-Bob");
+
+        let vars = extend(DUMP);
+        assert_eq!(vars["faces"], "0");
+        assert_eq!(vars["people"], "");
+    }
+
+    #[test]
+    fn test_edited() {
+        let vars = extend("Software : Ver.1.10\n");
+        assert_eq!(vars["software"], "Ver.1.10");
+        assert_eq!(vars["edited"], "no");
+
+        let dump = "Software : Adobe Photoshop Lightroom Classic 12.0 (Windows)\n";
+        assert_eq!(extend(dump)["edited"], "yes");
+        let dump = "Software : iOS 16.5\nHistory Action : saved\n";
+        assert_eq!(extend(dump)["edited"], "yes");
+    }
+
+    #[test]
+    fn test_fallback_date() {
+        let mut warnings = Vec::new();
+        let btime = NaiveDate::from_ymd_opt(2023, 9, 8)
+            .unwrap()
+            .and_hms_opt(10, 11, 12)
+            .map(|btime| (btime, "the file creation time"));
+        let vars = extend_vars(
+            exif_vars("Model : X\n"),
+            None,
+            TimeDelta::seconds(50),
+            None,
+            btime,
+            &mut warnings,
+        );
+        assert_eq!(vars["t"], "101112");
+        assert_eq!(vars["date_confidence"], "low");
+        assert_eq!(
+            warnings,
+            vec![Warning::FallbackDate("the file creation time")]
+        );
+
+        let vars = extend_vars(
+            exif_vars(DUMP),
+            None,
+            TimeDelta::zero(),
+            None,
+            btime,
+            &mut warnings,
+        );
+        assert_eq!(vars["date_confidence"], "high");
+
+        let path = std::env::temp_dir().join(format!("exif-rename-btime-{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+        let created = file_created(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        // not every platform and filesystem records it
+        if let Some(created) = created {
+            let age = Local::now().naive_local() - created;
+            assert!(age.num_seconds().abs() < 60, "{}", age);
+        }
+    }
+
+    #[test]
+    fn test_localized_dump() {
+        let mut warnings = Vec::new();
+        let mut dump = "ExifTool Versionsnummer : 12.76\nDateiname : IMG_1234.JPG\n".to_string();
+        for i in 0..10 {
+            dump.push_str(&format!("Herstellernotiz {} : {}\n", i, i));
+        }
+        extend_vars(
+            exif_vars(&dump),
+            None,
+            TimeDelta::zero(),
+            None,
+            None,
+            &mut warnings,
+        );
+        assert!(warnings.contains(&Warning::UnknownTags));
+
+        let mut warnings = Vec::new();
+        extend_vars(
+            exif_vars(DUMP),
+            None,
+            TimeDelta::zero(),
+            None,
+            None,
+            &mut warnings,
+        );
+        assert!(!warnings.contains(&Warning::UnknownTags));
+    }
+
+    #[test]
+    fn test_best_date() {
+        let date = |dump: &str| {
+            let (dt, confidence) = best_date(&exif_vars(dump), None)?;
+            Some((dt.to_string(), confidence))
+        };
+        let expect = |dt: &str, confidence| Some((dt.to_string(), confidence));
+
+        assert_eq!(date(DUMP), expect("2023-09-08 10:11:12", "high"));
+        assert_eq!(
+            date("Date/Time Original : 2023:09:08 10:11:12+09:00\n"),
+            expect("2023-09-08 10:11:12", "high")
+        );
+        assert_eq!(
+            date("Create Date : 2023:09:08 10:11\n"),
+            expect("2023-09-08 10:11:00", "medium")
+        );
+        let gps = "GPS Date Stamp : 2023:09:08\nGPS Time Stamp : 01:11:12.5\n";
+        assert_eq!(date(gps), expect("2023-09-08 01:11:12", "low"));
+        assert_eq!(
+            date(&format!("{}Offset Time : +09:00\n", gps)),
+            expect("2023-09-08 10:11:12", "medium")
+        );
+        assert_eq!(
+            date("GPS Date/Time : 2023:09:08 01:11:12Z\n"),
+            expect("2023-09-08 01:11:12", "low")
+        );
+        assert_eq!(
+            date("GPS Date Stamp : 2023:09:08\n"),
+            expect("2023-09-08 00:00:00", "low")
+        );
+        assert_eq!(date("Create Date : 0000:00:00 00:00:00\n"), None);
+
+        assert_eq!(extend(DUMP)["date_confidence"], "high");
+    }
+
+    #[test]
+    fn test_parse_coordinate() {
+        let lat = parse_coordinate("37 deg 33' 59.40\" N", None).unwrap();
+        assert!((lat - 37.5665).abs() < 1e-4);
+        let lon = parse_coordinate("126 deg 58' 40.80\" W", None).unwrap();
+        assert!((lon + 126.978).abs() < 1e-3);
+        let south = "South".to_string();
+        assert_eq!(parse_coordinate("33.5", Some(&south)), Some(-33.5));
+    }
+
+    #[test]
+    fn test_gps_position() {
+        let (lat, lon) = parse_position("37 deg 33' 59.40\" S, 126 deg 58' 40.80\" E").unwrap();
+        assert!((lat + 37.5665).abs() < 1e-4);
+        assert!((lon - 126.978).abs() < 1e-3);
+        assert_eq!(
+            parse_position("37.5665 -126.978"),
+            Some((37.5665, -126.978))
+        );
+        assert_eq!(parse_position("37.5665"), None);
+
+        let vars = extend("GPS Position : 37 deg 33' 59.40\" N, 126 deg 58' 40.80\" E\n");
+        assert_eq!(
+            (vars["lat"].as_str(), vars["lon"].as_str()),
+            ("37.5665", "126.9780")
+        );
+        assert_eq!(extend(DUMP)["lat"], "37.5665");
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_vars`.
+    #[test]
+    #[ignore]
+    fn bench_vars() {
+        // exiftool dumps of camera files typically have a few hundred tags
+        let mut dump = DUMP.to_string();
+        for i in 0..300 {
+            dump.push_str(&format!("Maker Note Tag {} : value number {}\n", i, i));
+        }
+
+        let runs = 10_000;
+        let start = std::time::Instant::now();
+        for _ in 0..runs {
+            let vars = extend(&dump);
+            assert!(vars.contains_key("t"));
+        }
+        let elapsed = start.elapsed();
+        println!("{} runs in {:?}, {:?}/file", runs, elapsed, elapsed / runs);
+    }
+}
@@ -0,0 +1,540 @@
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use chrono::{Local, NaiveDateTime};
+use clap::Parser;
+
+use exif_rename::progress::{Cancel, Stage};
+use exif_rename::renamer::Event;
+use exif_rename::{
+    bag::Bag, config, dates, empty, glob, journal, matrix, origin, skiplist, source, stats,
+    summary, walk, Args, Command, Input, Outcome, PatternCommand, Renamer, Status,
+};
+
+/// Exit status of a run stopped with Ctrl-C, as shells report it.
+const EXIT_CANCELLED: i32 = 130;
+
+fn main() {
+    let args = Args::parse();
+
+    let settings = config::load(args.config.as_deref())
+        .and_then(|config| config.resolve(args.profile.as_deref()))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+    if let Some(Command::Undo {
+        file,
+        run,
+        filter,
+        name,
+    }) = &args.command
+    {
+        let Some(journal) = file.as_ref().or(args.journal.as_ref()) else {
+            eprintln!("no journal to undo, give one or use --journal");
+            process::exit(1);
+        };
+        // tags come from the sources a rename run reads; the journal of the
+        // run to undo isn't opened for renaming
+        let reader = Args {
+            journal: None,
+            ..args.clone()
+        };
+        let reader = Renamer::new(reader, settings).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        let select = |entry: &journal::Entry| {
+            let target = entry.target.to_string_lossy();
+            let named = name.is_empty() || name.iter().any(|g| glob::matches_name(g, &target));
+            // tags are read only when needed
+            named
+                && (filter.is_empty()
+                    || reader
+                        .read_renamed(&entry.source.to_string_lossy(), &target)
+                        .is_ok_and(|tags| filter.iter().all(|f| f.matches(&tags))))
+        };
+        undo(journal, run.as_deref(), select, args.dry_run);
+        return;
+    }
+    if let Some(Command::RestoreNames { files }) = &args.command {
+        restore_names(files, args.dry_run);
+        return;
+    }
+    if let Some(Command::Stats { files }) = &args.command {
+        stats(&args, files);
+        return;
+    }
+    let mut renamer = Renamer::new(args, settings).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let args = renamer.args().clone();
+
+    if let Some(Command::Pattern {
+        command: PatternCommand::Preview { .. },
+    }) = &args.command
+    {
+        let plans = renamer.plan(matrix::inputs());
+        print!("{}", matrix::report(plans));
+        return;
+    }
+
+    if let Some(Command::ExportBag { bag, files }) = &args.command {
+        export_bag(&renamer, bag, files);
+        return;
+    }
+
+    let mut failed = 0;
+    let mut files = expand(&args, &args.files, &mut failed);
+    // a dump of several files names the files to rename if none are given
+    let listed = renamer
+        .dump()
+        .filter(|dump| args.files.is_empty() && !dump.is_single());
+    if let Some(dump) = listed {
+        files = dump.files().map(str::to_string).collect();
+    }
+    let given = !args.files.is_empty() || listed.is_some();
+    if renamer.dump().is_some_and(|dump| dump.is_single()) && files.len() > 1 {
+        eprintln!("--exif describes a single file, omit it to read the metadata of each file");
+        process::exit(1);
+    }
+    files.retain(|file| renamer.selects(file));
+    if let Some(skiplist) = &args.skiplist {
+        let skips = skiplist::load(skiplist).unwrap_or_else(|e| {
+            eprintln!("{}: {}", skiplist.display(), e);
+            process::exit(1);
+        });
+        files.retain(|file| !skips.contains(file));
+    }
+    if let (true, Some(journal)) = (args.since_last_run, &args.journal) {
+        let entries = journal::load(journal).unwrap_or_else(|e| {
+            eprintln!("{}: {}", journal.display(), e);
+            process::exit(1);
+        });
+        let processed = journal::Processed::new(&entries);
+        files.retain(|file| !processed.contains(Path::new(file)));
+    }
+    let total = files.len() + failed;
+
+    let mut inputs = Vec::new();
+    if !given {
+        let exif = source::read(renamer.dump(), &[], None, None).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        inputs.push(Input { file: None, exif });
+    }
+    let cancel = Cancel::new();
+    let handler = cancel.clone();
+    // a second Ctrl-C doesn't wait for the file being renamed
+    let installed = ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            process::exit(EXIT_CANCELLED);
+        }
+        handler.cancel();
+    });
+    if installed.is_ok() {
+        renamer.set_cancel(cancel.clone());
+    }
+    // the width of the progress line on the terminal: `\r` only moves back,
+    // so a shorter line is padded with spaces over the rest of the last one
+    let shown = Arc::new(AtomicUsize::new(0));
+    if args.progress {
+        let terminal = std::io::stderr().is_terminal();
+        let shown = shown.clone();
+        renamer.set_progress(move |progress| match progress.stage {
+            Stage::Read if terminal => {
+                let line = progress.to_string();
+                let width = line.chars().count();
+                let last = shown.swap(width, Ordering::Relaxed);
+                eprint!("\r{}{}", line, " ".repeat(last.saturating_sub(width)));
+            }
+            Stage::Read => eprintln!("{}", progress),
+            Stage::Execute => {}
+        });
+    }
+    for (file, exif) in renamer.read(files) {
+        match exif {
+            Ok(exif) => inputs.push(Input {
+                file: Some(file),
+                exif,
+            }),
+            Err(e) => {
+                eprintln!("{}", e);
+                update_skiplist(&args, &file, &e);
+                failed += 1;
+            }
+        }
+    }
+    if args.progress && std::io::stderr().is_terminal() {
+        eprint!("\r{}\r", " ".repeat(shown.load(Ordering::Relaxed)));
+    }
+    if cancel.is_cancelled() {
+        eprintln!("cancelled, nothing was renamed");
+        process::exit(EXIT_CANCELLED);
+    }
+
+    let files: Vec<Option<String>> = inputs.iter().map(|input| input.file.clone()).collect();
+    let plans = renamer.plan(inputs);
+    let filtered = Outcome::Skip("filter".to_string());
+    let empty = given
+        && plans
+            .iter()
+            .all(|plan| plan.as_ref().is_ok_and(|plan| plan.outcome == filtered));
+    let mut summary = summary::Summary::default();
+    for (file, plan) in files.iter().zip(plans) {
+        if renamer.aborted() {
+            eprintln!("stopped, the remaining files are left as they are");
+            break;
+        }
+        let plan = match plan {
+            Ok(plan) => {
+                summary.add_plan(&plan);
+                plan
+            }
+            Err(e) => {
+                match file {
+                    Some(file) => eprintln!("{}: {}", file, e),
+                    None => eprintln!("{}", e),
+                }
+                if let Some(file) = file {
+                    update_skiplist(&args, file, &e.message);
+                }
+                failed += 1;
+                continue;
+            }
+        };
+        let name = file
+            .as_deref()
+            .or(plan.vars.get("FileName").map(String::as_str))
+            .unwrap_or_default()
+            .to_string();
+        for warning in &plan.warnings {
+            eprintln!("{}: warning: {}", name, warning);
+        }
+        if args.date_report {
+            print_date_report(&name, plan.date, &plan.candidates);
+        }
+        let executed = renamer.execute(file.as_deref(), plan);
+        for event in &executed.events {
+            print_event(&name, event);
+        }
+        match executed.result {
+            Ok(status) => summary.add(&status, 1),
+            Err(e) => {
+                eprintln!("{}: {}", file.as_deref().unwrap_or_default(), e);
+                failed += 1;
+            }
+        }
+    }
+    if args.summary {
+        summary.add(&Status::Failed, failed);
+        eprint!("{}", summary);
+    }
+    let preflight = args.dry_run.then(|| renamer.preflight());
+    if let Some(report) = &preflight {
+        eprint!("{}", report);
+    }
+
+    if cancel.is_cancelled() {
+        process::exit(EXIT_CANCELLED);
+    }
+    if failed > 0 {
+        if total > 1 {
+            eprintln!("{} of {} files failed", failed, total);
+        }
+        process::exit(1);
+    }
+    if preflight.is_some_and(|report| !report.is_ok()) {
+        eprintln!("the destinations can't take the files");
+        process::exit(1);
+    }
+    if empty {
+        match args.on_empty {
+            empty::OnEmpty::Ignore => {}
+            empty::OnEmpty::Status => {
+                eprintln!("nothing to do, no files to rename");
+                process::exit(empty::EXIT_NOTHING_TO_DO);
+            }
+            empty::OnEmpty::Error => {
+                eprintln!("no files to rename");
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Prints what executing the plan of the file `name` did: new names on
+/// stdout, everything else on stderr.
+fn print_event(name: &str, event: &Event) {
+    match event {
+        Event::Renamed(path) => println!("{} -> {}", name, path.display()),
+        Event::Copied(path) | Event::Converted(path) => {
+            println!("{} => {}", name, path.display())
+        }
+        Event::Named(path) => println!("{}", path.display()),
+        Event::Skipped(message) => eprintln!("{}: {}", name, message),
+        Event::Warning(message) => eprintln!("{}: warning: {}", name, message),
+    }
+}
+
+/// Prints the candidate dates of a file and how far each is from the
+/// chosen `date`, for `--date-report`.
+fn print_date_report(name: &str, date: Option<NaiveDateTime>, candidates: &[dates::Candidate]) {
+    match date {
+        Some(date) => eprintln!(
+            "{}: capture date {}",
+            name,
+            date.format("%Y-%m-%d %H:%M:%S")
+        ),
+        None => eprintln!("{}: no capture date", name),
+    }
+    for candidate in candidates {
+        let format = if candidate.date_only {
+            "%Y-%m-%d"
+        } else {
+            "%Y-%m-%d %H:%M:%S"
+        };
+        let delta = date.map(|date| dates::Delta(candidate.delta(date)).to_string());
+        eprintln!(
+            "  {:<18} {:<19} {}",
+            candidate.source,
+            candidate.date.format(format).to_string(),
+            delta.unwrap_or_default()
+        );
+    }
+}
+
+/// Appends `file`, which failed with `reason`, to the skip list with
+/// `--update-skiplist`, exiting with an error if it can't be written.
+fn update_skiplist(args: &Args, file: &str, reason: &str) {
+    let (Some(skiplist), true, false) = (&args.skiplist, args.update_skiplist, args.dry_run) else {
+        return;
+    };
+    if let Err(e) = skiplist::append(skiplist, file, reason) {
+        eprintln!("{}: {}", skiplist.display(), e);
+        process::exit(1);
+    }
+    eprintln!("{}: added to {}", file, skiplist.display());
+}
+
+/// Returns the files `patterns` match, walking directories with
+/// `--recursive`. Patterns matching nothing are reported and counted in
+/// `failed`.
+fn expand(args: &Args, patterns: &[String], failed: &mut usize) -> Vec<String> {
+    let mut files = Vec::new();
+    for arg in patterns {
+        let matched = glob::expand(arg);
+        if matched.is_empty() {
+            eprintln!("{}: no files match", arg);
+            *failed += 1;
+        }
+        for path in matched {
+            if !Path::new(&path).is_dir() {
+                files.push(path);
+            } else if args.recursive {
+                let (found, errors) = walk::files(Path::new(&path), args.max_depth);
+                for (dir, e) in errors {
+                    eprintln!("{}: {}", dir.display(), e);
+                    *failed += 1;
+                }
+                files.extend(found);
+            } else if !glob::is_glob(arg) {
+                // skip directories matched by a glob, so "dir/*" takes the files in it
+                eprintln!("{}: is a directory, use --recursive", path);
+                *failed += 1;
+            }
+        }
+    }
+    files
+}
+
+/// Copies the files `patterns` match into a new bag at `bag`, named as
+/// they would be renamed, exiting with an error if any file failed.
+fn export_bag(renamer: &Renamer, bag: &Path, patterns: &[String]) {
+    let args = renamer.args();
+    let mut failed = 0;
+    let mut files = expand(args, patterns, &mut failed);
+    if renamer.dump().is_some_and(|dump| dump.is_single()) && files.len() > 1 {
+        eprintln!("--exif describes a single file, omit it to read the metadata of each file");
+        process::exit(1);
+    }
+    files.retain(|file| renamer.selects(file));
+    let total = files.len() + failed;
+
+    let mut inputs = Vec::new();
+    for file in files {
+        let exiftool = source::exiftool_program(args);
+        let sidecars = source::sidecar_exts(args);
+        match source::read(renamer.dump(), &sidecars, exiftool, Some(&file)) {
+            Ok(exif) => inputs.push(Input {
+                file: Some(file),
+                exif,
+            }),
+            Err(e) => {
+                eprintln!("{}", e);
+                failed += 1;
+            }
+        }
+    }
+    let files: Vec<String> = inputs.iter().filter_map(|i| i.file.clone()).collect();
+    let plans = renamer.plan(inputs);
+
+    let now = Local::now();
+    let mut out = if args.dry_run {
+        None
+    } else {
+        Some(Bag::create(bag).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        }))
+    };
+    for (file, plan) in files.iter().zip(plans) {
+        let plan = match plan {
+            Ok(plan) => plan,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed += 1;
+                continue;
+            }
+        };
+        for warning in &plan.warnings {
+            eprintln!("{}: warning: {}", file, warning);
+        }
+        let name = match plan.outcome {
+            Outcome::Rename(name) | Outcome::CopyTo(_, name) => name,
+            Outcome::Quarantine(_) => {
+                eprintln!("{}: skipped, quarantined by a rule", file);
+                continue;
+            }
+            Outcome::Skip(reason) => {
+                eprintln!("{}: {}", file, Status::Skipped(reason));
+                continue;
+            }
+        };
+        let Some(out) = &mut out else {
+            println!("{} => {}", file, bag.join("data").join(&name).display());
+            continue;
+        };
+        match out.add(Path::new(file), &name) {
+            Ok(path) => println!("{} => {}", file, path.display()),
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed += 1;
+            }
+        }
+    }
+    if let Some(out) = out {
+        let mut info: Vec<_> = renamer
+            .patterns()
+            .iter()
+            .map(|pattern| ("Exif-Rename-Pattern", pattern.to_string()))
+            .collect();
+        info.push(("Exif-Rename-Run", journal::run_id(now.naive_local())));
+        let date = now.format("%Y-%m-%d").to_string();
+        if let Err(e) = out.finish(&date, &info) {
+            eprintln!("{}: failed to write bag: {}", bag.display(), e);
+            process::exit(1);
+        }
+    }
+    if failed > 0 {
+        if total > 1 {
+            eprintln!("{} of {} files failed", failed, total);
+        }
+        process::exit(1);
+    }
+}
+
+/// Prints the stats of the files `patterns` match, walking directories.
+fn stats(args: &Args, patterns: &[String]) {
+    let args = Args {
+        recursive: true,
+        ..args.clone()
+    };
+    let mut failed = 0;
+    let files = expand(&args, patterns, &mut failed);
+    let dump = args
+        .exif
+        .as_deref()
+        .map(|path| source::Dump::load(path, args.exif_format))
+        .transpose()
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+    let sidecars = source::sidecar_exts(&args);
+    let mut stats = stats::Stats::default();
+    for file in &files {
+        let exiftool = source::exiftool_program(&args);
+        let exif = source::read(dump.as_ref(), &sidecars, exiftool, Some(file));
+        stats.add(file, exif.as_ref().ok(), args.date_source.as_ref());
+    }
+    print!("{}", stats);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// Reverses a run recorded in `journal`, the last one unless `run` is
+/// given, exiting with an error if any file couldn't be restored.
+fn undo(
+    journal: &Path,
+    run: Option<&str>,
+    select: impl Fn(&journal::Entry) -> bool,
+    dry_run: bool,
+) {
+    let now = Local::now().naive_local();
+    let results = journal::Journal::open(journal, now)
+        .and_then(|mut journal| journal.undo(run, select, dry_run));
+    let results = results.unwrap_or_else(|e| {
+        eprintln!("{}: {}", journal.display(), e);
+        process::exit(1);
+    });
+    if results.is_empty() {
+        eprintln!("{}: nothing to undo", journal.display());
+        return;
+    }
+    let total = results.len();
+    let mut failed = 0;
+    for (entry, result) in results {
+        let target = entry.target.display();
+        match (result, entry.action) {
+            (Ok(()), journal::Action::Rename) => {
+                println!("{} -> {}", target, entry.source.display())
+            }
+            (Ok(()), journal::Action::Copy) => println!("{}: removed copy", target),
+            (Err(e), _) => {
+                eprintln!("{}: {}", target, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        if total > 1 {
+            eprintln!("{} of {} files failed", failed, total);
+        }
+        process::exit(1);
+    }
+}
+
+/// Renames files back to the names kept in their extended attributes.
+fn restore_names(files: &[String], dry_run: bool) {
+    let mut failed = 0;
+    for file in files.iter().flat_map(|arg| glob::expand(arg)) {
+        match origin::restore(Path::new(&file), dry_run) {
+            Ok(Some(original)) => println!("{} -> {}", file, original.display()),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        process::exit(1);
+    }
+}
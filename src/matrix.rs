@@ -0,0 +1,168 @@
+//! `pattern preview --matrix`: renders the pattern against built-in sample
+//! files covering the cases patterns tend to get wrong, so a new pattern
+//! can be checked before it touches real files.
+
+use std::fmt::Write;
+
+use crate::dump::exif_vars;
+use crate::pattern::Error;
+use crate::plan::{Input, Outcome, Plan};
+
+/// Sample files as exiftool dumps, with what each one covers. The burst
+/// shots share a second, so `{burst}` and `{u}` show up.
+const SAMPLES: &[(&str, &str)] = &[
+    (
+        "typical",
+        "File Name : IMG_1234.JPG\n\
+         Make : Canon\n\
+         Model : Canon EOS R6\n\
+         Create Date : 2023:09:08 10:11:12\n\
+         GPS Latitude : 37 deg 33' 59.40\" N\n\
+         GPS Latitude Ref : North\n\
+         GPS Longitude : 126 deg 58' 40.80\" E\n\
+         GPS Longitude Ref : East\n",
+    ),
+    (
+        "no GPS",
+        "File Name : IMG_1235.JPG\n\
+         Make : Canon\n\
+         Model : Canon EOS R6\n\
+         Create Date : 2023:09:08 10:20:00\n",
+    ),
+    (
+        "no Model",
+        "File Name : scan_0001.jpg\n\
+         Create Date : 2023:09:08 10:30:00\n",
+    ),
+    (
+        "no date",
+        "File Name : download.jpg\n\
+         Model : Canon EOS R6\n",
+    ),
+    (
+        "midnight",
+        "File Name : IMG_1236.JPG\n\
+         Model : Canon EOS R6\n\
+         Create Date : 2023:09:09 00:00:00\n",
+    ),
+    (
+        "Dec 31",
+        "File Name : IMG_1237.JPG\n\
+         Model : Canon EOS R6\n\
+         Create Date : 2023:12:31 23:59:59\n",
+    ),
+    (
+        "burst",
+        "File Name : IMG_1238.JPG\n\
+         Model : Canon EOS R6\n\
+         Create Date : 2023:09:08 14:15:16\n\
+         Sub Sec Time Original : 10\n",
+    ),
+    (
+        "burst",
+        "File Name : IMG_1239.JPG\n\
+         Model : Canon EOS R6\n\
+         Create Date : 2023:09:08 14:15:16\n\
+         Sub Sec Time Original : 50\n",
+    ),
+    (
+        "HEIC",
+        "File Name : IMG_1240.HEIC\n\
+         Make : Apple\n\
+         Model : iPhone 14 Pro\n\
+         Create Date : 2023:09:08 18:00:01\n\
+         Offset Time Original : +09:00\n",
+    ),
+    (
+        "video",
+        "File Name : MVI_1241.MP4\n\
+         Model : Canon EOS R6\n\
+         Create Date : 2023:09:08 20:00:00\n\
+         Duration : 0:01:05\n\
+         Image Width : 1920\n\
+         Image Height : 1080\n",
+    ),
+];
+
+/// Returns the sample files, in the order of the report.
+pub fn inputs() -> Vec<Input> {
+    SAMPLES
+        .iter()
+        .map(|(_, dump)| Input {
+            file: None,
+            exif: exif_vars(dump),
+        })
+        .collect()
+}
+
+/// Formats the plans of the samples, one line per sample and warning.
+pub fn report(plans: Vec<Result<Plan, Error>>) -> String {
+    let width = SAMPLES
+        .iter()
+        .map(|(case, _)| case.len())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for ((case, dump), plan) in SAMPLES.iter().zip(plans) {
+        let vars = exif_vars(dump);
+        let name = vars.get("FileName").map_or("", String::as_str);
+        let result = match plan {
+            Ok(plan) => {
+                let result = match plan.outcome {
+                    Outcome::Rename(new_name) => format!("-> {}", new_name),
+                    Outcome::CopyTo(dir, new_name) => {
+                        format!("=> {}", dir.join(new_name).display())
+                    }
+                    Outcome::Quarantine(dir) => format!("-> {}", dir.join(name).display()),
+                    Outcome::Skip(reason) => format!("skipped by {}", reason),
+                };
+                let warnings = plan.warnings.iter().map(|w| format!("warning: {}", w));
+                std::iter::once(result).chain(warnings).collect()
+            }
+            Err(e) => vec![format!("error: {}", e.message)],
+        };
+        for (i, line) in result.iter().enumerate() {
+            let (case, name) = if i == 0 { (*case, name) } else { ("", "") };
+            writeln!(out, "{:width$}  {:14} {}", case, name, line).unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use clap::Parser;
+
+    use super::*;
+    use crate::counters::Counters;
+    use crate::pattern::Pattern;
+    use crate::{plan, Args};
+
+    #[test]
+    fn test_report() {
+        let args = Args::parse_from(["exif-rename", "pattern", "preview", "--matrix"]);
+        let pattern = [Pattern::parse("{Y}{m}{D}_{t}{u}.{e}").unwrap()];
+        let plans = plan::plan(
+            &args,
+            &pattern,
+            &[],
+            inputs(),
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let report = report(plans);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), SAMPLES.len());
+        assert_eq!(lines[0], "typical   IMG_1234.JPG   -> 20230908_101112.JPG");
+        assert_eq!(
+            lines[3],
+            "no date   download.jpg   error: unknown variable 'Y'"
+        );
+        assert_eq!(
+            lines[7],
+            "burst     IMG_1239.JPG   -> 20230908_141516_1.JPG"
+        );
+        assert_eq!(lines[8], "HEIC      IMG_1240.HEIC  -> 20230908_180001.HEIC");
+    }
+}
@@ -0,0 +1,139 @@
+//! Filename-safe variables derived from tags whose values carry units or
+//! fractions, e.g. `FocalLength : 24.0 mm` becomes `{focal}` = `24mm`.
+//!
+//! Each normalizer accepts both the printable form and the numeric form
+//! written by `exiftool -n`, so a pattern gives the same name either way.
+
+use crate::Vars;
+
+type Normalizer = fn(&str) -> Option<String>;
+
+/// Variable, tags it is derived from in order of preference, normalizer.
+const NORMALIZED: &[(&str, &[&str], Normalizer)] = &[
+    ("exposure", &["ExposureTime"], exposure_time),
+    ("aperture", &["FNumber", "Aperture"], aperture),
+    ("focal", &["FocalLength"], focal_length),
+    ("focal35", &["FocalLengthIn35mmFormat"], focal_length),
+    ("ev", &["ExposureCompensation"], exposure_compensation),
+];
+
+/// Inserts every normalized variable that can be derived from `exif`.
+pub fn insert(exif: &Vars, vars: &mut Vars) {
+    for (var, tags, normalize) in NORMALIZED {
+        let value = tags
+            .iter()
+            .find_map(|tag| exif.get(*tag).and_then(|value| normalize(value)));
+        if let Some(value) = value {
+            vars.insert(var.to_string(), value);
+        }
+    }
+}
+
+/// Parses the leading number of `value`, which may be a fraction (`1/250`,
+/// `+2/3`) and may be followed by a unit (`24.0 mm`).
+fn number(value: &str) -> Option<f64> {
+    let value = value.split_whitespace().next()?;
+    let value = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let n = match value.split_once('/') {
+        Some((n, d)) => n.parse::<f64>().ok()? / d.parse::<f64>().ok()?,
+        None => value.parse::<f64>().ok()?,
+    };
+    n.is_finite().then_some(n)
+}
+
+/// Formats `n` with at most one decimal, dropping `.0`.
+fn decimal(n: f64) -> String {
+    let s = format!("{:.1}", n);
+    s.strip_suffix(".0").unwrap_or(&s).to_string()
+}
+
+/// `1/250` or `0.004` -> `1-250`, `2.5` -> `2.5`. Uses `-` in place of `/`
+/// so it can be used in filenames.
+fn exposure_time(value: &str) -> Option<String> {
+    let secs = number(value).filter(|secs| *secs > 0.0)?;
+    // same cut-off as exiftool's PrintExposureTime
+    if secs < 0.25001 {
+        Some(format!("1-{}", (0.5 + 1.0 / secs) as u64))
+    } else {
+        Some(decimal(secs))
+    }
+}
+
+/// `2.8` -> `f2.8`, `4.0` -> `f4`.
+fn aperture(value: &str) -> Option<String> {
+    let f = number(value).filter(|f| *f > 0.0)?;
+    Some(format!("f{}", decimal(f)))
+}
+
+/// `24.0 mm` or `24` -> `24mm`. Printable values of compact cameras such as
+/// `4.2 mm (35 mm equivalent: 26.0 mm)` keep the actual focal length.
+fn focal_length(value: &str) -> Option<String> {
+    let mm = number(value).filter(|mm| *mm > 0.0)?;
+    Some(format!("{}mm", decimal(mm)))
+}
+
+/// `+2/3` or `0.6666667` -> `+0.7`, `-1` -> `-1`, `0` -> `0`.
+fn exposure_compensation(value: &str) -> Option<String> {
+    let ev = number(value)?;
+    let s = decimal(ev);
+    if s == "0" || s == "-0" {
+        Some("0".to_string())
+    } else if ev > 0.0 {
+        Some(format!("+{}", s))
+    } else {
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposure_time() {
+        assert_eq!(exposure_time("1/250").as_deref(), Some("1-250"));
+        assert_eq!(exposure_time("0.004").as_deref(), Some("1-250"));
+        assert_eq!(exposure_time("0.0025").as_deref(), Some("1-400"));
+        assert_eq!(exposure_time("1/3").as_deref(), Some("0.3"));
+        assert_eq!(exposure_time("0.3333333").as_deref(), Some("0.3"));
+        assert_eq!(exposure_time("2").as_deref(), Some("2"));
+        assert_eq!(exposure_time("2.5").as_deref(), Some("2.5"));
+        assert_eq!(exposure_time("0"), None);
+        assert_eq!(exposure_time("1/0"), None);
+        assert_eq!(exposure_time("Bulb"), None);
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(aperture("2.8").as_deref(), Some("f2.8"));
+        assert_eq!(aperture("4.0").as_deref(), Some("f4"));
+        assert_eq!(focal_length("24.0 mm").as_deref(), Some("24mm"));
+        assert_eq!(focal_length("24").as_deref(), Some("24mm"));
+        assert_eq!(focal_length("4.2mm").as_deref(), Some("4.2mm"));
+        assert_eq!(
+            focal_length("4.2 mm (35 mm equivalent: 26.0 mm)").as_deref(),
+            Some("4.2mm")
+        );
+        assert_eq!(focal_length("0.0 mm"), None);
+        assert_eq!(exposure_compensation("+2/3").as_deref(), Some("+0.7"));
+        assert_eq!(exposure_compensation("0.6666667").as_deref(), Some("+0.7"));
+        assert_eq!(exposure_compensation("-1").as_deref(), Some("-1"));
+        assert_eq!(exposure_compensation("-0.01").as_deref(), Some("0"));
+        assert_eq!(exposure_compensation("0").as_deref(), Some("0"));
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut exif = Vars::new();
+        exif.insert("Aperture".to_string(), "5.6".to_string());
+        exif.insert("FocalLength".to_string(), "n/a".to_string());
+        exif.insert("FocalLengthIn35mmFormat".to_string(), "26 mm".to_string());
+
+        let mut vars = Vars::new();
+        insert(&exif, &mut vars);
+        assert_eq!(vars["aperture"], "f5.6");
+        assert_eq!(vars["focal35"], "26mm");
+        assert!(!vars.contains_key("focal"));
+        assert!(!vars.contains_key("exposure"));
+    }
+}
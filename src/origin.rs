@@ -0,0 +1,220 @@
+//! `--keep-origin`: the original name of each renamed file and the run that
+//! renamed it, kept in the `user.exif-rename.orig` extended attribute of the
+//! file itself, so provenance survives lost journals. `exif-rename
+//! restore-names` reads it back.
+//!
+//! The value is the run ID, a tab and the original file name. Extended
+//! attributes are supported on Linux and macOS; filesystems without them,
+//! such as FAT on memory cards, fail with an error.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the extended attribute.
+pub const ATTR: &str = "user.exif-rename.orig";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    /// ID of the run that renamed the file, the time it started.
+    pub run: String,
+    /// File name before the run.
+    pub name: String,
+}
+
+impl Origin {
+    fn encode(&self) -> String {
+        format!("{}\t{}", self.run, self.name)
+    }
+
+    fn decode(value: &[u8]) -> Option<Origin> {
+        let value = std::str::from_utf8(value).ok()?;
+        let (run, name) = value.split_once('\t')?;
+        Some(Origin {
+            run: run.to_string(),
+            name: name.to_string(),
+        })
+    }
+}
+
+/// Stores `origin` on `path`, replacing an earlier one.
+pub fn set(path: &Path, origin: &Origin) -> io::Result<()> {
+    sys::set(path, origin.encode().as_bytes())
+}
+
+/// Reads the origin stored on `path`, if any.
+pub fn get(path: &Path) -> io::Result<Option<Origin>> {
+    match sys::get(path)? {
+        Some(value) => Origin::decode(&value).map(Some).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("malformed {}", ATTR))
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Renames `path` back to its original name, in the directory it is in
+/// now, and drops the attribute. With `dry_run` nothing is touched.
+/// Returns the restored path, or `None` if the file has its original name.
+pub fn restore(path: &Path, dry_run: bool) -> Result<Option<PathBuf>, String> {
+    let origin = get(path)
+        .map_err(|e| format!("failed to read {}: {}", ATTR, e))?
+        .ok_or_else(|| "no original name recorded".to_string())?;
+    let original = path.with_file_name(&origin.name);
+    if original == path {
+        return Ok(None);
+    }
+    if original.exists() {
+        return Err(format!(
+            "{} exists, not restoring the name",
+            original.display()
+        ));
+    }
+    if dry_run {
+        return Ok(Some(original));
+    }
+    fs::rename(path, &original).map_err(|e| format!("failed to rename file: {}", e))?;
+    sys::remove(&original).map_err(|e| format!("failed to remove {}: {}", ATTR, e))?;
+    Ok(Some(original))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod sys {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::ATTR;
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    fn c_attr() -> CString {
+        CString::new(ATTR).unwrap()
+    }
+
+    pub fn set(path: &Path, value: &[u8]) -> io::Result<()> {
+        let (path, attr) = (c_path(path)?, c_attr());
+        let value_ptr = value.as_ptr().cast();
+        // SAFETY: the strings are nul-terminated and value is valid for its length
+        #[cfg(target_os = "linux")]
+        let result =
+            unsafe { libc::setxattr(path.as_ptr(), attr.as_ptr(), value_ptr, value.len(), 0) };
+        #[cfg(target_os = "macos")]
+        let result =
+            unsafe { libc::setxattr(path.as_ptr(), attr.as_ptr(), value_ptr, value.len(), 0, 0) };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn get(path: &Path) -> io::Result<Option<Vec<u8>>> {
+        let (path, attr) = (c_path(path)?, c_attr());
+        // names are at most 255 bytes, with room for a long run ID
+        let mut value = vec![0u8; 4096];
+        let value_ptr = value.as_mut_ptr().cast();
+        // SAFETY: the strings are nul-terminated and value is valid for its length
+        #[cfg(target_os = "linux")]
+        let len = unsafe { libc::getxattr(path.as_ptr(), attr.as_ptr(), value_ptr, value.len()) };
+        #[cfg(target_os = "macos")]
+        let len =
+            unsafe { libc::getxattr(path.as_ptr(), attr.as_ptr(), value_ptr, value.len(), 0, 0) };
+        if len < 0 {
+            let e = io::Error::last_os_error();
+            #[cfg(target_os = "linux")]
+            let missing = e.raw_os_error() == Some(libc::ENODATA);
+            #[cfg(target_os = "macos")]
+            let missing = e.raw_os_error() == Some(libc::ENOATTR);
+            return if missing { Ok(None) } else { Err(e) };
+        }
+        value.truncate(len as usize);
+        Ok(Some(value))
+    }
+
+    pub fn remove(path: &Path) -> io::Result<()> {
+        let (path, attr) = (c_path(path)?, c_attr());
+        // SAFETY: the strings are nul-terminated
+        #[cfg(target_os = "linux")]
+        let result = unsafe { libc::removexattr(path.as_ptr(), attr.as_ptr()) };
+        #[cfg(target_os = "macos")]
+        let result = unsafe { libc::removexattr(path.as_ptr(), attr.as_ptr(), 0) };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod sys {
+    use std::io;
+    use std::path::Path;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "extended attributes are not supported on this platform",
+        )
+    }
+
+    pub fn set(_path: &Path, _value: &[u8]) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn get(_path: &Path) -> io::Result<Option<Vec<u8>>> {
+        Err(unsupported())
+    }
+
+    pub fn remove(_path: &Path) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let origin = Origin {
+            run: "2023-09-08T10:11:12.000".to_string(),
+            name: "IMG\t1234.JPG".to_string(),
+        };
+        assert_eq!(Origin::decode(origin.encode().as_bytes()), Some(origin));
+        assert_eq!(Origin::decode(b"IMG_1234.JPG"), None);
+    }
+
+    #[test]
+    fn test_restore() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-origin-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("20230908_101112.JPG");
+        fs::write(&path, "").unwrap();
+        let origin = Origin {
+            run: "2023-09-08T10:11:12.000".to_string(),
+            name: "IMG_1234.JPG".to_string(),
+        };
+        if let Err(e) = set(&path, &origin) {
+            // e.g. tmpfs before Linux 6.6 has no user attributes
+            eprintln!("skipped, no extended attributes: {}", e);
+            fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+        assert_eq!(get(&path).unwrap(), Some(origin));
+
+        let original = dir.join("IMG_1234.JPG");
+        assert_eq!(restore(&path, true), Ok(Some(original.clone())));
+        assert!(path.exists());
+        assert_eq!(restore(&path, false), Ok(Some(original.clone())));
+        assert!(original.exists() && !path.exists());
+        assert_eq!(get(&original).unwrap(), None);
+        assert_eq!(
+            restore(&original, false),
+            Err("no original name recorded".to_string())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
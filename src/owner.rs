@@ -0,0 +1,113 @@
+//! Ownership in shared archives (Unix only): `--require-owner` refuses to
+//! touch files of other users, `--chown-to` hands renamed files and created
+//! directories to a common owner.
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// User and group to give files to, e.g. `photos:family`, `1000` or `:family`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl FromStr for Owner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, group) = s.split_once(':').unwrap_or((s, ""));
+        let owner = Owner {
+            uid: Some(user).filter(|u| !u.is_empty()).map(uid).transpose()?,
+            gid: Some(group).filter(|g| !g.is_empty()).map(gid).transpose()?,
+        };
+        if owner.uid.is_none() && owner.gid.is_none() {
+            return Err(format!("expected USER[:GROUP], got '{}'", s));
+        }
+        Ok(owner)
+    }
+}
+
+fn uid(user: &str) -> Result<u32, String> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    let name = CString::new(user).map_err(|_| format!("unknown user '{}'", user))?;
+    // SAFETY: getpwnam returns null or a pointer to a static entry, read
+    // before any other call can overwrite it
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("unknown user '{}'", user));
+    }
+    Ok(unsafe { (*entry).pw_uid })
+}
+
+fn gid(group: &str) -> Result<u32, String> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let name = CString::new(group).map_err(|_| format!("unknown group '{}'", group))?;
+    // SAFETY: as for getpwnam
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("unknown group '{}'", group));
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+/// Returns the name of the user with `uid`, or the number if it has none.
+pub fn user_name(uid: u32) -> String {
+    // SAFETY: as for getpwnam
+    let entry = unsafe { libc::getpwuid(uid) };
+    if entry.is_null() {
+        return uid.to_string();
+    }
+    unsafe { CStr::from_ptr((*entry).pw_name) }
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Returns the owner of `path` if it isn't the user running the tool.
+pub fn foreign_owner(path: &Path) -> io::Result<Option<u32>> {
+    let uid = fs::metadata(path)?.uid();
+    // SAFETY: geteuid can't fail
+    let me = unsafe { libc::geteuid() };
+    Ok((uid != me).then_some(uid))
+}
+
+pub fn chown(path: &Path, owner: &Owner) -> io::Result<()> {
+    std::os::unix::fs::chown(path, owner.uid, owner.gid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let root: Owner = "root".parse().unwrap();
+        assert_eq!((root.uid, root.gid), (Some(0), None));
+        let owner: Owner = "1000:100".parse().unwrap();
+        assert_eq!((owner.uid, owner.gid), (Some(1000), Some(100)));
+        let owner: Owner = ":0".parse().unwrap();
+        assert_eq!((owner.uid, owner.gid), (None, Some(0)));
+        assert!(":".parse::<Owner>().is_err());
+        assert_eq!(
+            "no-such-user-here".parse::<Owner>().unwrap_err(),
+            "unknown user 'no-such-user-here'"
+        );
+        assert_eq!(user_name(0), "root");
+    }
+
+    #[test]
+    fn test_foreign_owner() {
+        let path = std::env::temp_dir().join(format!("exif-rename-owner-{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+        assert_eq!(foreign_owner(&path).unwrap(), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
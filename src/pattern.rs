@@ -0,0 +1,1102 @@
+//! The pattern language used for new filenames.
+//!
+//! ```text
+//! pattern     = { literal | escape | token }
+//! literal     = any character except '{' and '}'
+//! escape      = "{{" | "}}" | "\" special
+//! special     = '{' | '}' | '\' | ':' | '|' | '?' | '!' | '(' | ')'
+//! token       = "{" ( variable | conditional | comparison ) "}"
+//! variable    = name { ":" filter } [ "|" default ]
+//! conditional = ( "?" | "!" ) name ":" pattern
+//! comparison  = name ( ">" | ">=" | "<" | "<=" | "==" | "!=" ) number "?" quoted ":" quoted
+//! quoted      = "'" { any character except "'" | "\'" } "'"
+//! name        = [ group ":" ] tag
+//! tag         = ( alphanumeric | "_" | "-" )+
+//! filter      = filter-name [ "(" argument ")" ] | digit+ | "0" digit+ | "%" format
+//! default     = { literal | escape }
+//! ```
+//!
+//! `{T2|unknown}` renders `unknown` if `T2` is missing or empty, instead of
+//! failing; the default is used as is, without filters.
+//!
+//! `{?T2:_{T2}}` renders `_{T2}` only if `T2` is set and non-empty,
+//! `{!T2:...}` only if it isn't. `{ISO>1600?'highiso':'normal'}` compares a
+//! number, the leading one of the value such as `24` in `24.0 mm` or a
+//! fraction such as `1/250`, and renders the first text if it holds, the
+//! second one if it doesn't or the value isn't a number. Filters transform
+//! a variable value from left to right:
+//!
+//! | filter     | description                              |
+//! |------------|------------------------------------------|
+//! | `nospace`  | removes whitespace                       |
+//! | `lower`    | lowercase                                |
+//! | `upper`    | uppercase                                |
+//! | `title`    | first letter of each word uppercase, the others lowercase |
+//! | `left(N)`  | first `N` characters                     |
+//! | `right(N)` | last `N` characters                      |
+//! | `N`        | the block of `N` numbers containing a number, e.g. `{bucket:500}` is `0001-0500` for 1 to 500, `0501-1000` for 501 to 1000 |
+//! | `0N`       | a number zero-padded to `N` digits, e.g. `{n:04}` is `0042` for 42 |
+//! | `%...`     | a date formatted with strftime specifiers, e.g. `{date:%Y-%m-%d}` or `{date:%G-W%V}` |
+//!
+//! Names may carry an exiftool group as in `-filename<` recipes, e.g.
+//! `{EXIF:DateTimeOriginal}` or `{QuickTime:CreateDate}`. Dumps are keyed
+//! by tag name alone, so the group is dropped. `prev:` is kept instead:
+//! `{prev:slug}` is a variable of its own, for values read back from the
+//! current file name.
+//!
+//! A backslash escapes the next special character anywhere, including in
+//! filter arguments (`\:`). A backslash before any other character is kept
+//! as is, so `{Y}\photos` stays a Windows path, but `{Y}\{m}` is the
+//! literal text `{m}`; prefer `/` as the directory separator.
+//!
+//! The module only depends on `std` and `chrono` so fuzz targets can
+//! include it directly.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::ops::Range;
+use std::str::FromStr;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{NaiveDate, NaiveDateTime};
+
+/// Widest zero-padding a `0N` filter may ask for.
+pub const MAX_PAD: usize = 255;
+
+/// Deepest nesting of conditionals, so that parsing and rendering a
+/// hostile pattern can't run out of stack.
+pub const MAX_DEPTH: usize = 64;
+
+/// A parse or render error pointing at the offending part of the pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub message: String,
+    /// Byte range in the pattern.
+    pub span: Range<usize>,
+    pattern: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = self.pattern[..self.span.start].chars().count();
+        let width = self.pattern[self.span.clone()].chars().count().max(1);
+        writeln!(f, "invalid pattern: {}", self.message)?;
+        writeln!(f, "  {}", self.pattern)?;
+        write!(f, "  {}{}", " ".repeat(start), "^".repeat(width))
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A parsed pattern, ready to be rendered against many files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    source: String,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(String),
+    Var {
+        name: String,
+        filters: Vec<Filter>,
+        /// Rendered if the variable is missing or empty.
+        default: Option<String>,
+        span: Range<usize>,
+    },
+    Cond {
+        name: String,
+        negate: bool,
+        body: Vec<Node>,
+    },
+    Compare {
+        name: String,
+        op: Op,
+        value: f64,
+        then: String,
+        otherwise: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    fn holds(self, a: f64, b: f64) -> bool {
+        match self {
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+        }
+    }
+}
+
+/// Returns the number a value starts with, e.g. `24` for `24.0 mm` or
+/// `0.004` for `1/250`.
+fn leading_number(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let end = value
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && "+-".contains(c))))
+        .map_or(value.len(), |(i, _)| i);
+    let number: f64 = value[..end].parse().ok()?;
+    let Some(rest) = value[end..].strip_prefix('/') else {
+        return Some(number);
+    };
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    match rest[..digits].parse::<f64>() {
+        Ok(denominator) if denominator != 0.0 => Some(number / denominator),
+        _ => Some(number),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    NoSpace,
+    Lower,
+    Upper,
+    Title,
+    Left(usize),
+    Right(usize),
+    /// Block of this many numbers.
+    Range(usize),
+    /// Number zero-padded to this many digits, at most [`MAX_PAD`].
+    Pad(usize),
+    /// strftime format of a date.
+    Format(String),
+}
+
+impl Filter {
+    fn apply(&self, value: String) -> String {
+        match self {
+            Filter::NoSpace => value.split_whitespace().collect(),
+            Filter::Lower => value.to_lowercase(),
+            Filter::Upper => value.to_uppercase(),
+            Filter::Title => {
+                let mut out = String::with_capacity(value.len());
+                let mut start = true;
+                for c in value.chars() {
+                    if start {
+                        out.extend(c.to_uppercase());
+                    } else {
+                        out.extend(c.to_lowercase());
+                    }
+                    start = !c.is_alphanumeric();
+                }
+                out
+            }
+            Filter::Left(n) => value.chars().take(*n).collect(),
+            Filter::Right(n) => {
+                let len = value.chars().count();
+                value.chars().skip(len.saturating_sub(*n)).collect()
+            }
+            Filter::Pad(width) if value.chars().all(|c| c.is_ascii_digit()) => {
+                format!("{:0>width$}", value, width = width)
+            }
+            Filter::Pad(_) => value,
+            Filter::Range(size) => match value.parse::<usize>() {
+                Ok(n) if n > 0 => {
+                    let start = (n - 1) / size * size + 1;
+                    format!("{:04}-{:04}", start, start.saturating_add(size - 1))
+                }
+                _ => value,
+            },
+            Filter::Format(format) => {
+                let Some(date) = parse_date(&value) else {
+                    return value;
+                };
+                // dates have no offset for `%z`
+                let mut out = String::new();
+                match write!(out, "{}", date.format(format)) {
+                    Ok(()) => out,
+                    Err(_) => value,
+                }
+            }
+        }
+    }
+}
+
+/// Parses a date as in `{date}` (`20230908T101112`), as printed by
+/// exiftool (`2023:09:08 10:11:12`), in ISO 8601, or a day as in
+/// `{import_date}` (`2023-09-08`).
+fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    let value = value.get(..value.len().min(19))?;
+    ["%Y%m%dT%H%M%S", "%Y:%m:%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            let day = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+            day.and_hms_opt(0, 0, 0)
+        })
+}
+
+impl Pattern {
+    pub fn parse(source: &str) -> Result<Pattern, Error> {
+        let mut parser = Parser {
+            source,
+            pos: 0,
+            depth: 0,
+        };
+        let nodes = parser.parse_nodes()?;
+        Ok(Pattern {
+            source: source.to_string(),
+            nodes,
+        })
+    }
+
+    /// Renders the pattern. Referencing a variable missing from `vars` is an
+    /// error, except in conditionals and with a default.
+    pub fn render(&self, vars: &HashMap<String, String>) -> Result<String, Error> {
+        self.render_or(vars, None)
+    }
+
+    /// Renders the pattern, with `default` for variables missing from
+    /// `vars` that have no default of their own.
+    pub fn render_or(
+        &self,
+        vars: &HashMap<String, String>,
+        default: Option<&str>,
+    ) -> Result<String, Error> {
+        let mut out = String::new();
+        self.render_nodes(&self.nodes, vars, default, &mut out)?;
+        Ok(out)
+    }
+
+    /// Returns the names of the variables the pattern references, including
+    /// those tested by conditionals, in order of appearance.
+    pub fn variables(&self) -> Vec<&str> {
+        fn walk<'a>(nodes: &'a [Node], names: &mut Vec<&'a str>) {
+            for node in nodes {
+                match node {
+                    Node::Literal(_) => {}
+                    Node::Var { name, .. } => names.push(name),
+                    Node::Compare { name, .. } => names.push(name),
+                    Node::Cond { name, body, .. } => {
+                        names.push(name);
+                        walk(body, names);
+                    }
+                }
+            }
+        }
+        let mut names = Vec::new();
+        walk(&self.nodes, &mut names);
+        names
+    }
+
+    /// Returns true if names rendered with the pattern can be read back with
+    /// [`Pattern::captures`], i.e. it has no conditionals, filters or
+    /// defaults.
+    pub fn is_reversible(&self) -> bool {
+        self.nodes.iter().all(|node| match node {
+            Node::Literal(_) => true,
+            Node::Var {
+                filters, default, ..
+            } => filters.is_empty() && default.is_none(),
+            Node::Cond { .. } | Node::Compare { .. } => false,
+        })
+    }
+
+    /// Reads the variables back from `text` rendered with the pattern.
+    /// Date and time variables (`Y`, `m`, `t`, ...) and `r` match digits
+    /// only; other variables match as little as possible, but never a `/`.
+    /// Returns `None` if `text` doesn't match or the pattern isn't
+    /// reversible.
+    pub fn captures(&self, text: &str) -> Option<HashMap<String, String>> {
+        let mut vars = HashMap::new();
+        match_nodes(&self.nodes, text, &mut vars).then_some(vars)
+    }
+
+    fn render_nodes(
+        &self,
+        nodes: &[Node],
+        vars: &HashMap<String, String>,
+        fallback: Option<&str>,
+        out: &mut String,
+    ) -> Result<(), Error> {
+        for node in nodes {
+            match node {
+                Node::Literal(s) => out.push_str(s),
+                Node::Var {
+                    name,
+                    filters,
+                    default: Some(default),
+                    ..
+                } => match vars.get(name).filter(|v| !v.is_empty()) {
+                    Some(value) => {
+                        let value = filters.iter().fold(value.clone(), |v, f| f.apply(v));
+                        out.push_str(&value);
+                    }
+                    None => out.push_str(default),
+                },
+                Node::Var {
+                    name,
+                    filters,
+                    default: None,
+                    span,
+                } => {
+                    let value = match (vars.get(name), fallback) {
+                        (Some(value), _) => filters.iter().fold(value.clone(), |v, f| f.apply(v)),
+                        (None, Some(fallback)) => fallback.to_string(),
+                        (None, None) => {
+                            return Err(Error {
+                                message: format!("unknown variable '{}'", name),
+                                span: span.clone(),
+                                pattern: self.source.clone(),
+                            })
+                        }
+                    };
+                    out.push_str(&value);
+                }
+                Node::Cond { name, negate, body } => {
+                    let set = vars.get(name).is_some_and(|v| !v.is_empty());
+                    if set != *negate {
+                        self.render_nodes(body, vars, fallback, out)?;
+                    }
+                }
+                Node::Compare {
+                    name,
+                    op,
+                    value,
+                    then,
+                    otherwise,
+                } => {
+                    let number = vars.get(name).and_then(|v| leading_number(v));
+                    if number.is_some_and(|n| op.holds(n, *value)) {
+                        out.push_str(then);
+                    } else {
+                        out.push_str(otherwise);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn match_nodes(nodes: &[Node], text: &str, vars: &mut HashMap<String, String>) -> bool {
+    let Some((node, rest)) = nodes.split_first() else {
+        return text.is_empty();
+    };
+    match node {
+        Node::Literal(s) => text
+            .strip_prefix(s.as_str())
+            .is_some_and(|text| match_nodes(rest, text, vars)),
+        Node::Var {
+            name,
+            filters,
+            default: None,
+            ..
+        } if filters.is_empty() => {
+            // a variable used twice must have the same value
+            if let Some(value) = vars.get(name).cloned() {
+                return text
+                    .strip_prefix(value.as_str())
+                    .is_some_and(|text| match_nodes(rest, text, vars));
+            }
+            for len in capture_lengths(name, text) {
+                vars.insert(name.clone(), text[..len].to_string());
+                if match_nodes(rest, &text[len..], vars) {
+                    return true;
+                }
+            }
+            vars.remove(name);
+            false
+        }
+        Node::Var { .. } | Node::Cond { .. } | Node::Compare { .. } => false,
+    }
+}
+
+/// Byte lengths a value of variable `name` at the start of `text` may
+/// have, in the order to try them.
+fn capture_lengths(name: &str, text: &str) -> Vec<usize> {
+    let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+    let fixed = |len: usize| if digits >= len { vec![len] } else { vec![] };
+    match name {
+        "Y" => fixed(4),
+        "y" | "m" | "D" | "H" | "M" | "S" => fixed(2),
+        "t" => fixed(6),
+        "r" => (1..=digits).rev().collect(),
+        _ => text
+            .char_indices()
+            .skip(1)
+            .map(|(i, _)| i)
+            .chain([text.len()])
+            .take_while(|&i| !text[..i].contains('/'))
+            .filter(|&i| i > 0)
+            .collect(),
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pattern::parse(s)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Prefix of variables read back from the current file name.
+pub const PREV: &str = "prev:";
+
+/// exiftool group names accepted before a tag name, lowercase. `XMP-*`
+/// namespaces and `Track1`, `Track2`, ... are matched by prefix.
+const GROUPS: &[&str] = &[
+    "apple",
+    "canon",
+    "composite",
+    "exif",
+    "exififd",
+    "file",
+    "fujifilm",
+    "gps",
+    "h264",
+    "icc_profile",
+    "ifd0",
+    "ifd1",
+    "interopifd",
+    "iptc",
+    "itemlist",
+    "jfif",
+    "keys",
+    "makernotes",
+    "nikon",
+    "olympus",
+    "panasonic",
+    "photoshop",
+    "png",
+    "quicktime",
+    "riff",
+    "sony",
+    "system",
+    "userdata",
+    "xmp",
+];
+
+fn is_group(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    GROUPS.contains(&name.as_str())
+        || name.starts_with("xmp-")
+        || name
+            .strip_prefix("track")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Characters that can be escaped with a backslash.
+const SPECIAL: &[char] = &['{', '}', '\\', ':', '|', '?', '!', '(', ')'];
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    /// Number of enclosing conditionals.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.source[self.pos..].chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Consumes a backslash escape and returns the escaped character, or
+    /// returns `None` if the next character doesn't start one.
+    fn escape(&mut self) -> Option<char> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        let c = self.peek_second().filter(|c| SPECIAL.contains(c))?;
+        self.pos += 1 + c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, message: impl Into<String>, span: Range<usize>) -> Error {
+        Error {
+            message: message.into(),
+            span,
+            pattern: self.source.to_string(),
+        }
+    }
+
+    /// Parses nodes up to the end of input, or up to the `}` closing the
+    /// current conditional, which is left for the caller.
+    fn parse_nodes(&mut self) -> Result<Vec<Node>, Error> {
+        let mut nodes = Vec::new();
+        let mut literal = String::new();
+
+        while let Some(c) = self.peek() {
+            if let Some(escaped) = self.escape() {
+                literal.push(escaped);
+                continue;
+            }
+            match c {
+                '{' if self.peek_second() == Some('{') => {
+                    self.pos += 2;
+                    literal.push('{');
+                }
+                '}' if self.peek_second() == Some('}') => {
+                    self.pos += 2;
+                    literal.push('}');
+                }
+                '{' => {
+                    if !literal.is_empty() {
+                        nodes.push(Node::Literal(std::mem::take(&mut literal)));
+                    }
+                    nodes.push(self.parse_token()?);
+                }
+                '}' if self.depth > 0 => break,
+                '}' => {
+                    return Err(self.error(
+                        "unmatched '}', use '}}' for a literal brace",
+                        self.pos..self.pos + 1,
+                    ))
+                }
+                _ => {
+                    self.bump();
+                    literal.push(c);
+                }
+            }
+        }
+
+        if !literal.is_empty() {
+            nodes.push(Node::Literal(literal));
+        }
+        Ok(nodes)
+    }
+
+    fn parse_token(&mut self) -> Result<Node, Error> {
+        let start = self.pos;
+        self.bump(); // '{'
+
+        let node = match self.peek() {
+            Some(c @ ('?' | '!')) => {
+                self.bump();
+                let name = self.parse_name()?;
+                if self.bump() != Some(':') {
+                    return Err(self.error(
+                        format!("expected ':' after '{{{}{}'", c, name),
+                        start..self.pos,
+                    ));
+                }
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error(
+                        format!("conditionals nested too deeply, at most {}", MAX_DEPTH),
+                        start..self.pos,
+                    ));
+                }
+                self.depth += 1;
+                let body = self.parse_nodes()?;
+                self.depth -= 1;
+                Node::Cond {
+                    name,
+                    negate: c == '!',
+                    body,
+                }
+            }
+            _ => {
+                let name = self.parse_name()?;
+                if let Some(op) = self.parse_op() {
+                    return self.parse_comparison(start, name, op);
+                }
+                let mut filters = Vec::new();
+                while self.peek() == Some(':') {
+                    self.bump();
+                    filters.push(self.parse_filter()?);
+                }
+                let default = if self.peek() == Some('|') {
+                    self.bump();
+                    Some(self.parse_default())
+                } else {
+                    None
+                };
+                Node::Var {
+                    name,
+                    filters,
+                    default,
+                    span: start..self.pos + 1,
+                }
+            }
+        };
+
+        match self.peek() {
+            Some('}') => {
+                self.bump();
+                Ok(node)
+            }
+            Some(c) => Err(self.error(
+                format!("unexpected '{}' in token", c),
+                self.pos..self.pos + c.len_utf8(),
+            )),
+            None => Err(self.error("unclosed '{'", start..self.pos)),
+        }
+    }
+
+    fn parse_op(&mut self) -> Option<Op> {
+        let rest = &self.source[self.pos..];
+        let (op, len) = [
+            (">=", Op::Ge),
+            ("<=", Op::Le),
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            (">", Op::Gt),
+            ("<", Op::Lt),
+        ]
+        .into_iter()
+        .find(|(s, _)| rest.starts_with(s))
+        .map(|(s, op)| (op, s.len()))?;
+        self.pos += len;
+        Some(op)
+    }
+
+    /// Parses the rest of `{name>1600?'high':'low'}` after the operator.
+    fn parse_comparison(&mut self, start: usize, name: String, op: Op) -> Result<Node, Error> {
+        let number_start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || ".+-".contains(c))
+        {
+            self.bump();
+        }
+        let value = self.source[number_start..self.pos]
+            .parse()
+            .map_err(|_| self.error("expected a number to compare with", number_start..self.pos))?;
+        if self.bump() != Some('?') {
+            return Err(self.error("expected '?' after the comparison", start..self.pos));
+        }
+        let then = self.parse_quoted()?;
+        if self.bump() != Some(':') {
+            return Err(self.error("expected ':' between the texts", start..self.pos));
+        }
+        let otherwise = self.parse_quoted()?;
+        match self.peek() {
+            Some('}') => {
+                self.bump();
+                Ok(Node::Compare {
+                    name,
+                    op,
+                    value,
+                    then,
+                    otherwise,
+                })
+            }
+            Some(_) => Err(self.error("expected '}' after the texts", start..self.pos)),
+            None => Err(self.error("unclosed '{'", start..self.pos)),
+        }
+    }
+
+    /// Parses a text in single quotes, where `\'` is a quote.
+    fn parse_quoted(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        if self.bump() != Some('\'') {
+            return Err(self.error("expected a text in quotes, e.g. 'high'", start..self.pos));
+        }
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(text),
+                Some('\\') if self.peek() == Some('\'') => {
+                    self.bump();
+                    text.push('\'');
+                }
+                Some(c) => text.push(c),
+                None => return Err(self.error("unclosed quote", start..self.pos)),
+            }
+        }
+    }
+
+    /// Parses a variable name, dropping an exiftool group prefix and
+    /// keeping a `prev:` one.
+    fn parse_name(&mut self) -> Result<String, Error> {
+        let name = self.parse_tag()?;
+        let tag_follows = self
+            .peek_second()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if self.peek() == Some(':') && tag_follows && name == "prev" {
+            self.bump();
+            return Ok(format!("{}{}", PREV, self.parse_tag()?));
+        }
+        if self.peek() == Some(':') && tag_follows && is_group(&name) {
+            self.bump();
+            return self.parse_tag();
+        }
+        Ok(name)
+    }
+
+    fn parse_tag(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !(c.is_alphanumeric() || c == '_' || c == '-') {
+                break;
+            }
+            self.bump();
+        }
+        if start == self.pos {
+            let end = self.pos + self.peek().map_or(0, char::len_utf8);
+            return Err(self.error("expected a variable name", start..end));
+        }
+        Ok(self.source[start..self.pos].to_string())
+    }
+
+    /// Parses the default of a variable, up to its closing `}`.
+    fn parse_default(&mut self) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if let Some(escaped) = self.escape() {
+                text.push(escaped);
+                continue;
+            }
+            if matches!(c, '{' | '}') {
+                break;
+            }
+            self.bump();
+            text.push(c);
+        }
+        text
+    }
+
+    fn parse_filter(&mut self) -> Result<Filter, Error> {
+        let start = self.pos;
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if let Some(escaped) = self.escape() {
+                text.push(escaped);
+                continue;
+            }
+            if matches!(c, ':' | '|' | '{' | '}') {
+                break;
+            }
+            self.bump();
+            text.push(c);
+        }
+        let text = text.as_str();
+        let span = start..self.pos;
+
+        if text.starts_with('%') {
+            if StrftimeItems::new(text).any(|item| matches!(item, Item::Error)) {
+                return Err(self.error(format!("invalid date format '{}'", text), span));
+            }
+            return Ok(Filter::Format(text.to_string()));
+        }
+
+        let (name, arg) = match text.split_once('(') {
+            Some((name, rest)) => match rest.strip_suffix(')') {
+                Some(arg) => (name, Some(arg)),
+                None => return Err(self.error("expected ')' after filter argument", span)),
+            },
+            None => (text, None),
+        };
+        let count = || {
+            arg.and_then(|a| a.trim().parse::<usize>().ok())
+                .ok_or_else(|| self.error(format!("'{}' expects a number", name), span.clone()))
+        };
+
+        if text.len() > 1 && text.starts_with('0') && text.chars().all(|c| c.is_ascii_digit()) {
+            return match text.parse() {
+                Ok(width) if width <= MAX_PAD => Ok(Filter::Pad(width)),
+                _ => Err(self.error(
+                    format!("zero-pad width can't be more than {}", MAX_PAD),
+                    span,
+                )),
+            };
+        }
+        if !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) {
+            return match text.parse() {
+                Ok(size) if size > 0 => Ok(Filter::Range(size)),
+                _ => Err(self.error("block size must be a positive number", span)),
+            };
+        }
+        match name {
+            "nospace" if arg.is_none() => Ok(Filter::NoSpace),
+            "lower" if arg.is_none() => Ok(Filter::Lower),
+            "upper" if arg.is_none() => Ok(Filter::Upper),
+            "title" if arg.is_none() => Ok(Filter::Title),
+            "left" => Ok(Filter::Left(count()?)),
+            "right" => Ok(Filter::Right(count()?)),
+            "" => Err(self.error("expected a filter after ':'", span)),
+            _ => Err(self.error(format!("unknown filter '{}'", text), span)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> HashMap<String, String> {
+        [
+            ("Y", "2023"),
+            ("m", "09"),
+            ("T2", "Canon EOS R5"),
+            ("e", "JPG"),
+            ("title", ""),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+    }
+
+    fn render(pattern: &str) -> Result<String, Error> {
+        Pattern::parse(pattern)?.render(&vars())
+    }
+
+    #[test]
+    fn test_variables() {
+        let pattern = Pattern::parse("{Y}{m}_{?T2:{!r:x}_{T2:nospace}}.{e}").unwrap();
+        assert_eq!(pattern.variables(), vec!["Y", "m", "T2", "r", "T2", "e"]);
+    }
+
+    #[test]
+    fn test_groups() {
+        let pattern =
+            Pattern::parse("{EXIF:Y}{xmp-exif:m}_{QuickTime:T2:nospace}.{Track1:e}").unwrap();
+        assert_eq!(pattern.variables(), vec!["Y", "m", "T2", "e"]);
+        assert_eq!(pattern.render(&vars()).unwrap(), "202309_CanonEOSR5.JPG");
+        assert_eq!(
+            pattern.to_string(),
+            "{EXIF:Y}{xmp-exif:m}_{QuickTime:T2:nospace}.{Track1:e}"
+        );
+        assert_eq!(render("{?EXIF:T2:_{T2}}").unwrap(), "_Canon EOS R5");
+        assert!(Pattern::parse("{Y:EXIF}").is_err());
+
+        let pattern = Pattern::parse("{t}_{prev:title:upper}{?prev:r:_{prev:r}}").unwrap();
+        assert_eq!(
+            pattern.variables(),
+            vec!["t", "prev:title", "prev:r", "prev:r"]
+        );
+        let captured = Pattern::parse("{Y}_{prev:title}")
+            .unwrap()
+            .captures("2023_Sunset");
+        assert_eq!(captured.unwrap()["prev:title"], "Sunset");
+    }
+
+    #[test]
+    fn test_captures() {
+        let pattern = Pattern::parse("{Y}{m}{D}_{t}_{r}.{e}").unwrap();
+        let vars = pattern.captures("20230908_101112_0042.JPG").unwrap();
+        assert_eq!(vars["Y"], "2023");
+        assert_eq!(vars["t"], "101112");
+        assert_eq!(vars["r"], "0042");
+        assert_eq!(vars["e"], "JPG");
+        assert!(pattern.captures("2023098_101112_0042.JPG").is_none());
+        assert!(pattern.captures("IMG_0042.JPG").is_none());
+
+        let pattern = Pattern::parse("{Y}/{title}_{r}{e}").unwrap();
+        let vars = pattern.captures("2023/Sunset_2_12.jpg").unwrap();
+        assert_eq!(vars["title"], "Sunset");
+        assert_eq!(vars["r"], "2");
+        assert_eq!(vars["e"], "_12.jpg");
+        assert!(pattern.captures("2023/a/b_1x").is_none());
+
+        let pattern = Pattern::parse("{Y}-{D}-{Y}.{e}").unwrap();
+        assert!(pattern.captures("2023-08-2023.jpg").is_some());
+        assert!(pattern.captures("2023-08-2024.jpg").is_none());
+
+        for source in ["{Y}_{T2:nospace}", "{Y}{?T2:_{T2}}", "{Y}_{T2|x}"] {
+            let pattern = Pattern::parse(source).unwrap();
+            assert!(!pattern.is_reversible());
+            assert!(pattern.captures("2023_CanonEOSR5").is_none());
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render("{Y}{m}_{T2}.{e}").unwrap(),
+            "202309_Canon EOS R5.JPG"
+        );
+        assert_eq!(render("photo").unwrap(), "photo");
+        assert_eq!(render("").unwrap(), "");
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(render("{{{Y}}}").unwrap(), "{2023}");
+        assert_eq!(render("a}}b{{").unwrap(), "a}b{");
+    }
+
+    #[test]
+    fn test_backslash_escapes() {
+        assert_eq!(render(r"\{Y\}").unwrap(), "{Y}");
+        assert_eq!(render(r"{Y}\{{m}\}").unwrap(), "2023{09}");
+        assert_eq!(render(r"a\\b").unwrap(), r"a\b");
+        assert_eq!(render(r"{Y}\:{m}").unwrap(), "2023:09");
+        assert_eq!(render(r"{?T2:\{\}}").unwrap(), "{}");
+        // not an escape: kept as is
+        assert_eq!(render(r"{Y}\photos\").unwrap(), r"2023\photos\");
+        assert_eq!(render(r"{T2:left(5)}\(x\)").unwrap(), "Canon(x)");
+
+        let err = Pattern::parse(r"{Y:nospace\:x}").unwrap_err();
+        assert_eq!(err.message, "unknown filter 'nospace:x'");
+        assert_eq!(err.span, 3..13);
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(render("{T2:nospace}").unwrap(), "CanonEOSR5");
+        assert_eq!(render("{T2:left(5)}").unwrap(), "Canon");
+        assert_eq!(render("{T2:nospace:right(4)}").unwrap(), "OSR5");
+        assert_eq!(render("{e:lower}").unwrap(), "jpg");
+        assert_eq!(render("{T2:upper:nospace}").unwrap(), "CANONEOSR5");
+        let vars = HashMap::from([("f".to_string(), "NIKON d750-x_ray".to_string())]);
+        let title = Pattern::parse("{f:title}").unwrap();
+        assert_eq!(title.render(&vars).unwrap(), "Nikon D750-X_Ray");
+        assert_eq!(render("{m:500}").unwrap(), "0001-0500");
+        assert_eq!(render("{Y:500}").unwrap(), "2001-2500");
+        assert_eq!(render("{Y:1}").unwrap(), "2023-2023");
+        assert_eq!(render("{T2:500}").unwrap(), "Canon EOS R5");
+        let max = usize::MAX;
+        let huge = Pattern::parse(&format!("{{n:{}}}", max - 1)).unwrap();
+        let vars = HashMap::from([("n".to_string(), max.to_string())]);
+        assert_eq!(huge.render(&vars).unwrap(), format!("{}-{}", max, max));
+        assert_eq!(render("{m:04}").unwrap(), "0009");
+        assert_eq!(render("{Y:02}").unwrap(), "2023");
+        assert_eq!(render("{T2:04}").unwrap(), "Canon EOS R5");
+        let err = Pattern::parse("{Y:0}").unwrap_err();
+        assert_eq!(err.message, "block size must be a positive number");
+        assert_eq!(render("{Y:0255}").unwrap().len(), 255);
+        for pattern in [
+            "{Y:0256}",
+            "{Y:0999999999999}",
+            "{Y:099999999999999999999999}",
+        ] {
+            let err = Pattern::parse(pattern).unwrap_err();
+            assert_eq!(err.message, "zero-pad width can't be more than 255");
+        }
+    }
+
+    #[test]
+    fn test_date_formats() {
+        let vars = HashMap::from([
+            ("date".to_string(), "20230908T101112".to_string()),
+            (
+                "CreateDate".to_string(),
+                "2023:01:01 00:00:00+09:00".to_string(),
+            ),
+            ("T2".to_string(), "Canon EOS R5".to_string()),
+        ]);
+        let render = |source: &str| Pattern::parse(source).unwrap().render(&vars).unwrap();
+        assert_eq!(render("{date:%Y-%m-%d}"), "2023-09-08");
+        assert_eq!(render("{date:%b %-d, %a}"), "Sep 8, Fri");
+        assert_eq!(render(r"{date:%H\:%M}"), "10:11");
+        assert_eq!(render("{CreateDate:%G-W%V-%u}"), "2022-W52-7");
+        assert_eq!(render("{date:%B:upper}"), "SEPTEMBER");
+        assert_eq!(render("{T2:%Y}"), "Canon EOS R5");
+        let vars = HashMap::from([("import_date".to_string(), "2023-09-08".to_string())]);
+        let import = Pattern::parse("{import_date:%d %b}").unwrap();
+        assert_eq!(import.render(&vars).unwrap(), "08 Sep");
+        assert_eq!(render("{date:%z}"), "20230908T101112");
+        let err = Pattern::parse("{date:%Q}").unwrap_err();
+        assert_eq!(err.message, "invalid date format '%Q'");
+        assert_eq!(err.span, 6..8);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        assert_eq!(render("{Y}{?T2:_{T2:nospace}}").unwrap(), "2023_CanonEOSR5");
+        assert_eq!(render("{Y}{?title:_{title}}").unwrap(), "2023");
+        assert_eq!(render("{Y}{?Missing:_{Missing}}").unwrap(), "2023");
+        assert_eq!(render("{!title:untitled}").unwrap(), "untitled");
+        assert_eq!(render("{?T2:{{x}}}").unwrap(), "{x}");
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert_eq!(render("{Y>2000?'new':'old'}").unwrap(), "new");
+        assert_eq!(render("{Y<=2000?'new':'old'}").unwrap(), "old");
+        assert_eq!(render("{Y==2023?'it\\'s':''}").unwrap(), "it's");
+        assert_eq!(render("{m!=9?'a':'b'}").unwrap(), "b");
+        assert_eq!(render("{T2>1?'a':'b'}").unwrap(), "b");
+        assert_eq!(render("{Missing>1?'a':'b'}").unwrap(), "b");
+        let pattern = Pattern::parse("{ISO>=1600?'highiso':'normal'}").unwrap();
+        assert_eq!(pattern.variables(), vec!["ISO"]);
+        assert!(!pattern.is_reversible());
+
+        assert_eq!(leading_number("24.0 mm"), Some(24.0));
+        assert_eq!(leading_number("1/250"), Some(0.004));
+        assert_eq!(leading_number("-1.5"), Some(-1.5));
+        assert_eq!(leading_number("f/2.8"), None);
+
+        let err = |pattern: &str| Pattern::parse(pattern).unwrap_err().message;
+        assert_eq!(err("{Y>x?'a':'b'}"), "expected a number to compare with");
+        assert_eq!(err("{Y>1'a':'b'}"), "expected '?' after the comparison");
+        assert_eq!(err("{Y>1?a:'b'}"), "expected a text in quotes, e.g. 'high'");
+        assert_eq!(err("{Y>1?'a''b'}"), "expected ':' between the texts");
+        assert_eq!(err("{Y>1?'a':'b}"), "unclosed quote");
+        assert_eq!(err("{Y>1?'a':'b'x}"), "expected '}' after the texts");
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(render("{Model|unknown}.{e}").unwrap(), "unknown.JPG");
+        assert_eq!(render("{title|untitled}").unwrap(), "untitled");
+        assert_eq!(render("{T2:nospace|x}").unwrap(), "CanonEOSR5");
+        assert_eq!(render("{Model|}_{Y}").unwrap(), "_2023");
+        assert_eq!(render(r"{Model|a\}b\|c}").unwrap(), "a}b|c");
+        assert_eq!(render("{?Y:{Model|none}}").unwrap(), "none");
+        assert_eq!(
+            Pattern::parse("{Model|{Y}}").unwrap_err().message,
+            "unexpected '{' in token"
+        );
+
+        let pattern = Pattern::parse("{Y}_{Model}_{Lens|}_{title}").unwrap();
+        assert_eq!(pattern.render_or(&vars(), Some("na")).unwrap(), "2023_na__");
+        assert!(pattern.render_or(&vars(), None).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = render("{Y}{Model}").unwrap_err();
+        assert_eq!(err.message, "unknown variable 'Model'");
+        assert_eq!(err.span, 3..10);
+        assert_eq!(
+            err.to_string(),
+            "invalid pattern: unknown variable 'Model'\n  {Y}{Model}\n     ^^^^^^^"
+        );
+
+        assert_eq!(Pattern::parse("{Y").unwrap_err().span, 0..2);
+        assert_eq!(Pattern::parse("{Y}}").unwrap_err().span, 3..4);
+        assert_eq!(Pattern::parse("{}").unwrap_err().span, 1..2);
+        assert_eq!(Pattern::parse("{Y:upperx}").unwrap_err().span, 3..9);
+        assert_eq!(Pattern::parse("{Y:left(x)}").unwrap_err().span, 3..10);
+        assert_eq!(Pattern::parse("{?Y_}").unwrap_err().span, 0..5);
+        assert_eq!(
+            Pattern::parse("{?Y:abc").unwrap_err().message,
+            "unclosed '{'"
+        );
+        assert_eq!(Pattern::parse("{Y m}").unwrap_err().span, 2..3);
+
+        // '}}' is an escaped brace, so each conditional is closed by '}_'
+        let nested = |depth| "{?Y:".repeat(depth) + &"}_".repeat(depth);
+        assert!(Pattern::parse(&nested(MAX_DEPTH)).is_ok());
+        let err = Pattern::parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(err.message, "conditionals nested too deeply, at most 64");
+        assert_eq!(err.span, 4 * MAX_DEPTH..4 * MAX_DEPTH + 4);
+        assert!(Pattern::parse(&nested(20000)).is_err());
+    }
+}
@@ -0,0 +1,1320 @@
+//! Planning the new name of a file, collecting warnings along the way.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::burst;
+use crate::calendar;
+use crate::city;
+use crate::counters::Counters;
+use crate::dates::{self, Candidate, Delta};
+use crate::dump;
+use crate::gpx;
+use crate::pattern::{Error, Pattern, PREV};
+use crate::rules::{self, Action, Rule};
+use crate::size;
+use crate::sort::SortKey;
+use crate::suffix::SuffixFormat;
+use crate::truncate;
+use crate::tz;
+use crate::unique;
+use crate::{
+    camera, capture_date, extend_vars, file_created, gps_coordinates, insert_date_vars,
+    insert_text, parse_datetime, Args, Vars,
+};
+
+/// Something noteworthy that happened while planning a file. Warnings don't
+/// stop the rename, but callers should surface them to the user.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// No usable capture date; date variables are unavailable.
+    MissingDate,
+    /// No capture date in the tags; the date from this source is used instead.
+    FallbackDate(&'static str),
+    /// Characters were dropped or the value was cut to make a variable filename-safe.
+    Sanitized(String),
+    /// Control or bidi formatting characters were removed from the new name.
+    Invisible,
+    /// None of the tag names exiftool always prints were found, most likely
+    /// because the dump was written in another language.
+    UnknownTags,
+    /// The file name doesn't match `--from-pattern`.
+    NoMatch,
+    /// A candidate date from this source is further off the chosen date
+    /// than `--max-date-disagreement`.
+    DateDisagreement(&'static str, Delta),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::MissingDate => write!(f, "no capture date, date variables are unavailable"),
+            Warning::FallbackDate(source) => write!(f, "no capture date, using {}", source),
+            Warning::Sanitized(var) => write!(f, "sanitized {{{}}}", var),
+            Warning::Invisible => write!(
+                f,
+                "removed control or bidi formatting characters from the new name"
+            ),
+            Warning::UnknownTags => write!(
+                f,
+                "no known tag names in the exif dump, write it with `exiftool -lang en`"
+            ),
+            Warning::NoMatch => write!(f, "the file name doesn't match --from-pattern"),
+            Warning::DateDisagreement(source, delta) => {
+                write!(
+                    f,
+                    "the date from the {} is {} off the capture date",
+                    source, delta
+                )
+            }
+        }
+    }
+}
+
+impl Warning {
+    /// The warning without file-specific details, for counting files with
+    /// the same problem.
+    pub fn summary(&self) -> String {
+        match self {
+            Warning::DateDisagreement(source, _) => {
+                format!("the date from the {} is off the capture date", source)
+            }
+            warning => warning.to_string(),
+        }
+    }
+}
+
+/// What to do with a file.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// Rename to the new filename.
+    Rename(String),
+    /// Copy under the new filename into a directory.
+    CopyTo(PathBuf, String),
+    /// Move into a directory, keeping the filename.
+    Quarantine(PathBuf),
+    /// Leave the file untouched, with the reason.
+    Skip(String),
+}
+
+/// Planned new name of a file.
+#[derive(Debug)]
+pub struct Plan {
+    pub outcome: Outcome,
+    /// Problems with the new name that don't stop the rename, for the
+    /// caller to report.
+    pub warnings: Vec<Warning>,
+    pub vars: Vars,
+    /// The chosen capture date.
+    pub date: Option<NaiveDateTime>,
+    /// Candidate dates, collected for `--date-report` and
+    /// `--max-date-disagreement`.
+    pub candidates: Vec<Candidate>,
+    /// Index of the rule that matched, if any.
+    pub rule: Option<usize>,
+}
+
+/// Returns an error naming the first variable of `patterns` whose value
+/// depends on when the run happens rather than on the files, for
+/// `--deterministic`.
+pub fn check_deterministic(patterns: &[&Pattern]) -> Result<(), String> {
+    for pattern in patterns {
+        let name = pattern
+            .variables()
+            .into_iter()
+            .find(|name| name.starts_with("import_") || *name == "age_bucket");
+        if let Some(name) = name {
+            return Err(format!(
+                "{{{}}} in '{}' depends on the time of the run, which --deterministic forbids",
+                name, pattern
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A file to plan: the path given on the command line, if any, and its
+/// exif tags.
+#[derive(Debug)]
+pub struct Input {
+    pub file: Option<String>,
+    pub exif: Vars,
+}
+
+/// Variables of a file, collected before anything is rendered.
+struct File {
+    vars: Vars,
+    date: Option<NaiveDateTime>,
+    warnings: Vec<Warning>,
+    candidates: Vec<Candidate>,
+    /// True if candidate dates disagree beyond `--max-date-disagreement`.
+    suspect: bool,
+    /// False if the file is excluded by filters.
+    included: bool,
+    /// Set if a batch or group variable failed to render.
+    error: Option<Error>,
+}
+
+/// Computes the new filenames for a batch of files. `now` is the time the
+/// import started, shared by every file of a run. The first of `rules`
+/// matching a file overrides renaming it with the first of `patterns` it
+/// has all variables for, or the last one.
+///
+/// Variables of every file are collected before any name is rendered, so
+/// patterns can use batch variables computed from all included files.
+pub fn plan(
+    args: &Args,
+    patterns: &[Pattern],
+    rules: &[Rule],
+    inputs: Vec<Input>,
+    now: NaiveDateTime,
+    counters: &Counters,
+) -> Vec<Result<Plan, Error>> {
+    let renames = rules.iter().filter_map(|rule| match &rule.action {
+        Action::Rename(pattern) => Some(pattern),
+        _ => None,
+    });
+    let all: Vec<&Pattern> = patterns
+        .iter()
+        .chain(renames)
+        .chain(&args.dir_pattern)
+        .collect();
+    let known = args
+        .from_pattern
+        .as_ref()
+        .or(patterns.first().filter(|p| p.is_reversible()))
+        .filter(|_| {
+            let mut names = all.iter().flat_map(|p| p.variables());
+            names.any(|name| name.starts_with(PREV))
+        });
+    let mut files: Vec<File> = inputs
+        .into_iter()
+        .map(|input| collect(args, input, known, now))
+        .collect();
+    insert_batch_vars(args, &mut files);
+    if let Some(window) = args.collision_window {
+        insert_burst_vars(&mut files, window);
+    }
+    if let Some(group_by) = &args.group_by {
+        insert_group_vars(&mut files, group_by);
+    }
+    if all.iter().any(|p| p.variables().contains(&"n")) {
+        insert_counter_vars(args, rules, counters, &mut files);
+    }
+    if all.iter().any(|p| p.variables().contains(&"u")) {
+        insert_unique_vars(args, patterns, rules, &mut files);
+    }
+
+    files
+        .into_iter()
+        .map(|mut file| {
+            if let Some(e) = file.error {
+                return Err(e);
+            }
+            let (outcome, rule) = if !file.included {
+                (Outcome::Skip("filter".to_string()), None)
+            } else {
+                outcome(
+                    args,
+                    patterns,
+                    rules,
+                    &file.vars,
+                    file.date,
+                    &mut file.warnings,
+                )?
+            };
+            // suspicious dates are only acted on by rules routing them for review
+            let outcome = match outcome {
+                Outcome::Rename(_) | Outcome::CopyTo(..) if file.suspect => {
+                    Outcome::Skip("date disagreement".to_string())
+                }
+                outcome => outcome,
+            };
+            Ok(Plan {
+                outcome,
+                warnings: file.warnings,
+                vars: file.vars,
+                date: file.date,
+                candidates: file.candidates,
+                rule,
+            })
+        })
+        .collect()
+}
+
+/// Returns what to do with an included file, and the index of the rule
+/// deciding it: rename it with the first of `patterns` it has all
+/// variables for, unless one of `rules` matches.
+fn outcome(
+    args: &Args,
+    patterns: &[Pattern],
+    rules: &[Rule],
+    vars: &Vars,
+    date: Option<NaiveDateTime>,
+    warnings: &mut Vec<Warning>,
+) -> Result<(Outcome, Option<usize>), Error> {
+    let pattern = choose(args, patterns, vars);
+    let mut name = |pattern: &Pattern| render_name(args, pattern, vars, warnings);
+    let Some((index, rule)) = rules::first_match(rules, vars, date.map(|d| d.date())) else {
+        return Ok((Outcome::Rename(name(pattern)?), None));
+    };
+    let outcome = match &rule.action {
+        Action::Rename(pattern) => Outcome::Rename(name(pattern)?),
+        Action::Skip => Outcome::Skip(format!("rule {}", index + 1)),
+        Action::Quarantine(dir) => Outcome::Quarantine(dir.clone()),
+        Action::CopyTo(dir) => Outcome::CopyTo(dir.clone(), name(pattern)?),
+    };
+    Ok((outcome, Some(index)))
+}
+
+/// Returns the first of `patterns` rendering without `--default-value`,
+/// or the last one.
+fn choose<'a>(args: &Args, patterns: &'a [Pattern], vars: &Vars) -> &'a Pattern {
+    let last = &patterns[patterns.len() - 1];
+    if patterns.len() == 1 {
+        return last;
+    }
+    let vars = truncate::apply(&args.max_var_len, vars);
+    patterns
+        .iter()
+        .find(|pattern| pattern.render(&vars).is_ok())
+        .unwrap_or(last)
+}
+
+/// Renders `pattern` with values shortened by `--max-var-len`, prefixed
+/// with the directory rendered from `--dir-pattern` if given. Control and
+/// bidi formatting characters, which can make a filename display
+/// deceptively, are removed with a warning. `--dcf` uppercases the name.
+fn render_name(
+    args: &Args,
+    pattern: &Pattern,
+    vars: &Vars,
+    warnings: &mut Vec<Warning>,
+) -> Result<String, Error> {
+    let vars = truncate::apply(&args.max_var_len, vars);
+    let default = args.default_value.as_deref();
+    let mut name = pattern.render_or(&vars, default)?;
+    if let Some(dir_pattern) = &args.dir_pattern {
+        let dir = dir_pattern.render_or(&vars, default)?;
+        let dir = dir.trim_end_matches('/');
+        if !dir.is_empty() {
+            name = format!("{}/{}", dir, name);
+        }
+    }
+    if name.chars().any(dump::is_invisible) {
+        warnings.push(Warning::Invisible);
+        name = dump::strip_invisible(&name);
+    }
+    if args.dcf {
+        name = name.to_ascii_uppercase();
+    }
+    Ok(name)
+}
+
+/// Collects the variables of a file. With `known`, the pattern the file
+/// was named with, the values read back from its name are inserted as
+/// `prev:` variables.
+fn collect(args: &Args, input: Input, known: Option<&Pattern>, now: NaiveDateTime) -> File {
+    let mut warnings = Vec::new();
+    let mut exif = input.exif;
+
+    let mut offset = camera::camera_offset(&args.sync_offsets, &exif)
+        + args.shift.map(|shift| shift.0).unwrap_or_default();
+    if let Some(tz) = &args.tz {
+        offset = tz::convert(tz, &mut exif, offset, args.date_source.as_ref());
+    }
+    let cam = camera::camera_label(&args.cam_map, &exif)
+        .unwrap_or_default()
+        .to_string();
+    let recovered = match (&args.from_pattern, input.file.as_deref()) {
+        (Some(from), Some(file)) => {
+            let recovered = recover(from, file);
+            if recovered.is_none() {
+                warnings.push(Warning::NoMatch);
+            }
+            recovered.unwrap_or_default()
+        }
+        _ => Vars::new(),
+    };
+    let btime = input.file.as_deref().and_then(file_created);
+    let fallback = recovered_date(&recovered)
+        .map(|date| (date, "the date in the file name"))
+        .or(btime
+            .filter(|_| args.btime_fallback)
+            .map(|btime| (btime, "the file creation time")));
+    let exif_date = capture_date(&exif, offset, args.date_source.as_ref());
+    let date = exif_date.or(fallback.map(|(date, _)| date));
+    let candidates = if args.date_report || args.max_date_disagreement.is_some() {
+        let name_date = recovered_date(&recovered);
+        dates::candidates(exif_date, &exif, input.file.as_deref(), name_date, btime)
+    } else {
+        Vec::new()
+    };
+    let disagreement = match (args.max_date_disagreement, date) {
+        (Some(max), Some(date)) => dates::disagreement(&candidates, date, max),
+        _ => None,
+    };
+    if let (Some(candidate), Some(date)) = (disagreement, date) {
+        let delta = Delta(candidate.delta(date));
+        warnings.push(Warning::DateDisagreement(candidate.source, delta));
+    }
+    let suspect = disagreement.is_some();
+    if let (Some(track), Some(date)) = (&args.gpx, date) {
+        let position = gpx::to_utc(date, &exif).and_then(|utc| track.locate(utc));
+        if let (None, Some(position)) = (gps_coordinates(&exif), position) {
+            gpx::insert(&mut exif, position);
+        }
+    }
+    let mut vars = extend_vars(
+        exif,
+        input.file.as_deref(),
+        offset,
+        args.date_source.as_ref(),
+        fallback,
+        &mut warnings,
+    );
+    if let Some(city) = city::city(&vars, gps_coordinates(&vars), args.cities.as_ref()) {
+        let city = city.to_string();
+        insert_text(&mut vars, &mut warnings, "city", &city);
+    }
+    if let Some(date) = date {
+        let utc = gpx::to_utc(date, &vars);
+        if let Some(event) = calendar::event(&args.calendar, date, utc) {
+            let event = event.to_string();
+            insert_text(&mut vars, &mut warnings, "event", &event);
+        }
+    }
+    for (name, value) in recovered {
+        // date variables follow the chosen capture date
+        if !DATE_VARS.contains(&name.as_str()) {
+            vars.insert(name, value);
+        }
+    }
+    if let (Some(known), Some(file)) = (known, input.file.as_deref()) {
+        for (name, value) in recover(known, file).unwrap_or_default() {
+            let name = name.strip_prefix(PREV).unwrap_or(&name);
+            vars.insert(format!("{}{}", PREV, name), value);
+        }
+    }
+    vars.insert("cam".to_string(), cam);
+    insert_date_vars(&mut vars, "import_", now);
+    vars.insert(
+        "import_date".to_string(),
+        now.format("%Y-%m-%d").to_string(),
+    );
+
+    if let Some(btime) = btime {
+        insert_date_vars(&mut vars, "btime_", btime);
+        let btime_date = btime.format("%Y-%m-%d").to_string();
+        vars.insert("btime_date".to_string(), btime_date);
+    }
+
+    if let Some(date) = date {
+        let buckets = args.age_buckets.clone().unwrap_or_default();
+        let age = (now - date).num_days();
+        vars.insert("age_bucket".to_string(), buckets.bucket(age));
+    }
+
+    let size = input
+        .file
+        .as_deref()
+        .and_then(|f| std::fs::metadata(f).ok());
+    let size = size
+        .map(|metadata| metadata.len())
+        .or_else(|| vars.get("FileSize").and_then(|s| size::parse_file_size(s)));
+    if let Some(size) = size {
+        let tiers = args.size_tiers.clone().unwrap_or_default();
+        vars.insert("size_tier".to_string(), tiers.tier(size).to_string());
+    }
+
+    if args.max_date_disagreement.is_some() {
+        let suspect = if suspect { "yes" } else { "no" };
+        vars.insert("date_suspect".to_string(), suspect.to_string());
+    }
+
+    let included = args.filter.iter().all(|f| f.matches(&vars));
+    File {
+        vars,
+        date,
+        warnings,
+        candidates,
+        suspect,
+        included,
+        error: None,
+    }
+}
+
+/// Variables derived from the capture date.
+const DATE_VARS: &[&str] = &["Y", "y", "m", "D", "H", "M", "S", "t"];
+
+/// Reads variables back from the name of `file` with `--from-pattern`. A
+/// pattern with `/` matches as many trailing path components.
+fn recover(from: &Pattern, file: &str) -> Option<Vars> {
+    let components = from.to_string().matches('/').count() + 1;
+    let start = file
+        .rmatch_indices(['/', '\\'])
+        .nth(components - 1)
+        .map_or(0, |(i, _)| i + 1);
+    from.captures(&file[start..].replace('\\', "/"))
+}
+
+/// Returns the date of recovered date variables: `Y` (or `y`), `m` and
+/// `D`, with the time from `t` or `H`, `M` and `S` if present.
+fn recovered_date(vars: &Vars) -> Option<NaiveDateTime> {
+    let number = |name: &str| vars.get(name).and_then(|v| v.parse::<u32>().ok());
+    let year = match (number("Y"), number("y")) {
+        (Some(year), _) => year as i32,
+        (None, Some(year)) => 2000 + year as i32,
+        (None, None) => return None,
+    };
+    let date = NaiveDate::from_ymd_opt(year, number("m")?, number("D")?)?;
+    let time = match vars.get("t") {
+        Some(t) => NaiveTime::parse_from_str(t, "%H%M%S").ok()?,
+        None => NaiveTime::from_hms_opt(
+            number("H").unwrap_or(0),
+            number("M").unwrap_or(0),
+            number("S").unwrap_or(0),
+        )?,
+    };
+    Some(date.and_time(time))
+}
+
+/// Returns the indices of `files` in `--sort` order, then in input order.
+fn sorted(args: &Args, files: &[File]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    match args.sort {
+        SortKey::Date => order.sort_by_key(|&i| (files[i].date.is_none(), files[i].date, i)),
+        SortKey::Name => order.sort_by_key(|&i| (files[i].vars.get("FileName"), i)),
+        SortKey::Mtime => order.sort_by_key(|&i| {
+            let mtime = files[i].vars.get("FileModifyDate");
+            let mtime = mtime.and_then(|s| parse_datetime(s));
+            (mtime.is_none(), mtime, i)
+        }),
+    }
+    order
+}
+
+/// Inserts `batch_count`, `batch_first_date` and `batch_last_date`,
+/// computed from the included files, into the variables of every file, and
+/// `bucket`, the position of each included file in `--sort` order. The
+/// dates are missing if no included file has a capture date.
+fn insert_batch_vars(args: &Args, files: &mut [File]) {
+    let mut included = sorted(args, files);
+    included.retain(|&i| files[i].included);
+    for (position, &i) in included.iter().enumerate() {
+        let bucket = (position + 1).to_string();
+        files[i].vars.insert("bucket".to_string(), bucket);
+    }
+
+    let count = included.len();
+    let first = included.iter().filter_map(|&i| files[i].date).min();
+    let last = included.iter().filter_map(|&i| files[i].date).max();
+
+    for file in files.iter_mut() {
+        let vars = &mut file.vars;
+        vars.insert("batch_count".to_string(), count.to_string());
+        if let (Some(first), Some(last)) = (first, last) {
+            let first = first.format("%Y-%m-%d").to_string();
+            let last = last.format("%Y-%m-%d").to_string();
+            vars.insert("batch_first_date".to_string(), first);
+            vars.insert("batch_last_date".to_string(), last);
+        }
+    }
+}
+
+/// Inserts `burst`, the suffix of included files captured within `window`
+/// of each other, empty for files outside of a burst.
+fn insert_burst_vars(files: &mut [File], window: burst::Window) {
+    let times = files
+        .iter()
+        .enumerate()
+        .filter(|(_, file)| file.included)
+        .filter_map(|(i, file)| Some((i, burst::capture_time(file.date?, &file.vars))))
+        .collect();
+    for (i, suffix) in burst::assign(times, window) {
+        files[i].vars.insert("burst".to_string(), suffix);
+    }
+}
+
+/// The counter `{n}` of a file counts in: its capture day with
+/// `--counter-per-day` and its directory with `--counter-per-dir`, or the
+/// whole run.
+pub fn counter_scope(args: &Args, vars: &Vars, date: Option<NaiveDateTime>) -> String {
+    let mut scope = Vec::new();
+    if args.counter_per_day {
+        scope.push(date.map_or(String::new(), |d| d.format("%Y-%m-%d").to_string()));
+    }
+    if args.counter_per_dir {
+        scope.push(vars.get("Directory").cloned().unwrap_or_default());
+    }
+    scope.join("\t")
+}
+
+/// Inserts `n`, a counter from `--counter-start` by `--counter-step` over
+/// the included files in `--sort` order, or over those of each scope of
+/// [`counter_scope`]. A scope `last` has a value for continues after it.
+/// Files skipped or quarantined by a rule don't take a number.
+fn insert_counter_vars(args: &Args, rules: &[Rule], last: &Counters, files: &mut [File]) {
+    let order = sorted(args, files);
+    let mut counters: HashMap<String, u64> = HashMap::new();
+    for i in order {
+        let file = &mut files[i];
+        if !file.included || file.error.is_some() {
+            continue;
+        }
+        let date = file.date.map(|d| d.date());
+        if let Some((_, rule)) = rules::first_match(rules, &file.vars, date) {
+            if matches!(rule.action, Action::Skip | Action::Quarantine(_)) {
+                continue;
+            }
+        }
+        let scope = counter_scope(args, &file.vars, file.date);
+        let start = match last.last(&scope) {
+            Some(last) => last.saturating_add(args.counter_step),
+            None => args.counter_start,
+        };
+        let n = counters.entry(scope).or_insert(start);
+        file.vars.insert("n".to_string(), n.to_string());
+        *n = n.saturating_add(args.counter_step);
+    }
+}
+
+/// Inserts `u`, empty for the first of the included files getting the same
+/// name with an empty `u` and a suffix for the others, ordered by capture
+/// date, then by input order.
+fn insert_unique_vars(args: &Args, patterns: &[Pattern], rules: &[Rule], files: &mut [File]) {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| (files[i].date.is_none(), files[i].date, i));
+    let mut names = Vec::new();
+    for i in order {
+        let file = &mut files[i];
+        file.vars.insert("u".to_string(), String::new());
+        if !file.included || file.error.is_some() {
+            continue;
+        }
+        // warnings are collected when the name is rendered for good
+        let name = match outcome(
+            args,
+            patterns,
+            rules,
+            &file.vars,
+            file.date,
+            &mut Vec::new(),
+        ) {
+            Ok((Outcome::Rename(name), _)) => {
+                let dir = file.vars.get("Directory").map_or("", String::as_str);
+                Path::new(dir).join(name)
+            }
+            Ok((Outcome::CopyTo(dir, name), _)) => dir.join(name),
+            Ok(_) => continue,
+            Err(e) => {
+                file.error = Some(e);
+                continue;
+            }
+        };
+        names.push((i, name));
+    }
+    let suffixes = unique::Suffixes {
+        style: args.unique_style,
+        format: args
+            .suffix_format
+            .clone()
+            .unwrap_or_else(|| SuffixFormat::new("_")),
+        first: args.suffix_first,
+    };
+    for (i, suffix) in unique::assign(names, &suffixes) {
+        files[i].vars.insert("u".to_string(), suffix);
+    }
+}
+
+/// Partitions the included files by `group_by` rendered with their
+/// variables, and inserts `group`, `group_count`, `group_index`,
+/// `group_first_time` and `group_last_time`. Files of a group are numbered
+/// by capture date, then by input order.
+fn insert_group_vars(files: &mut [File], group_by: &Pattern) {
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, file) in files.iter_mut().enumerate() {
+        if !file.included {
+            continue;
+        }
+        match group_by.render(&file.vars) {
+            Ok(key) => groups.entry(key).or_default().push(i),
+            Err(e) => file.error = Some(e),
+        }
+    }
+
+    for (key, mut members) in groups {
+        members.sort_by_key(|&i| (files[i].date.is_none(), files[i].date, i));
+        let dates = members.iter().filter_map(|&i| files[i].date);
+        let first = dates.clone().min().map(|d| d.format("%H%M%S").to_string());
+        let last = dates.max().map(|d| d.format("%H%M%S").to_string());
+
+        for (index, &i) in members.iter().enumerate() {
+            let vars = &mut files[i].vars;
+            vars.insert("group".to_string(), key.clone());
+            vars.insert("group_count".to_string(), members.len().to_string());
+            vars.insert("group_index".to_string(), (index + 1).to_string());
+            if let (Some(first), Some(last)) = (&first, &last) {
+                vars.insert("group_first_time".to_string(), first.clone());
+                vars.insert("group_last_time".to_string(), last.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::dump::exif_vars;
+
+    fn input(dump: &str) -> Input {
+        Input {
+            file: None,
+            exif: exif_vars(dump),
+        }
+    }
+
+    #[test]
+    fn test_batch_vars() {
+        let args = Args::parse_from(["exif-rename", "-e", "-", "--filter", "!Model=Scanner"]);
+        let pattern = [Pattern::parse("{batch_first_date}_to_{batch_last_date}/{t}").unwrap()];
+        let inputs = vec![
+            input("Create Date : 2023:09:10 08:00:00\n"),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+            input("Create Date : 2020:01:01 00:00:00\nModel : Scanner\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+
+        let outcomes: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::Rename("2023-09-08_to_2023-09-10/080000".to_string()),
+                Outcome::Rename("2023-09-08_to_2023-09-10/101112".to_string()),
+                Outcome::Skip("filter".to_string()),
+            ]
+        );
+
+        let pattern = [Pattern::parse("{batch_count}_{?batch_first_date:dated}").unwrap()];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            vec![input("")],
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let outcome = &plans[0].as_ref().unwrap().outcome;
+        assert_eq!(outcome, &Outcome::Rename("1_".to_string()));
+    }
+
+    #[test]
+    fn test_patterns() {
+        let args = Args::parse_from([
+            "exif-rename",
+            "-e",
+            "-",
+            "-p",
+            "{Y}{m}{D}_{t}_{T2:nospace}",
+            "-p",
+            "{Y}{m}{D}_{t}",
+            "-p",
+            "{f|unknown}",
+        ]);
+        let inputs = vec![
+            input("Create Date : 2023:09:08 10:11:12\nModel : Canon EOS R5\n"),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+            input(""),
+        ];
+        let plans = plan(
+            &args,
+            &args.pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let outcomes: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::Rename("20230908_101112_CanonEOSR5".to_string()),
+                Outcome::Rename("20230908_101112".to_string()),
+                Outcome::Rename("unknown".to_string()),
+            ]
+        );
+
+        let pattern = [
+            Pattern::parse("{T2}").unwrap(),
+            Pattern::parse("{t}").unwrap(),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            vec![input("")],
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let err = plans[0].as_ref().unwrap_err().to_string();
+        assert!(err.contains("unknown variable 't'"), "{}", err);
+    }
+
+    #[test]
+    fn test_invisible() {
+        let args = Args::parse_from(["exif-rename", "-e", "-"]);
+        let pattern = [Pattern::parse("{T2}_{t}").unwrap()];
+        let dump = "Create Date : 2023:09:08 10:11:12\nModel : X\u{202E}gpj.exe\n";
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            vec![input(dump)],
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let plan = plans[0].as_ref().unwrap();
+        assert_eq!(plan.outcome, Outcome::Rename("Xgpj.exe_101112".to_string()));
+        assert_eq!(plan.warnings, vec![Warning::Invisible]);
+    }
+
+    #[test]
+    fn test_check_deterministic() {
+        let good = Pattern::parse("{Y}{m}{D}_{t}.{e}").unwrap();
+        let bad = Pattern::parse("{Y}/{?age_bucket:{import_date}}").unwrap();
+        assert!(check_deterministic(&[&good]).is_ok());
+        assert_eq!(
+            check_deterministic(&[&good, &bad]).unwrap_err(),
+            "{age_bucket} in '{Y}/{?age_bucket:{import_date}}' depends on the time of the run, \
+             which --deterministic forbids"
+        );
+    }
+
+    #[test]
+    fn test_bucket() {
+        let args = Args::parse_from(["exif-rename", "-e", "-"]);
+        let pattern = [Pattern::parse("{bucket:2}/{bucket}").unwrap()];
+        let inputs = vec![
+            input("Create Date : 2023:09:10 08:00:00\n"),
+            input(""),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+            input("Create Date : 2023:09:09 10:11:12\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let outcomes: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::Rename("0003-0004/3".to_string()),
+                Outcome::Rename("0003-0004/4".to_string()),
+                Outcome::Rename("0001-0002/1".to_string()),
+                Outcome::Rename("0001-0002/2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_burst_vars() {
+        let args = Args::parse_from(["exif-rename", "--collision-window", "1s"]);
+        let pattern = [Pattern::parse("{t}{burst}").unwrap()];
+        let inputs = vec![
+            input("Create Date : 2023:09:08 10:11:12\nSub Sec Time Original : 50\n"),
+            input("Create Date : 2023:09:08 10:11:12\nSub Sec Time Original : 10\n"),
+            input("Create Date : 2023:09:08 10:11:13\n"),
+            input("Create Date : 2023:09:08 10:20:00\n"),
+            input(""),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let names: Vec<_> = plans
+            .into_iter()
+            .map(|p| p.map(|p| p.outcome).ok())
+            .collect();
+        let rename = |name: &str| Some(Outcome::Rename(name.to_string()));
+        assert_eq!(
+            names,
+            vec![
+                rename("101112b"),
+                rename("101112a"),
+                rename("101113c"),
+                rename("102000"),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unique_vars() {
+        let args = Args::parse_from(["exif-rename", "-e", "-"]);
+        let pattern = [Pattern::parse("{t}{u}").unwrap()];
+        let inputs = vec![
+            input("Create Date : 2023:09:08 10:11:12\nSub Sec Time Original : 50\n"),
+            input("Create Date : 2023:09:08 10:11:12\nSub Sec Time Original : 10\n"),
+            input("Create Date : 2023:09:08 10:11:13\n"),
+            input("Create Date : 2023:09:08 10:11:11\n"),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let names: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        let rename = |name: &str| Outcome::Rename(name.to_string());
+        assert_eq!(
+            names,
+            vec![
+                rename("101112"),
+                rename("101112_1"),
+                rename("101113"),
+                rename("101111"),
+                rename("101112_2"),
+            ]
+        );
+
+        let args = Args::parse_from(["exif-rename", "-e", "-", "--unique-style", "letter"]);
+        let inputs = vec![
+            input("Create Date : 2023:09:08 10:11:12\n"),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let names: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(names, vec![rename("101112"), rename("101112a")]);
+    }
+
+    #[test]
+    fn test_counter_vars() {
+        let args = Args::parse_from(["exif-rename", "-e", "-", "--counter-start", "10"]);
+        let pattern = [Pattern::parse("{Y}-{m}-{D}_{n:04}").unwrap()];
+        let rules = [Rule {
+            extensions: vec![],
+            filters: vec!["Model=Scanner".parse().unwrap()],
+            after: None,
+            before: None,
+            action: Action::Skip,
+        }];
+        let inputs = vec![
+            input("Create Date : 2023:09:08 18:00:00\n"),
+            input("Create Date : 2023:09:08 10:11:12\nModel : Scanner\n"),
+            input("Create Date : 2023:09:09 09:00:00\n"),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &rules,
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let names: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        let rename = |name: &str| Outcome::Rename(name.to_string());
+        assert_eq!(
+            names,
+            vec![
+                rename("2023-09-08_0011"),
+                Outcome::Skip("rule 1".to_string()),
+                rename("2023-09-09_0012"),
+                rename("2023-09-08_0010"),
+            ]
+        );
+
+        let args = Args::parse_from(["exif-rename", "-e", "-", "--counter-step", "5"]);
+        let pattern = [Pattern::parse("{n}").unwrap()];
+        let inputs = vec![input(""), input("")];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let names: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(names, vec![rename("1"), rename("6")]);
+
+        let args = Args::parse_from(["exif-rename", "-e", "-", "--counter-per-dir"]);
+        let inputs = vec![
+            input("Directory : 2023-09 Paris\n"),
+            input("Directory : 2023-10 Rome\n"),
+            input("Directory : 2023-09 Paris\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let names: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(names, vec![rename("1"), rename("1"), rename("2")]);
+
+        let args = Args::parse_from(["exif-rename", "-e", "-", "--counter-per-day"]);
+        let mut counters = Counters::default();
+        counters.record("2023-09-08".to_string(), 214);
+        let inputs = vec![
+            input("Create Date : 2023:09:08 18:00:00\n"),
+            input("Create Date : 2023:09:09 09:00:00\n"),
+            input("Create Date : 2023:09:08 19:00:00\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &counters,
+        );
+        let names: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(names, vec![rename("215"), rename("1"), rename("216")]);
+
+        let inputs = || {
+            vec![
+                input("FileName : IMG_0002.JPG\nFileModifyDate : 2023:09:08 10:00:00+02:00\n"),
+                input("FileName : DSC_0001.JPG\nFileModifyDate : 2023:09:08 12:00:00+02:00\n"),
+                input("FileName : IMG_0001.JPG\nCreate Date : 2023:09:08 09:00:00\n"),
+            ]
+        };
+        let names = |sort: &str| {
+            let args = Args::parse_from(["exif-rename", "-e", "-", "--sort", sort]);
+            let plans = plan(
+                &args,
+                &pattern,
+                &[],
+                inputs(),
+                NaiveDateTime::default(),
+                &Counters::default(),
+            );
+            let names = plans.into_iter().map(|p| p.unwrap().outcome);
+            names.collect::<Vec<_>>()
+        };
+        assert_eq!(names("date"), vec![rename("2"), rename("3"), rename("1")]);
+        assert_eq!(names("name"), vec![rename("3"), rename("1"), rename("2")]);
+        assert_eq!(names("mtime"), vec![rename("1"), rename("2"), rename("3")]);
+    }
+
+    #[test]
+    fn test_group_vars() {
+        let args = Args::parse_from(["exif-rename", "-e", "-", "--group-by", "{Y}-{m}-{D}"]);
+        let pattern = [Pattern::parse("{group} ({group_count} photos)/{group_index}_{t}").unwrap()];
+        let inputs = vec![
+            input("Create Date : 2023:09:08 18:00:00\n"),
+            input("Create Date : 2023:09:09 09:00:00\n"),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+            input("Model : Scanner\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let names: Vec<_> = plans
+            .iter()
+            .map(|p| match p {
+                Ok(plan) => format!("{:?}", plan.outcome),
+                Err(e) => e.message.clone(),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "Rename(\"2023-09-08 (2 photos)/2_180000\")",
+                "Rename(\"2023-09-09 (1 photos)/1_090000\")",
+                "Rename(\"2023-09-08 (2 photos)/1_101112\")",
+                "unknown variable 'Y'",
+            ]
+        );
+
+        let pattern = [Pattern::parse("{group_first_time}-{group_last_time}").unwrap()];
+        let inputs = vec![
+            input("Create Date : 2023:09:08 18:00:00\n"),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let outcome = &plans[0].as_ref().unwrap().outcome;
+        assert_eq!(outcome, &Outcome::Rename("101112-180000".to_string()));
+    }
+
+    #[test]
+    fn test_prev_vars() {
+        let args = Args::parse_from(["exif-rename"]);
+        let pattern = [Pattern::parse("{Y}{m}{D}_{t}_{prev:slug}.{e}").unwrap()];
+        let exif = exif_vars("Create Date : 2023:09:08 10:11:12\n");
+        let inputs = vec![
+            Input {
+                file: Some("archive/20230908_091112_Paris trip.JPG".to_string()),
+                exif: exif.clone(),
+            },
+            Input {
+                file: Some("IMG_1234.JPG".to_string()),
+                exif: exif.clone(),
+            },
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let outcome = &plans[0].as_ref().unwrap().outcome;
+        let rename = |name: &str| Outcome::Rename(name.to_string());
+        assert_eq!(outcome, &rename("20230908_101112_Paris trip.JPG"));
+        let err = plans[1].as_ref().unwrap_err();
+        assert_eq!(err.message, "unknown variable 'prev:slug'");
+
+        let from = "{Y}{m}{D}_{slug}.{e}";
+        let args = Args::parse_from(["exif-rename", "--from-pattern", from]);
+        let pattern = [Pattern::parse("{Y}-{m}-{D}/{t}{?prev:slug:_{prev:slug}}.{e}").unwrap()];
+        let inputs = vec![
+            Input {
+                file: Some("20230908_Paris.JPG".to_string()),
+                exif: exif.clone(),
+            },
+            Input {
+                file: Some("IMG_1234.JPG".to_string()),
+                exif,
+            },
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let names: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(
+            names,
+            vec![
+                rename("2023-09-08/101112_Paris.JPG"),
+                rename("2023-09-08/101112.JPG")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_pattern() {
+        let from = "{Y}{m}{D}_{t}_{title}.{e}";
+        let args = Args::parse_from(["exif-rename", "--from-pattern", from]);
+        let pattern = [Pattern::parse("{Y}-{m}-{D}/{H}{M}_{title}_{r}.{e}").unwrap()];
+        let inputs = vec![
+            Input {
+                file: Some("archive/20230908_101112_Sunset 2.JPG".to_string()),
+                exif: Vars::new(),
+            },
+            Input {
+                file: Some("archive/20230908_101112_Sunset 2.JPG".to_string()),
+                exif: exif_vars("Create Date : 2024:01:02 03:04:05\n"),
+            },
+            Input {
+                file: Some("IMG_0042.JPG".to_string()),
+                exif: Vars::new(),
+            },
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+
+        let recovered = plans[0].as_ref().unwrap();
+        assert_eq!(
+            recovered.outcome,
+            Outcome::Rename("2023-09-08/1011_Sunset 2_2.JPG".to_string())
+        );
+        let source = "the date in the file name";
+        assert_eq!(recovered.warnings, vec![Warning::FallbackDate(source)]);
+        let tagged = plans[1].as_ref().unwrap();
+        assert_eq!(
+            tagged.outcome,
+            Outcome::Rename("2024-01-02/0304_Sunset 2_2.JPG".to_string())
+        );
+        assert!(tagged.warnings.is_empty());
+        assert!(plans[2].is_err());
+
+        let file = "2023/09/08_101112_x.jpg";
+        let from = Pattern::parse("{Y}/{m}/{D}_{t}_{title}.{e}").unwrap();
+        let vars = recover(&from, file).unwrap();
+        assert_eq!(
+            recovered_date(&vars).unwrap().to_string(),
+            "2023-09-08 10:11:12"
+        );
+        assert!(recover(&from, "a/2023/09/08.jpg").is_none());
+
+        let vars = recover(&Pattern::parse("{y}{m}{D}").unwrap(), "230908").unwrap();
+        assert_eq!(
+            recovered_date(&vars).unwrap().to_string(),
+            "2023-09-08 00:00:00"
+        );
+        let vars = recover(&Pattern::parse("{Y}{m}{D}").unwrap(), "20231308").unwrap();
+        assert_eq!(recovered_date(&vars), None);
+    }
+
+    #[test]
+    fn test_size_tier() {
+        let args = Args::parse_from(["exif-rename", "-e", "-", "--size-tiers", "1M,1G"]);
+        let pattern = [Pattern::parse("{?size_tier:{size_tier}/}{t}").unwrap()];
+        let inputs = vec![
+            input("Create Date : 2023:09:08 10:11:12\nFile Size : 4.2 MB\n"),
+            input("Create Date : 2023:09:08 10:11:12\nFile Size : 2.5 GB\n"),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let outcomes: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::Rename("medium/101112".to_string()),
+                Outcome::Rename("huge/101112".to_string()),
+                Outcome::Rename("101112".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gpx() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-gpx-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (gpx, cities) = (dir.join("track.gpx"), dir.join("cities.txt"));
+        let point = |lat, lon, time| {
+            format!(
+                "<trkpt lat=\"{}\" lon=\"{}\"><time>{}</time></trkpt>",
+                lat, lon, time
+            )
+        };
+        let track = point(37.5, 127.0, "2023-09-08T01:00:00Z")
+            + &point(37.6, 127.0, "2023-09-08T01:20:00Z");
+        std::fs::write(
+            &gpx,
+            format!("<gpx><trk><trkseg>{}</trkseg></trk></gpx>", track),
+        )
+        .unwrap();
+        std::fs::write(&cities, "Seoul\t37.566\t126.978\n").unwrap();
+        let args = Args::parse_from([
+            "exif-rename".as_ref(),
+            "--gpx".as_ref(),
+            gpx.as_os_str(),
+            "--cities".as_ref(),
+            cities.as_os_str(),
+        ]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let pattern = [Pattern::parse("{lat}_{lon}_{city}").unwrap()];
+        let inputs = vec![
+            input("Create Date : 2023:09:08 10:10:00\nOffset Time Original : +09:00\n"),
+            input("Create Date : 2023:09:08 10:10:00\nOffset Time Original : +09:00\nGPS Position : 35.1 129.0\nCity : Busan\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let outcomes: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::Rename("37.5500_127.0000_Seoul".to_string()),
+                Outcome::Rename("35.1000_129.0000_Busan".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dir_pattern() {
+        let args = Args::parse_from(["exif-rename", "-e", "-", "--dir-pattern", "{Y}/{?T2:{T2}}"]);
+        let pattern = [Pattern::parse("{m}{D}_{t}").unwrap()];
+        let inputs = vec![
+            input("Create Date : 2023:09:08 10:11:12\nModel : X100\n"),
+            input("Create Date : 2023:09:08 10:11:12\n"),
+        ];
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            inputs,
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let outcomes: Vec<_> = plans.into_iter().map(|p| p.unwrap().outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::Rename("2023/X100/0908_101112".to_string()),
+                Outcome::Rename("2023/0908_101112".to_string()),
+            ]
+        );
+
+        let plans = plan(
+            &args,
+            &pattern,
+            &[],
+            vec![input("")],
+            NaiveDateTime::default(),
+            &Counters::default(),
+        );
+        let err = plans[0].as_ref().unwrap_err().to_string();
+        assert!(err.contains("{m}{D}_{t}"), "{}", err);
+    }
+}
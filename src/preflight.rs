@@ -0,0 +1,272 @@
+//! Destination checks of `--dry-run`: every directory files would be renamed
+//! or copied into must exist or be creatable, and be writable, and each
+//! volume copies go to must have room for all of them, so a long import
+//! doesn't fail halfway through.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::size::format_size;
+
+/// The destinations of a planned run.
+#[derive(Debug, Default)]
+pub struct Preflight {
+    /// Bytes copied into each directory, 0 for renames.
+    dirs: BTreeMap<PathBuf, u64>,
+}
+
+/// A filesystem copies go to.
+#[derive(Debug, PartialEq)]
+pub struct Volume {
+    /// Mount point, or the deepest existing destination if it is unknown.
+    pub path: PathBuf,
+    /// Bytes the copies take.
+    pub needed: u64,
+    /// Bytes free for unprivileged users, if they can be read.
+    pub available: Option<u64>,
+}
+
+impl Volume {
+    pub fn fits(&self) -> bool {
+        self.available
+            .is_none_or(|available| self.needed <= available)
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), format_size(self.needed))?;
+        match self.available {
+            Some(available) if self.needed > available => write!(
+                f,
+                " of {} free, {} short",
+                format_size(available),
+                format_size(self.needed - available)
+            ),
+            Some(available) => write!(f, " of {} free", format_size(available)),
+            None => write!(f, ", free space unknown"),
+        }
+    }
+}
+
+/// Destinations that can't be written and the space copies need.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub problems: Vec<String>,
+    pub volumes: Vec<Volume>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty() && self.volumes.iter().all(Volume::fits)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for problem in &self.problems {
+            writeln!(f, "{}", problem)?;
+        }
+        let volumes: Vec<_> = self.volumes.iter().filter(|v| v.needed > 0).collect();
+        if !volumes.is_empty() {
+            writeln!(f, "space needed by copies")?;
+            for volume in volumes {
+                writeln!(f, "  {}", volume)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Preflight {
+    /// Records a file of `bytes` going into `dir`, with `bytes` 0 for a
+    /// rename, which takes no space.
+    pub fn add(&mut self, dir: &Path, bytes: u64) {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        *self.dirs.entry(dir.to_path_buf()).or_default() += bytes;
+    }
+
+    /// Checks the recorded destinations.
+    pub fn check(&self) -> Report {
+        let mut report = Report::default();
+        let mut volumes: Vec<(Option<u64>, Volume)> = Vec::new();
+        for (dir, &bytes) in &self.dirs {
+            let Some((existing, metadata)) = existing_ancestor(dir) else {
+                report
+                    .problems
+                    .push(format!("{}: no existing parent directory", dir.display()));
+                continue;
+            };
+            if !metadata.is_dir() {
+                report.problems.push(format!(
+                    "{}: can't create directory, {} is not a directory",
+                    dir.display(),
+                    existing.display()
+                ));
+                continue;
+            }
+            if !is_writable(existing, &metadata) {
+                let problem = if existing == dir {
+                    format!("{}: directory is not writable", dir.display())
+                } else {
+                    format!(
+                        "{}: can't create directory, {} is not writable",
+                        dir.display(),
+                        existing.display()
+                    )
+                };
+                report.problems.push(problem);
+                continue;
+            }
+            if bytes == 0 {
+                continue;
+            }
+            let device = device(&metadata);
+            match volumes
+                .iter_mut()
+                .find(|(d, v)| device.is_some() && *d == device || v.path == existing)
+            {
+                Some((_, volume)) => volume.needed += bytes,
+                None => volumes.push((
+                    device,
+                    Volume {
+                        path: mount_point(existing, &metadata),
+                        needed: bytes,
+                        available: available(existing).ok(),
+                    },
+                )),
+            }
+        }
+        report.volumes = volumes.into_iter().map(|(_, volume)| volume).collect();
+        report
+    }
+}
+
+/// The deepest existing ancestor of `dir`, `dir` itself included.
+fn existing_ancestor(dir: &Path) -> Option<(&Path, fs::Metadata)> {
+    dir.ancestors()
+        .filter(|path| !path.as_os_str().is_empty())
+        .find_map(|path| fs::metadata(path).ok().map(|metadata| (path, metadata)))
+        .or_else(|| {
+            // a relative path with no existing component lives in the current directory
+            let cwd = Path::new(".");
+            let metadata = fs::metadata(cwd).ok().filter(|_| dir.is_relative())?;
+            Some((cwd, metadata))
+        })
+}
+
+#[cfg(unix)]
+fn is_writable(path: &Path, _metadata: &fs::Metadata) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: the string is nul-terminated
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_writable(_path: &Path, metadata: &fs::Metadata) -> bool {
+    !metadata.permissions().readonly()
+}
+
+#[cfg(unix)]
+fn device(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// The topmost ancestor of `path` on the same filesystem.
+#[cfg(unix)]
+fn mount_point(path: &Path, metadata: &fs::Metadata) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    path.ancestors()
+        .take_while(|ancestor| fs::metadata(ancestor).is_ok_and(|m| m.dev() == metadata.dev()))
+        .last()
+        .unwrap_or(&path)
+        .to_path_buf()
+}
+
+#[cfg(not(unix))]
+fn mount_point(path: &Path, _metadata: &fs::Metadata) -> PathBuf {
+    path.to_path_buf()
+}
+
+#[cfg(unix)]
+fn available(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: the string is nul-terminated and stat is written by the call
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space is not known on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let dir =
+            std::env::temp_dir().join(format!("exif-rename-preflight-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("IMG_1234.JPG"), "").unwrap();
+
+        let mut preflight = Preflight::default();
+        preflight.add(&dir, 0);
+        preflight.add(&dir.join("2023/09"), 100);
+        preflight.add(&dir.join("2023/10"), 20);
+        let report = preflight.check();
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.volumes.len(), 1);
+        assert_eq!(report.volumes[0].needed, 120);
+
+        preflight.add(&dir.join("IMG_1234.JPG/2023"), 0);
+        preflight.add(&dir.join("huge"), u64::MAX / 2);
+        let report = preflight.check();
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.problems,
+            [format!(
+                "{}: can't create directory, {} is not a directory",
+                dir.join("IMG_1234.JPG/2023").display(),
+                dir.join("IMG_1234.JPG").display()
+            )]
+        );
+        #[cfg(unix)]
+        assert!(report.to_string().contains(" short\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+//! Progress reports and cancellation of long runs, for embedders showing a
+//! progress bar with a cancel button. The command line shows the progress
+//! of reading with `--progress` and cancels on Ctrl-C.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Cancels a run from another thread. Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn new() -> Cancel {
+        Cancel::default()
+    }
+
+    /// Stops the run before the next file; the file being renamed is
+    /// finished first, so the journal stays consistent.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    /// Reading the metadata of the files.
+    Read,
+    /// Renaming, copying or moving the files.
+    Execute,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Read => write!(f, "reading"),
+            Stage::Execute => write!(f, "renaming"),
+        }
+    }
+}
+
+/// Reported after each file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress<'a> {
+    pub stage: Stage,
+    /// Files done in this stage, including `path`.
+    pub done: usize,
+    pub total: usize,
+    pub path: &'a str,
+}
+
+impl fmt::Display for Progress<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}/{} {}",
+            self.stage, self.done, self.total, self.path
+        )
+    }
+}
+
+/// The callback progress is reported to, doing nothing by default.
+pub(crate) struct Callback(Box<dyn FnMut(&Progress) + Send>);
+
+impl Callback {
+    pub(crate) fn new(callback: impl FnMut(&Progress) + Send + 'static) -> Callback {
+        Callback(Box::new(callback))
+    }
+
+    pub(crate) fn report(&mut self, progress: &Progress) {
+        (self.0)(progress);
+    }
+}
+
+impl Default for Callback {
+    fn default() -> Callback {
+        Callback::new(|_| {})
+    }
+}
+
+impl fmt::Debug for Callback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Callback")
+    }
+}
@@ -0,0 +1,108 @@
+//! Read-only and immutable files are left in place unless `--force` is
+//! given, so protected originals aren't moved by accident. Copies leave the
+//! original untouched and are always made.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protection {
+    /// No write permission, or the read-only attribute on Windows.
+    ReadOnly,
+    /// `chattr +i` on Linux, `chflags uchg` on macOS.
+    Immutable,
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protection::ReadOnly => write!(f, "read-only file"),
+            Protection::Immutable => write!(f, "immutable file"),
+        }
+    }
+}
+
+/// Returns how `path` is protected, if it is.
+pub fn protection(path: &Path) -> io::Result<Option<Protection>> {
+    let metadata = fs::metadata(path)?;
+    if is_immutable(path, &metadata) {
+        return Ok(Some(Protection::Immutable));
+    }
+    Ok(metadata
+        .permissions()
+        .readonly()
+        .then_some(Protection::ReadOnly))
+}
+
+#[cfg(target_os = "linux")]
+fn is_immutable(path: &Path, _metadata: &fs::Metadata) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    /// `FS_IMMUTABLE_FL` from `linux/fs.h`.
+    const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    let mut flags: libc::c_int = 0;
+    // SAFETY: the descriptor is open and the kernel writes an int to flags;
+    // filesystems without flags fail with ENOTTY
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) };
+    result == 0 && flags & FS_IMMUTABLE_FL != 0
+}
+
+#[cfg(target_os = "macos")]
+fn is_immutable(_path: &Path, metadata: &fs::Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+
+    metadata.st_flags() & (libc::UF_IMMUTABLE | libc::SF_IMMUTABLE) != 0
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn is_immutable(_path: &Path, _metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Renames `path` to `new_path`. On Windows, the read-only attribute of a
+/// file renamed with `--force` is cleared for the rename and set again.
+pub fn rename(path: &Path, new_path: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        let mut permissions = fs::metadata(path)?.permissions();
+        if permissions.readonly() {
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(path, permissions.clone())?;
+            fs::rename(path, new_path)?;
+            permissions.set_readonly(true);
+            return fs::set_permissions(new_path, permissions);
+        }
+    }
+    fs::rename(path, new_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protection() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-protect-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("IMG_1234.JPG");
+        fs::write(&path, "").unwrap();
+        assert_eq!(protection(&path).unwrap(), None);
+
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+        assert_eq!(protection(&path).unwrap(), Some(Protection::ReadOnly));
+        let new_path = dir.join("20230908.JPG");
+        rename(&path, &new_path).unwrap();
+        assert!(fs::metadata(&new_path).unwrap().permissions().readonly());
+
+        assert!(protection(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
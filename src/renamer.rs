@@ -0,0 +1,853 @@
+//! [`Renamer`]: the new names of a batch of files, and carrying them out.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+use chrono::{Local, NaiveDateTime};
+
+use crate::config::Settings;
+#[cfg(feature = "convert")]
+use crate::convert;
+use crate::counters::Counters;
+#[cfg(unix)]
+use crate::owner;
+use crate::pattern::{Error, Pattern};
+use crate::plan::{self, Input, Outcome, Plan};
+use crate::preflight::{self, Preflight};
+use crate::progress::{Callback, Cancel, Progress, Stage};
+use crate::rules::{self, Rule};
+#[cfg(feature = "convert")]
+use crate::sheet;
+use crate::source::Dump;
+use crate::suffix::SuffixFormat;
+use crate::summary::Status;
+use crate::{
+    camera, conflict, dcf, glob, hook, journal, origin, protect, source, verify, Args, Vars,
+};
+
+/// Something [`Renamer::execute`] did with a file or noticed about it, for
+/// the caller to report.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Renamed or moved to the path, or would be with `--dry-run`.
+    Renamed(PathBuf),
+    /// Copied to the path, or would be with `--dry-run`.
+    Copied(PathBuf),
+    /// Converted into the path, for `--convert`.
+    Converted(PathBuf),
+    /// The new path of a file only described by its metadata.
+    Named(PathBuf),
+    /// Left alone, with why.
+    Skipped(String),
+    /// Something that went wrong without failing the file.
+    Warning(String),
+}
+
+/// Why [`Renamer::execute`] failed for a file.
+#[derive(Debug)]
+pub enum ExecuteError {
+    /// The run was cancelled before the file was touched.
+    Cancelled,
+    /// The file can't go where it was planned to, e.g. the name is taken
+    /// with `--on-conflict error` or isn't a DCF name.
+    Refused(String),
+    /// A step of renaming the file failed: what it was, and why.
+    Io(&'static str, io::Error),
+    /// `--paranoid` found the renamed file differs from the original. The
+    /// run stops, see [`Renamer::aborted`].
+    Verify(String),
+}
+
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecuteError::Cancelled => write!(f, "cancelled"),
+            ExecuteError::Refused(message) | ExecuteError::Verify(message) => {
+                write!(f, "{}", message)
+            }
+            ExecuteError::Io(step, e) => write!(f, "failed to {}: {}", step, e),
+        }
+    }
+}
+
+impl std::error::Error for ExecuteError {}
+
+/// What [`Renamer::execute`] did with a file: the events in the order they
+/// happened, including those before a failure, and how it ended.
+#[derive(Debug)]
+pub struct Executed {
+    pub events: Vec<Event>,
+    pub result: Result<Status, ExecuteError>,
+}
+
+/// Renames the files of a run by a pattern, given the command line
+/// options and the config.
+#[derive(Debug)]
+pub struct Renamer {
+    args: Args,
+    patterns: Vec<Pattern>,
+    rules: Vec<Rule>,
+    /// The file given with `--exif`.
+    dump: Option<Dump>,
+    /// The last `{n}` of each counter, from `--counters`.
+    counters: Counters,
+    /// Directory renamed files are moved or copied into.
+    target: Option<PathBuf>,
+    /// Shell commands run after each rename.
+    hooks: Vec<String>,
+    /// Variables named after exif tags, from the config.
+    aliases: BTreeMap<String, String>,
+    journal: Option<journal::Journal>,
+    /// The new paths of the run so far, for `--on-conflict`.
+    taken: HashSet<PathBuf>,
+    /// Destinations of a dry run, checked at the end.
+    preflight: Preflight,
+    /// When the run started.
+    now: NaiveDateTime,
+    /// Set when a file fails `--paranoid` verification.
+    aborted: bool,
+    cancel: Cancel,
+    progress: Callback,
+    /// Files of the last plan, and how many of them were executed.
+    total: Cell<usize>,
+    done: Cell<usize>,
+}
+
+impl Renamer {
+    /// Creates a renamer for a run with `args`, taking what they leave out
+    /// from the config `settings`, or built-in defaults by file type if
+    /// neither has a pattern. Fails if the patterns don't fit together or
+    /// the journal can't be opened.
+    pub fn new(mut args: Args, mut settings: Settings) -> Result<Renamer, String> {
+        if let Some(name) = &args.preset {
+            args.pattern = vec![settings.preset(name)?.clone()];
+        }
+        if args.filter.is_empty() {
+            args.filter = settings.filters;
+        }
+        if args.include.is_empty() {
+            args.include = settings.include;
+        }
+        if args.exclude.is_empty() {
+            args.exclude = settings.exclude;
+        }
+        if args.on_conflict.is_none() {
+            args.on_conflict = settings.on_conflict;
+        }
+        if args.age_buckets.is_none() {
+            args.age_buckets = settings.age_buckets;
+        }
+        if args.size_tiers.is_none() {
+            args.size_tiers = settings.size_tiers;
+        }
+        if args.dir_pattern.is_none() {
+            args.dir_pattern = settings.dir_pattern;
+        }
+        if args.use_exiftool && args.exiftool_path.is_none() {
+            args.exiftool_path = settings.exiftool;
+        }
+        // offsets given on the command line come first and win
+        args.sync_offsets.extend(
+            settings
+                .model_offsets
+                .into_iter()
+                .map(|(camera, offset)| camera::SyncOffset { camera, offset }),
+        );
+        let patterns = match (args.pattern.is_empty(), settings.pattern) {
+            (false, _) => args.pattern.clone(),
+            (true, Some(pattern)) => vec![pattern],
+            (true, None) => {
+                let (pattern, defaults) = rules::defaults();
+                settings.rules.extend(defaults);
+                vec![pattern]
+            }
+        };
+        if args.dir_pattern.is_some() && patterns.iter().any(|p| p.to_string().contains('/')) {
+            return Err(
+                "the pattern can't contain '/' when a directory pattern is given".to_string(),
+            );
+        }
+        if args
+            .from_pattern
+            .as_ref()
+            .is_some_and(|from| !from.is_reversible())
+        {
+            return Err("--from-pattern can't use conditionals or filters".to_string());
+        }
+        if args.deterministic {
+            let patterns = patterns
+                .iter()
+                .chain(&args.dir_pattern)
+                .chain(&args.group_by)
+                .chain(settings.rules.iter().filter_map(|rule| match &rule.action {
+                    rules::Action::Rename(pattern) => Some(pattern),
+                    _ => None,
+                }))
+                .collect::<Vec<_>>();
+            plan::check_deterministic(&patterns)?;
+        }
+
+        let dump = args
+            .exif
+            .as_deref()
+            .map(|path| Dump::load(path, args.exif_format))
+            .transpose()?;
+        let counters = match &args.counters {
+            Some(path) => Counters::load(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+            None => Counters::default(),
+        };
+        let now = Local::now().naive_local();
+        let journal = match args.journal.as_deref().filter(|_| !args.dry_run) {
+            Some(path) => Some(
+                journal::Journal::open(path, now)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+        Ok(Renamer {
+            target: args.copy_to.clone().or(settings.target),
+            args,
+            patterns,
+            rules: settings.rules,
+            hooks: settings.hooks,
+            aliases: settings.aliases,
+            journal,
+            taken: HashSet::new(),
+            preflight: Preflight::default(),
+            now,
+            dump,
+            counters,
+            aborted: false,
+            cancel: Cancel::default(),
+            progress: Callback::default(),
+            total: Cell::new(0),
+            done: Cell::new(0),
+        })
+    }
+
+    /// The options of the run, completed from the config.
+    pub fn args(&self) -> &Args {
+        &self.args
+    }
+
+    /// The metadata of `--exif`, if given.
+    pub fn dump(&self) -> Option<&Dump> {
+        self.dump.as_ref()
+    }
+
+    /// The patterns files are renamed with unless a rule decides otherwise,
+    /// in the order they are tried.
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    /// Returns true unless `--include` and `--exclude` leave `file` out.
+    pub fn selects(&self, file: &str) -> bool {
+        let Args {
+            include, exclude, ..
+        } = &self.args;
+        // the dumps of --sidecars describe files rather than being renamed
+        let ext = Path::new(file).extension().unwrap_or_default();
+        let sidecar = source::sidecar_exts(&self.args)
+            .iter()
+            .any(|sidecar| ext.eq_ignore_ascii_case(sidecar));
+        (include.is_empty() || include.iter().any(|glob| glob::matches_name(glob, file)))
+            && !exclude.iter().any(|glob| glob::matches_name(glob, file))
+            && !sidecar
+    }
+
+    /// Returns true if the run was cancelled or a file failed `--paranoid`
+    /// verification, after which it should stop.
+    pub fn aborted(&self) -> bool {
+        self.aborted || self.cancel.is_cancelled()
+    }
+
+    /// Lets `cancel` stop the run, see [`Renamer::read`] and
+    /// [`Renamer::execute`].
+    pub fn set_cancel(&mut self, cancel: Cancel) {
+        self.cancel = cancel;
+    }
+
+    /// Calls `callback` after each file is read or executed.
+    pub fn set_progress(&mut self, callback: impl FnMut(&Progress) + Send + 'static) {
+        self.progress = Callback::new(callback);
+    }
+
+    /// Reads the metadata of `files`, from `--exif` or exiftool if given. Once
+    /// cancelled, the files left aren't read and are left out.
+    pub fn read(&mut self, files: Vec<String>) -> Vec<(String, Result<Vars, String>)> {
+        let total = files.len();
+        let sidecars = source::sidecar_exts(&self.args);
+        let mut read = Vec::new();
+        for file in files {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            let exiftool = source::exiftool_program(&self.args);
+            let exif = source::read(self.dump.as_ref(), &sidecars, exiftool, Some(&file));
+            self.progress.report(&Progress {
+                stage: Stage::Read,
+                done: read.len() + 1,
+                total,
+                path: &file,
+            });
+            read.push((file, exif));
+        }
+        read
+    }
+
+    /// Reads the metadata of a file a journaled run renamed or copied from
+    /// `source` to `target`, from the sources [`Renamer::read`] uses, see
+    /// [`source::read_renamed`].
+    pub fn read_renamed(&self, source: &str, target: &str) -> Result<Vars, String> {
+        let sidecars = source::sidecar_exts(&self.args);
+        let exiftool = source::exiftool_program(&self.args);
+        source::read_renamed(self.dump.as_ref(), &sidecars, exiftool, source, target)
+    }
+
+    /// Checks the destinations of a dry run so far, see [`Preflight::check`].
+    pub fn preflight(&self) -> preflight::Report {
+        self.preflight.check()
+    }
+
+    /// Computes the new names of a batch of files, see [`plan::plan`].
+    /// Aliases get the values of their tags first.
+    pub fn plan(&self, mut inputs: Vec<Input>) -> Vec<Result<Plan, Error>> {
+        self.total.set(inputs.len());
+        self.done.set(0);
+        for input in &mut inputs {
+            for (alias, tag) in &self.aliases {
+                if let Some(value) = input.exif.get(tag).cloned() {
+                    input.exif.entry(alias.clone()).or_insert(value);
+                }
+            }
+        }
+        plan::plan(
+            &self.args,
+            &self.patterns,
+            &self.rules,
+            inputs,
+            self.now,
+            &self.counters,
+        )
+    }
+
+    /// Renames, copies or moves `file` as planned, or names its new path
+    /// if no file is given. Renamed files are moved into the target
+    /// directory if there is one, or copied with `--copy`, and hooks run
+    /// after each rename. Nothing is printed; what happened is returned as
+    /// events. Once cancelled, fails without touching the file.
+    pub fn execute(&mut self, file: Option<&str>, plan: Plan) -> Executed {
+        let mut events = Vec::new();
+        if self.cancel.is_cancelled() {
+            return Executed {
+                events,
+                result: Err(ExecuteError::Cancelled),
+            };
+        }
+        let result = self.execute_plan(file, plan, &mut events);
+        self.done.set(self.done.get() + 1);
+        self.progress.report(&Progress {
+            stage: Stage::Execute,
+            done: self.done.get(),
+            total: self.total.get().max(self.done.get()),
+            path: file.unwrap_or_default(),
+        });
+        Executed { events, result }
+    }
+
+    fn execute_plan(
+        &mut self,
+        file: Option<&str>,
+        plan: Plan,
+        events: &mut Vec<Event>,
+    ) -> Result<Status, ExecuteError> {
+        let Renamer {
+            args,
+            target,
+            hooks,
+            journal,
+            taken,
+            preflight,
+            now,
+            aborted,
+            counters,
+            ..
+        } = self;
+        let args = &*args;
+        let name = file
+            .or(plan.vars.get("FileName").map(String::as_str))
+            .unwrap_or_default();
+        let (dir, new_name, status) = match plan.outcome {
+            Outcome::Rename(new_name) => (target.clone(), new_name, Status::Renamed),
+            Outcome::CopyTo(dir, new_name) => (Some(dir), new_name, Status::Copied),
+            Outcome::Quarantine(dir) => {
+                let file_name = Path::new(name).file_name().unwrap_or_default();
+                (
+                    Some(dir),
+                    file_name.to_string_lossy().to_string(),
+                    Status::Moved,
+                )
+            }
+            Outcome::Skip(reason) => {
+                let status = Status::Skipped(reason);
+                events.push(Event::Skipped(status.to_string()));
+                return Ok(status);
+            }
+        };
+        // originals stay untouched in copy mode, even when quarantined
+        let status = match status {
+            Status::Renamed | Status::Moved if args.copy || args.copy_to.is_some() => {
+                Status::Copied
+            }
+            status => status,
+        };
+        let copy = status == Status::Copied;
+
+        let Some(file) = file else {
+            let new_path = match &dir {
+                Some(dir) => dir.join(&new_name),
+                None => PathBuf::from(&new_name),
+            };
+            events.push(Event::Named(new_path));
+            return Ok(Status::Printed);
+        };
+        let path = Path::new(file);
+        let new_path = match &dir {
+            Some(dir) => dir.join(&new_name),
+            None => path.with_file_name(&new_name),
+        };
+        if is_same_file(path, &new_path) {
+            // don't touch it, so repeated runs leave mtimes and hooks alone
+            return Ok(Status::Unchanged);
+        }
+        let Some(new_path) = conflict::resolve(
+            path,
+            new_path.clone(),
+            args.on_conflict.unwrap_or_default(),
+            &args
+                .suffix_format
+                .clone()
+                .unwrap_or_else(|| SuffixFormat::new("-")),
+            taken,
+        )
+        .map_err(ExecuteError::Refused)?
+        else {
+            let message = format!("skipped, {} exists", new_path.display());
+            events.push(Event::Skipped(message));
+            return Ok(Status::Skipped("--on-conflict".to_string()));
+        };
+        if is_same_file(path, &new_path) {
+            // numbered earlier, e.g. `x-1.jpg` for `x.jpg`
+            return Ok(Status::Unchanged);
+        }
+        if args.dcf {
+            dcf::check(&new_path).map_err(ExecuteError::Refused)?;
+        }
+        #[cfg(unix)]
+        if args.require_owner && !args.force {
+            check_owner(path)?;
+        }
+        if !copy && !args.force {
+            let protection =
+                protect::protection(path).map_err(|e| ExecuteError::Io("read file", e))?;
+            if let Some(protection) = protection {
+                let message = format!("skipped, {}, use --force to rename it anyway", protection);
+                events.push(Event::Skipped(message));
+                return Ok(Status::Skipped(protection.to_string()));
+            }
+        }
+        taken.insert(new_path.clone());
+        if args.dry_run {
+            // copies need room for the file, converted files are estimated by it
+            let bytes = if copy {
+                fs::metadata(path).map_or(0, |metadata| metadata.len())
+            } else {
+                0
+            };
+            preflight.add(new_path.parent().unwrap_or(Path::new("")), bytes);
+            events.push(if copy {
+                Event::Copied(new_path)
+            } else {
+                Event::Renamed(new_path)
+            });
+            return Ok(status);
+        }
+        #[cfg(unix)]
+        let created_dirs = new_path.parent().map(missing_dirs).unwrap_or_default();
+        if let Some(parent) = new_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| ExecuteError::Io("create directory", e))?;
+        }
+        #[cfg(feature = "convert")]
+        let new_path = convert_file(args, path, new_path, events)?;
+
+        let digest = if args.paranoid {
+            let digest = verify::sha256(path).map_err(|e| ExecuteError::Io("hash file", e))?;
+            Some(digest)
+        } else {
+            None
+        };
+        let action = if copy {
+            journal::Action::Copy
+        } else {
+            journal::Action::Rename
+        };
+        if let Some(journal) = journal {
+            journal
+                .begin(action, path, &new_path)
+                .map_err(|e| ExecuteError::Io("write journal", e))?;
+        }
+        if copy {
+            fs::copy(path, &new_path).map_err(|e| ExecuteError::Io("copy file", e))?;
+            events.push(Event::Copied(new_path.clone()));
+        } else {
+            protect::rename(path, &new_path).map_err(|e| ExecuteError::Io("rename file", e))?;
+            events.push(Event::Renamed(new_path.clone()));
+        }
+        if let Some(journal) = journal {
+            journal
+                .commit()
+                .map_err(|e| ExecuteError::Io("write journal", e))?;
+        }
+        let n = plan.vars.get("n").and_then(|n| n.parse().ok());
+        if let (Some(path), Some(n)) = (&args.counters, n) {
+            counters.record(plan::counter_scope(args, &plan.vars, plan.date), n);
+            if let Err(e) = counters.save(path) {
+                let message = format!("failed to write {}: {}", path.display(), e);
+                events.push(Event::Warning(message));
+            }
+        }
+        if let Some(digest) = digest {
+            if let Err(e) = verify::check(&new_path, &digest) {
+                *aborted = true;
+                return Err(ExecuteError::Verify(e));
+            }
+        }
+        if args.keep_origin {
+            let origin = origin::Origin {
+                run: journal::run_id(*now),
+                name: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+            };
+            if let Err(e) = origin::set(&new_path, &origin) {
+                let message = format!("failed to keep the original name: {}", e);
+                events.push(Event::Warning(message));
+            }
+        }
+        #[cfg(unix)]
+        if let Some(owner) = &args.chown_to {
+            for path in created_dirs.iter().chain([&new_path]) {
+                if let Err(e) = owner::chown(path, owner) {
+                    let message =
+                        format!("failed to change the owner of {}: {}", path.display(), e);
+                    events.push(Event::Warning(message));
+                }
+            }
+        }
+
+        for hook in hooks.iter() {
+            let message = match hook::run(hook, path, &new_path) {
+                Ok(status) if status.success() => continue,
+                Ok(status) => format!("hook '{}' {}", hook, status),
+                Err(e) => format!("hook '{}' failed: {}", hook, e),
+            };
+            events.push(Event::Warning(message));
+        }
+
+        #[cfg(feature = "convert")]
+        if let Some(sheet) = &args.contact_sheet {
+            if let Err(e) = sheet::add(sheet, &new_path) {
+                let message = format!("no contact sheet thumbnail: {}", e);
+                events.push(Event::Warning(message));
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// Fails if `path` is owned by another user, for `--require-owner`.
+#[cfg(unix)]
+fn check_owner(path: &Path) -> Result<(), ExecuteError> {
+    match owner::foreign_owner(path) {
+        Ok(None) => Ok(()),
+        Ok(Some(uid)) => Err(ExecuteError::Refused(format!(
+            "owned by {}, use --force to touch it anyway",
+            owner::user_name(uid)
+        ))),
+        Err(e) => Err(ExecuteError::Io("read the owner of the file", e)),
+    }
+}
+
+/// Returns the ancestors of `dir` that don't exist yet, outermost first.
+#[cfg(unix)]
+fn missing_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+/// Returns true if `new_path` is the existing file at `path` under the same
+/// name. A name differing only in case is a rename on case-insensitive
+/// filesystems too.
+fn is_same_file(path: &Path, new_path: &Path) -> bool {
+    if path.file_name() != new_path.file_name() {
+        return false;
+    }
+    match (fs::canonicalize(path), fs::canonicalize(new_path)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Transcodes `path` next to `new_path` if a `--convert` rule matches it and
+/// returns where the original should be renamed to.
+#[cfg(feature = "convert")]
+fn convert_file(
+    args: &Args,
+    path: &Path,
+    new_path: PathBuf,
+    events: &mut Vec<Event>,
+) -> Result<PathBuf, ExecuteError> {
+    let Some(conversion) = args.convert.iter().find(|c| c.matches(path)) else {
+        return Ok(new_path);
+    };
+
+    let converted = new_path.with_extension(&conversion.to);
+    convert::convert(path, &converted)
+        .map_err(|e| ExecuteError::Io("convert file", io::Error::other(e.to_string())))?;
+    events.push(Event::Converted(converted));
+
+    match &args.originals {
+        Some(dir) => {
+            let dir = new_path.with_file_name(dir);
+            fs::create_dir_all(&dir)
+                .map_err(|e| ExecuteError::Io("create originals directory", e))?;
+            Ok(dir.join(new_path.file_name().unwrap()))
+        }
+        None => Ok(new_path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::conflict::OnConflict;
+    use crate::Vars;
+
+    #[test]
+    fn test_is_same_file() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-same-{}", process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let path = dir.join("IMG_1234.JPG");
+        fs::write(&path, "").unwrap();
+
+        assert!(is_same_file(&path, &dir.join("sub/../IMG_1234.JPG")));
+        assert!(!is_same_file(&path, &dir.join("sub/IMG_1234.JPG")));
+        assert!(!is_same_file(&path, &dir.join("img_1234.jpg")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_execute_copy() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-copy-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("IMG_1234.JPG");
+        fs::write(&path, "").unwrap();
+        let plan = |outcome| Plan {
+            outcome,
+            warnings: Vec::new(),
+            vars: Vars::new(),
+            date: None,
+            candidates: Vec::new(),
+            rule: None,
+        };
+        let execute = |args: &[&str], outcome| {
+            let args = Args::parse_from([&["exif-rename"], args].concat());
+            let mut renamer = Renamer::new(args, Settings::default()).unwrap();
+            let executed = renamer.execute(path.to_str(), plan(outcome));
+            (executed.result.ok(), executed.events)
+        };
+
+        let rename = Outcome::Rename("20230908.JPG".to_string());
+        let copied = Event::Copied(dir.join("20230908.JPG"));
+        assert_eq!(
+            execute(&["--copy"], rename),
+            (Some(Status::Copied), vec![copied])
+        );
+        assert!(path.exists() && dir.join("20230908.JPG").exists());
+        let out = dir.join("out");
+        let copy_to = ["--copy-to", out.to_str().unwrap()];
+        let quarantine = Outcome::Quarantine(dir.join("odd"));
+        assert_eq!(execute(&copy_to, quarantine).0, Some(Status::Copied));
+        let rename = Outcome::Rename("2023/0908.JPG".to_string());
+        assert_eq!(execute(&copy_to, rename).0, Some(Status::Copied));
+        assert!(path.exists());
+        assert!(dir.join("odd/IMG_1234.JPG").exists() && out.join("2023/0908.JPG").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_execute_read_only() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-read-only-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("IMG_1234.JPG");
+        fs::write(&path, "").unwrap();
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+        let execute = |args: &[&str], new_name: &str| {
+            let args = Args::parse_from([&["exif-rename"], args].concat());
+            let mut renamer = Renamer::new(args, Settings::default()).unwrap();
+            let plan = Plan {
+                outcome: Outcome::Rename(new_name.to_string()),
+                warnings: Vec::new(),
+                vars: Vars::new(),
+                date: None,
+                candidates: Vec::new(),
+                rule: None,
+            };
+            let executed = renamer.execute(path.to_str(), plan);
+            (executed.result.ok(), executed.events)
+        };
+
+        let skipped = "skipped, read-only file, use --force to rename it anyway".to_string();
+        assert_eq!(
+            execute(&[], "a.JPG"),
+            (
+                Some(Status::Skipped("read-only file".to_string())),
+                vec![Event::Skipped(skipped)]
+            )
+        );
+        assert_eq!(execute(&["--copy"], "b.JPG").0, Some(Status::Copied));
+        assert!(path.exists());
+        assert_eq!(execute(&["--force"], "c.JPG").0, Some(Status::Renamed));
+        assert!(!path.exists() && dir.join("c.JPG").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_progress() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-progress-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let exif = dir.join("exif.txt");
+        fs::write(&exif, "Create Date : 2023:09:08 10:11:12\n").unwrap();
+        let files: Vec<String> = ["a.JPG", "b.JPG"]
+            .iter()
+            .map(|name| dir.join(name).to_string_lossy().to_string())
+            .collect();
+        for file in &files {
+            fs::write(file, "").unwrap();
+        }
+        let args = Args::parse_from([
+            "exif-rename",
+            "-e",
+            exif.to_str().unwrap(),
+            "-p",
+            "{t}_{f}.{e}",
+            "--dry-run",
+        ]);
+        let mut renamer = Renamer::new(args, Settings::default()).unwrap();
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        renamer.set_progress(move |progress| sink.lock().unwrap().push(progress.to_string()));
+        let cancel = Cancel::new();
+        renamer.set_cancel(cancel.clone());
+
+        let read = renamer.read(files.clone());
+        let inputs: Vec<Input> = read
+            .into_iter()
+            .map(|(file, exif)| Input {
+                file: Some(file),
+                exif: exif.unwrap(),
+            })
+            .collect();
+        let mut plans = renamer.plan(inputs).into_iter();
+        let plan = plans.next().unwrap().unwrap();
+        let executed = renamer.execute(Some(&files[0]), plan);
+        assert_eq!(executed.result.ok(), Some(Status::Renamed));
+        cancel.cancel();
+        assert!(renamer.aborted());
+        let plan = plans.next().unwrap().unwrap();
+        let executed = renamer.execute(Some(&files[1]), plan);
+        assert!(matches!(executed.result, Err(ExecuteError::Cancelled)));
+        assert!(executed.events.is_empty());
+        assert!(renamer.read(files.clone()).is_empty());
+        assert_eq!(
+            *reports.lock().unwrap(),
+            [
+                format!("reading 1/2 {}", files[0]),
+                format!("reading 2/2 {}", files[1]),
+                format!("renaming 1/2 {}", files[0]),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_new() {
+        let renamer = |args: &[&str]| {
+            let args = Args::parse_from([&["exif-rename"], args].concat());
+            Renamer::new(args, Settings::default()).map(|_| ())
+        };
+        assert_eq!(renamer(&["-p", "{Y}/{t}.{e}"]), Ok(()));
+        assert_eq!(
+            renamer(&["-p", "{Y}/{t}.{e}", "--dir-pattern", "{m}"]),
+            Err("the pattern can't contain '/' when a directory pattern is given".to_string())
+        );
+        assert!(renamer(&["--deterministic", "-p", "{import_Y}.{e}"]).is_err());
+        assert_eq!(
+            renamer(&["--preset", "travel"]),
+            Err("unknown preset 'travel'".to_string())
+        );
+
+        let settings = Settings {
+            pattern: Some(Pattern::parse("{Y}.{e}").unwrap()),
+            presets: [("travel".to_string(), Pattern::parse("{T2}.{e}").unwrap())].into(),
+            ..Settings::default()
+        };
+        let args = Args::parse_from(["exif-rename", "--preset", "travel"]);
+        let renamer = Renamer::new(args, settings).unwrap();
+        assert_eq!(renamer.patterns[0].to_string(), "{T2}.{e}");
+    }
+
+    #[test]
+    fn test_settings() {
+        let settings = Settings {
+            pattern: Some(Pattern::parse("{camera}.{e}").unwrap()),
+            include: vec!["*.jpg".to_string(), "*.heic".to_string()],
+            exclude: vec!["*_edit.*".to_string()],
+            on_conflict: Some(OnConflict::Skip),
+            aliases: [("camera".to_string(), "Model".to_string())].into(),
+            ..Settings::default()
+        };
+        let args = Args::parse_from(["exif-rename", "--exclude", "*.tmp.*"]);
+        let renamer = Renamer::new(args, settings.clone()).unwrap();
+        assert!(renamer.selects("IMG_1234_edit.jpg"));
+        assert!(!renamer.selects("IMG_1234.tmp.jpg"));
+        assert!(!renamer.selects("MVI_1234.MP4"));
+        assert_eq!(renamer.args().on_conflict, Some(OnConflict::Skip));
+
+        let args = Args::parse_from(["exif-rename", "--on-conflict", "number"]);
+        let renamer = Renamer::new(args, settings).unwrap();
+        assert!(renamer.selects("IMG_1234.HEIC"));
+        assert!(!renamer.selects("IMG_1234_edit.jpg"));
+        assert_eq!(renamer.args().on_conflict, Some(OnConflict::Number));
+        let exif = crate::dump::exif_vars("File Name : IMG_1234.JPG\nModel : X100V\n");
+        let plans = renamer.plan(vec![Input { file: None, exif }]);
+        let plan = plans.into_iter().next().unwrap().unwrap();
+        assert_eq!(plan.outcome, Outcome::Rename("X100V.JPG".to_string()));
+    }
+}
@@ -0,0 +1,173 @@
+//! Ordered `[[rules]]` from the config. The first rule matching a file
+//! decides what happens to it; files matching no rule are renamed with the
+//! default pattern.
+//!
+//! ```toml
+//! [[rules]]
+//! ext = ["mov", "mp4"]
+//! pattern = "videos/{Y}{m}{D}_{t}.{e}"
+//!
+//! [[rules]]
+//! filters = ["Model=iPhone 6"]
+//! before = "2015-01-01"
+//! quarantine = "/mnt/inbox/check"
+//! ```
+//!
+//! Without a configured pattern, the built-in defaults apply: videos get
+//! [`VIDEO_PATTERN`] and everything else [`PHOTO_PATTERN`].
+
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+
+use crate::filter::Filter;
+use crate::pattern::Pattern;
+use crate::Vars;
+
+/// Default pattern for photos, with the image number when the file name has one.
+pub const PHOTO_PATTERN: &str = "{Y}{m}{D}_{t}{?r:_{r}}.{e}";
+/// Default pattern for videos, which rarely carry a meaningful number.
+pub const VIDEO_PATTERN: &str = "{Y}{m}{D}_{t}.{e}";
+/// Extensions the video default applies to.
+pub const VIDEO_EXTENSIONS: &[&str] = &[
+    "3gp", "avi", "m2ts", "m4v", "mkv", "mov", "mp4", "mpg", "mts", "wmv",
+];
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// Lowercase extensions the file must have one of. Empty matches any.
+    pub extensions: Vec<String>,
+    pub filters: Vec<Filter>,
+    /// Captured on or after this date.
+    pub after: Option<NaiveDate>,
+    /// Captured before this date.
+    pub before: Option<NaiveDate>,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Rename with this pattern instead of the default one.
+    Rename(Pattern),
+    /// Leave the file untouched.
+    Skip,
+    /// Move the file into a directory, keeping its name.
+    Quarantine(PathBuf),
+    /// Copy the renamed file into a directory, leaving the original in place.
+    CopyTo(PathBuf),
+}
+
+impl Rule {
+    /// Returns true if the file with `vars`, captured at `date`, matches
+    /// every condition of the rule. Rules with a date range never match
+    /// files without a capture date.
+    pub fn matches(&self, vars: &Vars, date: Option<NaiveDate>) -> bool {
+        let ext = vars.get("e").map(|e| e.to_lowercase()).unwrap_or_default();
+        if !self.extensions.is_empty() && !self.extensions.contains(&ext) {
+            return false;
+        }
+        if !self.filters.iter().all(|f| f.matches(vars)) {
+            return false;
+        }
+        if self.after.is_none() && self.before.is_none() {
+            return true;
+        }
+        date.is_some_and(|date| {
+            self.after.is_none_or(|after| date >= after)
+                && self.before.is_none_or(|before| date < before)
+        })
+    }
+}
+
+/// Returns the built-in default pattern and the rules choosing the other
+/// defaults by extension, to be tried after the configured rules.
+pub fn defaults() -> (Pattern, Vec<Rule>) {
+    let video = Rule {
+        extensions: VIDEO_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+        filters: vec![],
+        after: None,
+        before: None,
+        action: Action::Rename(Pattern::parse(VIDEO_PATTERN).unwrap()),
+    };
+    (Pattern::parse(PHOTO_PATTERN).unwrap(), vec![video])
+}
+
+/// Returns the first rule matching the file with its index.
+pub fn first_match<'a>(
+    rules: &'a [Rule],
+    vars: &Vars,
+    date: Option<NaiveDate>,
+) -> Option<(usize, &'a Rule)> {
+    rules
+        .iter()
+        .enumerate()
+        .find(|(_, rule)| rule.matches(vars, date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(extensions: &[&str], filters: &[&str], action: Action) -> Rule {
+        Rule {
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            filters: filters.iter().map(|f| f.parse().unwrap()).collect(),
+            after: None,
+            before: None,
+            action,
+        }
+    }
+
+    #[test]
+    fn test_first_match() {
+        let mut dated = rule(&[], &[], Action::Quarantine(PathBuf::from("old")));
+        dated.before = NaiveDate::from_ymd_opt(2015, 1, 1);
+        let rules = vec![
+            rule(&["mov", "mp4"], &[], Action::Skip),
+            rule(
+                &[],
+                &["Model=iPhone 6"],
+                Action::CopyTo(PathBuf::from("phone")),
+            ),
+            dated,
+        ];
+
+        let mut vars = Vars::new();
+        vars.insert("e".to_string(), "MOV".to_string());
+        vars.insert("Model".to_string(), "iPhone 6".to_string());
+        let date = NaiveDate::from_ymd_opt(2014, 6, 1);
+        assert_eq!(first_match(&rules, &vars, date).unwrap().0, 0);
+
+        vars.insert("e".to_string(), "jpg".to_string());
+        let (index, rule) = first_match(&rules, &vars, date).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(rule.action, Action::CopyTo(PathBuf::from("phone")));
+
+        vars.remove("Model");
+        let (_, rule) = first_match(&rules, &vars, date).unwrap();
+        assert_eq!(rule.action, Action::Quarantine(PathBuf::from("old")));
+        assert!(first_match(&rules, &vars, None).is_none());
+        assert!(first_match(&rules, &vars, NaiveDate::from_ymd_opt(2015, 1, 1)).is_none());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let (pattern, rules) = defaults();
+        let mut vars = Vars::new();
+        for (name, value) in [("Y", "2023"), ("m", "09"), ("D", "08"), ("t", "101112")] {
+            vars.insert(name.to_string(), value.to_string());
+        }
+        vars.insert("e".to_string(), "JPG".to_string());
+        assert!(first_match(&rules, &vars, None).is_none());
+        assert_eq!(pattern.render(&vars).unwrap(), "20230908_101112.JPG");
+        vars.insert("r".to_string(), "1234".to_string());
+        assert_eq!(pattern.render(&vars).unwrap(), "20230908_101112_1234.JPG");
+
+        vars.insert("e".to_string(), "MOV".to_string());
+        let (_, rule) = first_match(&rules, &vars, None).unwrap();
+        let Action::Rename(video) = &rule.action else {
+            panic!("expected a rename rule");
+        };
+        assert_eq!(video.render(&vars).unwrap(), "20230908_101112.MOV");
+    }
+}
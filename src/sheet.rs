@@ -0,0 +1,114 @@
+//! `--contact-sheet DIR`: thumbnails of renamed files with an HTML grid
+//! (`index.html`) labeled with the new names, for clients to confirm what
+//! was ingested.
+//!
+//! Each run adds one thumbnail and rewrites the index from every thumbnail
+//! in the directory, so the sheet covers a whole import.
+
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::convert;
+
+/// Longest edge of a thumbnail in pixels.
+const THUMBNAIL_SIZE: u32 = 320;
+
+/// Writes a thumbnail of `path` into `dir` and rewrites the index. Returns
+/// the path of the index.
+pub fn add(dir: &Path, path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let name = path.file_name().ok_or("no file name")?.to_string_lossy();
+    let img = convert::decode(path)?.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    fs::create_dir_all(dir)?;
+    img.to_rgb8().save(dir.join(format!("{}.jpg", name)))?;
+    Ok(write_index(dir)?)
+}
+
+fn write_index(dir: &Path) -> io::Result<PathBuf> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.ends_with(".jpg"))
+        .collect();
+    names.sort();
+
+    let path = dir.join("index.html");
+    fs::write(&path, render_index(&names))?;
+    Ok(path)
+}
+
+/// Renders the grid for thumbnail file names, labeled without the `.jpg`
+/// added to the original name.
+fn render_index(thumbnails: &[String]) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n",
+        "<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Contact sheet</title>\n",
+        "<style>\n",
+        "body { font-family: sans-serif; display: flex; flex-wrap: wrap; gap: 12px; }\n",
+        "figure { margin: 0; width: 320px; text-align: center; }\n",
+        "figcaption { font-size: 12px; overflow-wrap: anywhere; }\n",
+        "</style>\n</head>\n<body>\n",
+    ));
+    for thumbnail in thumbnails {
+        let label = thumbnail.strip_suffix(".jpg").unwrap_or(thumbnail);
+        html.push_str(&format!(
+            "<figure><img src=\"{}\" alt=\"\"><figcaption>{}</figcaption></figure>\n",
+            escape(&url_encode(thumbnail)),
+            escape(label)
+        ));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Percent-encodes everything but unreserved characters, so names with
+/// `#`, `?` or `%` still resolve as relative paths.
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::DynamicImage;
+
+    #[test]
+    fn test_render_index() {
+        let html = render_index(&["a#1 <b>.JPG.jpg".to_string()]);
+        assert!(html.contains(
+            "<img src=\"a%231%20%3Cb%3E.JPG.jpg\" alt=\"\"><figcaption>a#1 &lt;b&gt;.JPG</figcaption>"
+        ));
+    }
+
+    #[test]
+    fn test_add() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-sheet-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("20230908_101112.png");
+        DynamicImage::new_rgba8(640, 480).save(&src).unwrap();
+
+        let sheet = dir.join("sheet");
+        let index = add(&sheet, &src).unwrap();
+        let thumbnail = image::open(sheet.join("20230908_101112.png.jpg")).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (320, 240));
+        let html = fs::read_to_string(index).unwrap();
+        assert!(html.contains("<figcaption>20230908_101112.png</figcaption>"));
+        assert!(add(&sheet, &dir.join("missing.png")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! `{size_tier}`: how large a file is, in tiers bounded by configurable
+//! thresholds, so large videos can be routed apart from small photos.
+
+use std::str::FromStr;
+
+/// Labels of the tiers below, between and above the thresholds.
+const LABELS: &[&str] = &["small", "medium", "large", "huge"];
+
+/// Ascending size thresholds in bytes, e.g. `10M,100M,1G` giving `small`,
+/// `medium`, `large` and `huge`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeTiers(Vec<u64>);
+
+impl Default for SizeTiers {
+    fn default() -> Self {
+        "10M,100M,1G".parse().unwrap()
+    }
+}
+
+impl FromStr for SizeTiers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let thresholds = s
+            .split(',')
+            .map(|t| {
+                parse_size(t.trim())
+                    .ok_or_else(|| format!("invalid size '{}', expected e.g. 500K, 10M or 1G", t))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        if thresholds.len() >= LABELS.len() {
+            return Err(format!(
+                "at most {} size thresholds are allowed: '{}'",
+                LABELS.len() - 1,
+                s
+            ));
+        }
+        if thresholds.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(format!("size thresholds must be ascending: '{}'", s));
+        }
+        Ok(SizeTiers(thresholds))
+    }
+}
+
+impl SizeTiers {
+    /// Returns the tier label for a file of `bytes`. Thresholds are lower
+    /// bounds of the next tier: with `10M`, a file of exactly 10M is `medium`.
+    pub fn tier(&self, bytes: u64) -> &'static str {
+        let index = self.0.iter().take_while(|&&t| bytes >= t).count();
+        if index == self.0.len() && !self.0.is_empty() {
+            // the tier above the last threshold is always `huge`
+            return LABELS[LABELS.len() - 1];
+        }
+        LABELS[index]
+    }
+}
+
+/// Parses `1234`, `500K`, `10M`, `1.5G` or `2T` (powers of 1024), with an
+/// optional `B` or `iB` suffix and any case.
+fn parse_size(s: &str) -> Option<u64> {
+    let upper = s.to_ascii_uppercase();
+    let s = upper.strip_suffix("IB").or(upper.strip_suffix('B'));
+    let s = s.unwrap_or(&upper).trim_end();
+    let (number, shift) = match s.chars().last()? {
+        'K' => (&s[..s.len() - 1], 10),
+        'M' => (&s[..s.len() - 1], 20),
+        'G' => (&s[..s.len() - 1], 30),
+        'T' => (&s[..s.len() - 1], 40),
+        _ => (s, 0),
+    };
+    let value: f64 = number.trim().parse().ok()?;
+    if !value.is_finite() || value < 0.0 {
+        return None;
+    }
+    Some((value * (1u64 << shift) as f64) as u64)
+}
+
+/// Parses the `File Size` exiftool prints, e.g. `4.2 MB`, `512 kB` or
+/// `1234 bytes`, or a plain number of bytes from `exiftool -n`.
+pub fn parse_file_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let s = s.strip_suffix("bytes").unwrap_or(s);
+    parse_size(s.trim())
+}
+
+/// Formats `bytes` for people, e.g. `512 B`, `4.5 MiB` or `1.2 TiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1 << 10 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_tiers() {
+        let tiers = SizeTiers::default();
+        assert_eq!(tiers.tier(0), "small");
+        assert_eq!(tiers.tier(4 << 20), "small");
+        assert_eq!(tiers.tier(10 << 20), "medium");
+        assert_eq!(tiers.tier(500 << 20), "large");
+        assert_eq!(tiers.tier(4 << 30), "huge");
+    }
+
+    #[test]
+    fn test_custom_tiers() {
+        let tiers: SizeTiers = "1.5G".parse().unwrap();
+        assert_eq!(tiers.tier(1 << 30), "small");
+        assert_eq!(tiers.tier(2 << 30), "huge");
+        let tiers: SizeTiers = "500KiB, 20MB".parse().unwrap();
+        assert_eq!(tiers.tier(600 << 10), "medium");
+        assert_eq!(tiers.tier(30 << 20), "huge");
+
+        assert!("1G,10M".parse::<SizeTiers>().is_err());
+        assert!("1K,2K,3K,4K".parse::<SizeTiers>().is_err());
+        assert!("10X".parse::<SizeTiers>().is_err());
+        assert!("-1M".parse::<SizeTiers>().is_err());
+    }
+
+    #[test]
+    fn test_parse_file_size() {
+        assert_eq!(parse_file_size("1234 bytes"), Some(1234));
+        assert_eq!(parse_file_size("512 kB"), Some(512 << 10));
+        assert_eq!(parse_file_size("4.5 MB"), Some(9 << 19));
+        assert_eq!(parse_file_size("4718592"), Some(4718592));
+        assert_eq!(parse_file_size("large"), None);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(9 << 19), "4.5 MiB");
+        assert_eq!(format_size(3 << 30), "3.0 GiB");
+        assert_eq!(format_size(5 << 50), "5120.0 TiB");
+    }
+}
@@ -0,0 +1,82 @@
+//! `--skiplist`: files known to fail, which are skipped without noise.
+//!
+//! One path per line, as given on the command line. Text after `#` is a
+//! comment, which `--update-skiplist` uses to record why a file was added.
+//! Paths are compared without `.` components and repeated separators, so
+//! `./scan.jpg` on the command line matches `scan.jpg` in the list.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Default)]
+pub struct Skiplist(HashSet<PathBuf>);
+
+impl Skiplist {
+    /// Returns true if `file` is listed.
+    pub fn contains(&self, file: &str) -> bool {
+        self.0.contains(&normalize(file))
+    }
+}
+
+/// Reads the skip list at `path`. A missing file is an empty list.
+pub fn load(path: &Path) -> io::Result<Skiplist> {
+    match fs::read_to_string(path) {
+        Ok(source) => Ok(parse(&source)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Skiplist::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn parse(source: &str) -> Skiplist {
+    let paths = source
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(path, _)| path).trim())
+        .filter(|path| !path.is_empty())
+        .map(normalize);
+    Skiplist(paths.collect())
+}
+
+/// Drops `.` components and repeated or trailing separators from `path`.
+fn normalize(path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+/// Appends `file` to the skip list at `path`, with `reason` as a comment.
+pub fn append(path: &Path, file: &str, reason: &str) -> io::Result<()> {
+    let mut out = OpenOptions::new().create(true).append(true).open(path)?;
+    let reason = reason.replace('\n', " ");
+    writeln!(out, "{}  # {}", file, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skiplist() {
+        let path = std::env::temp_dir().join(format!("exif-rename-skips-{}", std::process::id()));
+        assert!(load(&path).unwrap().0.is_empty());
+
+        append(&path, "a/IMG_0001.JPG", "unknown variable 'Y'").unwrap();
+        append(&path, "IMG_0002.JPG", "bad\ndump").unwrap();
+        let skips = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(skips.0.len(), 2);
+        assert!(skips.contains("a/IMG_0001.JPG"));
+        assert!(skips.contains("IMG_0002.JPG"));
+
+        let skips = parse("# comment\n\n  x.jpg  \n./b/./bad.JPG\n");
+        assert_eq!(skips.0.len(), 2);
+        assert!(skips.contains("./x.jpg"));
+        assert!(skips.contains("b//bad.JPG"));
+        assert!(!skips.contains("bad.JPG"));
+
+        let err = load(&std::env::temp_dir()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::IsADirectory);
+    }
+}
@@ -0,0 +1,32 @@
+//! `--sort`: the order counters such as `{n}` and `{bucket}` number the
+//! files of a run in, whatever order they were given or listed in, so files
+//! from several cards interleave by capture time.
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortKey {
+    /// Capture date, files without one last.
+    #[default]
+    Date,
+    /// File name, ignoring the directory.
+    Name,
+    /// Modification time of the file.
+    Mtime,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "date" => Ok(SortKey::Date),
+            "name" => Ok(SortKey::Name),
+            "mtime" => Ok(SortKey::Mtime),
+            _ => Err(format!(
+                "invalid sort order '{}', expected date, name or mtime",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,592 @@
+//! Where the metadata of a file comes from.
+//!
+//! `--exif` names an exiftool dump and always wins: the text output of
+//! `exiftool`, or with `--exif-format json` or `csv` the output of
+//! `exiftool -j` or `exiftool -csv`, which may describe several files.
+//! `--sidecars` reads the dump next to each file, if there is one.
+//! `--use-exiftool` runs exiftool on each file instead. Without any of them
+//! the metadata is read from the file itself: the EXIF tags of JPEG and
+//! TIFF-based raw files, and what the filesystem knows about it. Values use
+//! exiftool's tag names either way, so patterns don't depend on the source.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local};
+use serde_json::Value;
+
+use crate::dump::exif_vars;
+use crate::{exif, Args, Vars};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExifFormat {
+    /// `exiftool FILE`, `exiftool -s FILE` or `exiftool -G FILE`.
+    #[default]
+    Text,
+    /// `exiftool -j FILE...`.
+    Json,
+    /// `exiftool -csv FILE...`, e.g. of a whole directory.
+    Csv,
+}
+
+impl FromStr for ExifFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ExifFormat::Text),
+            "json" => Ok(ExifFormat::Json),
+            "csv" => Ok(ExifFormat::Csv),
+            _ => Err(format!(
+                "invalid format '{}', expected text, json or csv",
+                s
+            )),
+        }
+    }
+}
+
+/// The metadata of `--exif`, read once for all files.
+#[derive(Debug, Clone)]
+pub struct Dump {
+    path: String,
+    /// `SourceFile` and tags of each file described; a text dump has no
+    /// `SourceFile`.
+    entries: Vec<(Option<String>, Vars)>,
+}
+
+impl Dump {
+    /// Reads the dump at `path`, or standard input for `-`.
+    pub fn load(path: &str, format: ExifFormat) -> Result<Dump, String> {
+        let error =
+            |e: &dyn std::fmt::Display| format!("failed to read exif file '{}': {}", path, e);
+        let dump = match path {
+            "-" => io::read_to_string(io::stdin()),
+            _ => fs::read_to_string(path),
+        };
+        let dump = dump.map_err(|e| error(&e))?;
+        let entries = match format {
+            ExifFormat::Text => vec![(None, exif_vars(&dump))],
+            ExifFormat::Json => {
+                let objects = match serde_json::from_str(&dump).map_err(|e| error(&e))? {
+                    Value::Array(objects) => objects,
+                    object @ Value::Object(_) => vec![object],
+                    _ => return Err(error(&"expected the output of exiftool -j")),
+                };
+                objects
+                    .iter()
+                    .map(|object| {
+                        let mut vars = Vars::new();
+                        json_vars(object, &mut vars);
+                        (source_file(&vars), vars)
+                    })
+                    .collect()
+            }
+            ExifFormat::Csv => {
+                let records = csv_records(&dump).map_err(|e| error(&e))?;
+                let Some((header, rows)) = records.split_first() else {
+                    return Ok(Dump {
+                        path: path.to_string(),
+                        entries: Vec::new(),
+                    });
+                };
+                if !header.iter().any(|name| name == "SourceFile") {
+                    return Err(error(
+                        &"no SourceFile column, expected the output of exiftool -csv",
+                    ));
+                }
+                rows.iter()
+                    .map(|row| {
+                        let vars: Vars = header
+                            .iter()
+                            .zip(row)
+                            .filter(|(_, value)| !value.is_empty())
+                            .map(|(name, value)| {
+                                let name = name.rsplit(':').next().unwrap_or(name);
+                                (name.to_string(), value.clone())
+                            })
+                            .collect();
+                        (source_file(&vars), vars)
+                    })
+                    .collect()
+            }
+        };
+        Ok(Dump {
+            path: path.to_string(),
+            entries,
+        })
+    }
+
+    /// Returns true if the dump describes a single file without naming it,
+    /// as text dumps do, which is then taken for whichever file is renamed.
+    /// Entries with a `SourceFile` only describe that file.
+    pub fn is_single(&self) -> bool {
+        matches!(self.entries.as_slice(), [(None, _)])
+    }
+
+    /// The `SourceFile` of each file described, in order.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.entries
+            .iter()
+            .filter_map(|(source, _)| source.as_deref())
+    }
+
+    /// Returns the tags of `file`, those of a single unnamed file or else
+    /// found by its `SourceFile`: the same path, or else the only one of
+    /// the same name.
+    pub fn vars(&self, file: Option<&str>) -> Result<Vars, String> {
+        if let [(None, vars)] = self.entries.as_slice() {
+            return Ok(vars.clone());
+        }
+        let Some(file) = file else {
+            return Err(format!(
+                "{} describes files by their path, give the file",
+                self.path
+            ));
+        };
+        let sources = || {
+            self.entries
+                .iter()
+                .filter_map(|(source, vars)| Some((source.as_deref()?, vars)))
+        };
+        let path = Path::new(file);
+        let canonical = fs::canonicalize(path).ok();
+        let same = sources().find(|(source, _)| {
+            *source == file || canonical.is_some() && fs::canonicalize(source).ok() == canonical
+        });
+        let named: Vec<_> = sources()
+            .filter(|(source, _)| Path::new(source).file_name() == path.file_name())
+            .collect();
+        let only_named = || match named.as_slice() {
+            [entry] => Some(*entry),
+            _ => None,
+        };
+        same.or_else(only_named)
+            .map(|(_, vars)| vars.clone())
+            .ok_or_else(|| format!("'{}' is not described in {}", file, self.path))
+    }
+}
+
+/// The file an entry of a JSON or CSV dump describes. exiftool reading
+/// standard input writes `-`, which names no file.
+fn source_file(vars: &Vars) -> Option<String> {
+    vars.get("SourceFile")
+        .filter(|source| *source != "-")
+        .cloned()
+}
+
+/// Adds the tags of a JSON object written by `exiftool -j` to `vars`.
+/// Group prefixes of `-G` (`"EXIF:Make"`) are dropped and the groups of
+/// `-g` flattened; lists are joined with `, ` as in the text output.
+fn json_vars(object: &Value, vars: &mut Vars) {
+    let Value::Object(tags) = object else {
+        return;
+    };
+    for (key, value) in tags {
+        let key = key.rsplit(':').next().unwrap_or(key);
+        let text = match value {
+            Value::Object(_) => {
+                json_vars(value, vars);
+                continue;
+            }
+            Value::Array(items) => items.iter().map(json_text).collect::<Vec<_>>().join(", "),
+            _ => json_text(value),
+        };
+        if !key.is_empty() && !value.is_null() {
+            vars.insert(key.to_string(), text);
+        }
+    }
+}
+
+/// Splits `text` into records of fields, quoted with `"` as in RFC 4180.
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unclosed quote".to_string());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn json_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        _ => value.to_string(),
+    }
+}
+
+/// The exiftool `--use-exiftool` runs, if given.
+pub fn exiftool_program(args: &Args) -> Option<&Path> {
+    match &args.exiftool_path {
+        Some(path) => Some(path),
+        None => args.use_exiftool.then_some(Path::new("exiftool")),
+    }
+}
+
+/// The extensions of the dumps `--sidecars` looks for.
+pub fn sidecar_exts(args: &Args) -> Vec<String> {
+    if !args.sidecar_ext.is_empty() {
+        let exts = args.sidecar_ext.iter();
+        return exts
+            .map(|ext| ext.trim_start_matches('.').to_string())
+            .collect();
+    }
+    match args.sidecars {
+        true => vec!["txt".to_string(), "json".to_string()],
+        false => Vec::new(),
+    }
+}
+
+/// Returns the dump next to `file` with one of `exts`, `IMG_1234.JPG.txt`
+/// before `IMG_1234.txt` so a raw and a JPEG of the same shot can have
+/// their own.
+fn find_sidecar(file: &str, exts: &[String]) -> Option<PathBuf> {
+    let path = Path::new(file);
+    exts.iter()
+        .flat_map(|ext| {
+            let mut appended = path.as_os_str().to_os_string();
+            appended.push(format!(".{}", ext));
+            [appended.into(), path.with_extension(ext)]
+        })
+        .find(|sidecar: &PathBuf| sidecar.is_file())
+}
+
+/// Reads the metadata of `file` from the best available source: `dump`,
+/// else a sidecar dump with one of `sidecars`, else `exiftool` if given,
+/// else the file itself.
+pub fn read(
+    dump: Option<&Dump>,
+    sidecars: &[String],
+    exiftool: Option<&Path>,
+    file: Option<&str>,
+) -> Result<Vars, String> {
+    if let Some(dump) = dump {
+        return dump.vars(file);
+    }
+    let Some(file) = file else {
+        return Err("no metadata source, give a file or --exif".to_string());
+    };
+    if let Some(sidecar) = find_sidecar(file, sidecars) {
+        return read_sidecar(&sidecar, file);
+    }
+    match exiftool {
+        Some(program) => run_exiftool(program, file),
+        None => {
+            let path = Path::new(file);
+            let mut vars = file_vars(path)?;
+            let tags = exif::read(path).map_err(|e| format!("failed to read '{}': {}", file, e))?;
+            vars.extend(tags);
+            Ok(vars)
+        }
+    }
+}
+
+/// Reads the metadata of a file renamed from `source` to `target` from the
+/// same sources as [`read`]: a dump or sidecar describes the file under
+/// its old path, exiftool and the file itself are read under the new one.
+pub fn read_renamed(
+    dump: Option<&Dump>,
+    sidecars: &[String],
+    exiftool: Option<&Path>,
+    source: &str,
+    target: &str,
+) -> Result<Vars, String> {
+    if let Some(dump) = dump {
+        return dump.vars(Some(source));
+    }
+    if let Some(sidecar) = find_sidecar(source, sidecars) {
+        return read_sidecar(&sidecar, source);
+    }
+    read(None, &[], exiftool, Some(target))
+}
+
+/// Reads the metadata of `file` from the dump `sidecar`, in the format of
+/// its extension.
+fn read_sidecar(sidecar: &Path, file: &str) -> Result<Vars, String> {
+    let format = match sidecar.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("json") => ExifFormat::Json,
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => ExifFormat::Csv,
+        _ => ExifFormat::Text,
+    };
+    Dump::load(&sidecar.to_string_lossy(), format)?.vars(Some(file))
+}
+
+/// Reads the metadata of `file` from the output of `exiftool -s`.
+fn run_exiftool(program: &Path, file: &str) -> Result<Vars, String> {
+    // exiftool would take a name starting with '-' for an option
+    let arg = if file.starts_with('-') {
+        Path::new(".").join(file)
+    } else {
+        Path::new(file).to_path_buf()
+    };
+    let output = Command::new(program)
+        .arg("-s")
+        .arg(&arg)
+        .output()
+        .map_err(|e| {
+            format!(
+                "failed to run {}: {}, install exiftool or give --exiftool-path",
+                program.display(),
+                e
+            )
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.lines().next().unwrap_or("exited with an error");
+        return Err(format!("failed to read '{}': {}", file, message.trim()));
+    }
+    Ok(exif_vars(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// The tags exiftool derives from the filesystem, by their `exiftool -s` names.
+fn file_vars(path: &Path) -> Result<Vars, String> {
+    let metadata =
+        fs::metadata(path).map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+    let mut vars = Vars::new();
+    if let Some(name) = path.file_name() {
+        vars.insert("FileName".to_string(), name.to_string_lossy().to_string());
+    }
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let dir = dir.unwrap_or(Path::new("."));
+    vars.insert("Directory".to_string(), dir.to_string_lossy().to_string());
+    if let Ok(modified) = metadata.modified() {
+        let modified = DateTime::<Local>::from(modified);
+        vars.insert(
+            "FileModifyDate".to_string(),
+            modified.format("%Y:%m:%d %H:%M:%S%:z").to_string(),
+        );
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-source-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dump = dir.join("exif.txt");
+        let file = dir.join("IMG_1234.JPG");
+        fs::write(&dump, "Create Date : 2023:09:08 10:11:12\n").unwrap();
+        fs::write(&file, "").unwrap();
+        let (dump, file) = (dump.to_str().unwrap(), file.to_str().unwrap());
+
+        let dump = Dump::load(dump, ExifFormat::Text).unwrap();
+        let vars = read(Some(&dump), &[], None, Some(file)).unwrap();
+        assert_eq!(vars["CreateDate"], "2023:09:08 10:11:12");
+        assert!(!vars.contains_key("FileName"));
+
+        let vars = read(None, &[], None, Some(file)).unwrap();
+        assert_eq!(vars["FileName"], "IMG_1234.JPG");
+        assert_eq!(vars["Directory"], dir.to_str().unwrap());
+        assert!(vars.contains_key("FileModifyDate"));
+
+        assert!(read(None, &[], None, Some("missing.jpg")).is_err());
+        assert!(read(None, &[], None, None).is_err());
+        assert!(Dump::load("missing.txt", ExifFormat::Text).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sidecars() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-sidecars-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "IMG_1234.JPG",
+            "IMG_1234.CR3",
+            "IMG_1235.JPG",
+            "IMG_1236.JPG",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        fs::write(
+            dir.join("IMG_1234.txt"),
+            "Create Date : 2023:09:08 10:11:12\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("IMG_1234.JPG.txt"),
+            "Create Date : 2023:09:08 10:11:13\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("IMG_1235.json"),
+            r#"[{"SourceFile": "IMG_1235.JPG", "CreateDate": "2023:09:08 10:15:30"}]"#,
+        )
+        .unwrap();
+        let read = |name: &str| {
+            let file = dir.join(name);
+            let sidecars = ["txt".to_string(), "json".to_string()];
+            read(None, &sidecars, None, Some(file.to_str().unwrap())).unwrap()
+        };
+
+        assert_eq!(read("IMG_1234.JPG")["CreateDate"], "2023:09:08 10:11:13");
+        assert_eq!(read("IMG_1234.CR3")["CreateDate"], "2023:09:08 10:11:12");
+        assert_eq!(read("IMG_1235.JPG")["CreateDate"], "2023:09:08 10:15:30");
+        assert!(!read("IMG_1236.JPG").contains_key("CreateDate"));
+
+        // renamed away from its sidecar, which still has the old name
+        let (old, new) = (dir.join("IMG_1234.JPG"), dir.join("20230908.JPG"));
+        fs::rename(&old, &new).unwrap();
+        let sidecars = ["txt".to_string()];
+        let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+        let vars = read_renamed(None, &sidecars, None, old, new).unwrap();
+        assert_eq!(vars["CreateDate"], "2023:09:08 10:11:13");
+        let vars = read_renamed(None, &[], None, old, new).unwrap();
+        assert_eq!(vars["FileName"], "20230908.JPG");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_json() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-json-{}", std::process::id()));
+        fs::create_dir_all(dir.join("b")).unwrap();
+        let json = dir.join("exif.json");
+        fs::write(
+            &json,
+            r#"[{
+  "SourceFile": "a/IMG_1234.JPG",
+  "EXIF:CreateDate": "2023:09:08 10:11:12",
+  "FNumber": 2.8,
+  "Keywords": ["beach", "sunset"]
+},
+{
+  "SourceFile": "b/IMG_1234.JPG",
+  "EXIF": {"CreateDate": "2023:09:09 10:11:12"}
+},
+{
+  "SourceFile": "b/IMG_1235.JPG",
+  "CreateDate": "2023:09:10 10:11:12"
+}]"#,
+        )
+        .unwrap();
+        let dump = Dump::load(json.to_str().unwrap(), ExifFormat::Json).unwrap();
+        assert!(!dump.is_single());
+
+        let vars = dump.vars(Some("a/IMG_1234.JPG")).unwrap();
+        assert_eq!(vars["CreateDate"], "2023:09:08 10:11:12");
+        assert_eq!(vars["FNumber"], "2.8");
+        assert_eq!(vars["Keywords"], "beach, sunset");
+        let vars = dump.vars(Some("b/IMG_1234.JPG")).unwrap();
+        assert_eq!(vars["CreateDate"], "2023:09:09 10:11:12");
+        let vars = dump.vars(Some("/photos/IMG_1235.JPG")).unwrap();
+        assert_eq!(vars["CreateDate"], "2023:09:10 10:11:12");
+        assert!(dump.vars(Some("/photos/IMG_1234.JPG")).is_err());
+        assert!(dump.vars(Some("IMG_9999.JPG")).is_err());
+        assert!(dump.vars(None).is_err());
+
+        // a single entry still only describes its SourceFile
+        fs::write(&json, r#"[{"SourceFile": "x.jpg", "ISO": 100}]"#).unwrap();
+        let dump = Dump::load(json.to_str().unwrap(), ExifFormat::Json).unwrap();
+        assert!(!dump.is_single());
+        assert_eq!(dump.files().collect::<Vec<_>>(), ["x.jpg"]);
+        assert_eq!(dump.vars(Some("b/x.jpg")).unwrap()["ISO"], "100");
+        assert!(dump.vars(Some("IMG_1234.JPG")).is_err());
+        assert!(dump.vars(None).is_err());
+        // exiftool -j reading standard input names no file
+        fs::write(&json, r#"[{"SourceFile": "-", "ISO": 100}]"#).unwrap();
+        let dump = Dump::load(json.to_str().unwrap(), ExifFormat::Json).unwrap();
+        assert!(dump.is_single());
+        assert_eq!(dump.vars(Some("IMG_1234.JPG")).unwrap()["ISO"], "100");
+        fs::write(&json, "Create Date : 2023:09:08 10:11:12").unwrap();
+        assert!(Dump::load(json.to_str().unwrap(), ExifFormat::Json).is_err());
+        assert_eq!("json".parse(), Ok(ExifFormat::Json));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_csv() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-csv-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("meta.csv");
+        fs::write(
+            &csv,
+            "SourceFile,EXIF:CreateDate,Keywords,Title\r\n\
+             DCIM/IMG_1234.JPG,2023:09:08 10:11:12,\"beach, sunset\",\"say \"\"hi\"\"\nthere\"\r\n\
+             DCIM/IMG_1235.JPG,2023:09:08 10:15:30,,\r\n",
+        )
+        .unwrap();
+        let dump = Dump::load(csv.to_str().unwrap(), ExifFormat::Csv).unwrap();
+        assert_eq!(
+            dump.files().collect::<Vec<_>>(),
+            ["DCIM/IMG_1234.JPG", "DCIM/IMG_1235.JPG"]
+        );
+        let vars = dump.vars(Some("DCIM/IMG_1234.JPG")).unwrap();
+        assert_eq!(vars["CreateDate"], "2023:09:08 10:11:12");
+        assert_eq!(vars["Keywords"], "beach, sunset");
+        assert_eq!(vars["Title"], "say \"hi\"\nthere");
+        let vars = dump.vars(Some("DCIM/IMG_1235.JPG")).unwrap();
+        assert!(!vars.contains_key("Keywords"));
+
+        fs::write(&csv, "FileName,CreateDate\nIMG_1234.JPG,2023:09:08\n").unwrap();
+        assert!(Dump::load(csv.to_str().unwrap(), ExifFormat::Csv).is_err());
+        fs::write(&csv, "SourceFile,Title\na.jpg,\"open\n").unwrap();
+        assert!(Dump::load(csv.to_str().unwrap(), ExifFormat::Csv).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exiftool() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("exif-rename-exiftool-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("exiftool");
+        fs::write(
+            &program,
+            "#!/bin/sh\n\
+             [ -e \"$2\" ] || { echo \"Error: File not found - $2\" >&2; exit 1; }\n\
+             echo \"FileName                        : $(basename \"$2\")\"\n\
+             echo 'CreateDate                      : 2023:09:08 10:11:12'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+        let file = dir.join("-clip.mts");
+        fs::write(&file, "").unwrap();
+
+        let vars = read(None, &[], Some(&program), Some(file.to_str().unwrap())).unwrap();
+        assert_eq!(vars["FileName"], "-clip.mts");
+        assert_eq!(vars["CreateDate"], "2023:09:08 10:11:12");
+        let err = read(None, &[], Some(&program), Some("missing.mts")).unwrap_err();
+        assert_eq!(
+            err,
+            "failed to read 'missing.mts': Error: File not found - missing.mts"
+        );
+        let err = read(None, &[], Some(&dir.join("none")), Some("a.mts")).unwrap_err();
+        assert!(
+            err.ends_with("install exiftool or give --exiftool-path"),
+            "{}",
+            err
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+//! `stats`: what a collection holds before anything is renamed, by camera,
+//! month and extension, and how many files lack the metadata a pattern
+//! would need, to help design one.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use chrono::TimeDelta;
+
+use crate::dates::DateSource;
+use crate::summary::Counts;
+use crate::{capture_date, Vars};
+
+/// Widest bar of the month histogram.
+const BAR_WIDTH: usize = 40;
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    files: usize,
+    cameras: Counts,
+    /// Files by capture month, `YYYY-MM`.
+    months: BTreeMap<String, usize>,
+    extensions: Counts,
+    missing: Counts,
+}
+
+impl Stats {
+    /// Counts `file` with tags `exif`, `None` if they couldn't be read.
+    pub fn add(&mut self, file: &str, exif: Option<&Vars>, date_source: Option<&DateSource>) {
+        self.files += 1;
+        let ext = Path::new(file)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        self.extensions
+            .add(ext.unwrap_or_else(|| "(none)".to_string()), 1);
+        let Some(exif) = exif else {
+            self.missing.add("unreadable".to_string(), 1);
+            return;
+        };
+        match exif
+            .get("Model")
+            .map(|m| m.trim())
+            .filter(|m| !m.is_empty())
+        {
+            Some(model) => self.cameras.add(model.to_string(), 1),
+            None => self.missing.add("no camera model".to_string(), 1),
+        }
+        match capture_date(exif, TimeDelta::zero(), date_source) {
+            Some(date) => {
+                *self
+                    .months
+                    .entry(date.format("%Y-%m").to_string())
+                    .or_default() += 1
+            }
+            None => self.missing.add("no capture date".to_string(), 1),
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.files.to_string().len();
+        writeln!(f, "{} files", self.files)?;
+        let sections = [
+            ("by camera", &self.cameras),
+            ("by extension", &self.extensions),
+            ("missing", &self.missing),
+        ];
+        for (title, counts) in sections {
+            if counts.0.is_empty() {
+                continue;
+            }
+            writeln!(f, "{}", title)?;
+            for (label, n) in counts.sorted() {
+                writeln!(f, "  {:>width$} {}", n, label)?;
+            }
+        }
+        if let Some(&max) = self.months.values().max() {
+            writeln!(f, "by month")?;
+            for (month, &n) in &self.months {
+                // every month with files gets at least one mark
+                let bar = "#".repeat((n * BAR_WIDTH).div_ceil(max));
+                writeln!(f, "  {} {:>width$} {}", month, n, bar)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::exif_vars;
+
+    #[test]
+    fn test_stats() {
+        let mut stats = Stats::default();
+        let canon = exif_vars("Model : Canon EOS R5\nCreate Date : 2023:09:08 10:11:12\n");
+        for _ in 0..4 {
+            stats.add("card/IMG_1234.JPG", Some(&canon), None);
+        }
+        let scan = exif_vars("File Modification Date/Time : 2023:08:01 09:00:00+02:00\n");
+        stats.add("scans/scan_0001.png", Some(&scan), None);
+        stats.add("card/MVI_0001.MOV", None, None);
+
+        let expected = [
+            "6 files",
+            "by camera",
+            "  4 Canon EOS R5",
+            "by extension",
+            "  4 jpg",
+            "  1 png",
+            "  1 mov",
+            "missing",
+            "  1 no camera model",
+            "  1 no capture date",
+            "  1 unreadable",
+            "by month",
+            &format!("  2023-09 4 {}", "#".repeat(40)),
+        ];
+        assert_eq!(stats.to_string().lines().collect::<Vec<_>>(), expected);
+
+        let mtime: DateSource = "CreateDate>mtime".parse().unwrap();
+        stats.add("scans/scan_0002.png", Some(&scan), Some(&mtime));
+        assert!(stats.to_string().ends_with(
+            "  2023-08 1 ##########\n  2023-09 4 ########################################\n"
+        ));
+    }
+}
@@ -0,0 +1,80 @@
+//! `--suffix-format`: how the numbers telling apart files with the same
+//! name are written, by `--on-conflict number` and `{u}`. e.g. `-{n:02}`
+//! for `-01`, `-02`, ... or `" (copy {n})"`.
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuffixFormat {
+    before: String,
+    /// Digits the number is zero-padded to.
+    width: usize,
+    after: String,
+}
+
+impl SuffixFormat {
+    /// A format writing the number after `before`, unpadded.
+    pub fn new(before: &str) -> SuffixFormat {
+        SuffixFormat {
+            before: before.to_string(),
+            width: 0,
+            after: String::new(),
+        }
+    }
+
+    pub fn format(&self, n: usize) -> String {
+        format!(
+            "{}{:0width$}{}",
+            self.before,
+            n,
+            self.after,
+            width = self.width
+        )
+    }
+}
+
+impl FromStr for SuffixFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid suffix format '{}', expected one {{n}} or {{n:02}} and no '/'",
+                s
+            )
+        };
+        let (before, rest) = s.split_once("{n").ok_or_else(invalid)?;
+        let (spec, after) = rest.split_once('}').ok_or_else(invalid)?;
+        let width = match spec.strip_prefix(':') {
+            Some(width) if width.starts_with('0') => width.parse().map_err(|_| invalid())?,
+            None if spec.is_empty() => 0,
+            _ => return Err(invalid()),
+        };
+        if after.contains("{n") || s.contains('/') {
+            return Err(invalid());
+        }
+        Ok(SuffixFormat {
+            before: before.to_string(),
+            width,
+            after: after.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let format = |s: &str, n| s.parse::<SuffixFormat>().map(|f| f.format(n));
+        assert_eq!(format("-{n}", 3), Ok("-3".to_string()));
+        assert_eq!(format("-{n:02}", 3), Ok("-03".to_string()));
+        assert_eq!(format(" (copy {n})", 1), Ok(" (copy 1)".to_string()));
+        assert_eq!(format("-{n:03}", 1234), Ok("-1234".to_string()));
+        assert!(format("-n", 1).is_err());
+        assert!(format("-{n:2}", 1).is_err());
+        assert!(format("-{n}-{n}", 1).is_err());
+        assert!(format("/{n}", 1).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! `--summary`: counts of what happened to the files of a run, which rules
+//! matched and which warnings fired, to spot systemic metadata problems
+//! such as a camera without a clock at a glance.
+
+use std::fmt;
+
+use crate::plan::Plan;
+
+/// What happened to a file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    /// The new name was printed, no file was given.
+    Printed,
+    /// The file already has its new name.
+    Unchanged,
+    Renamed,
+    Copied,
+    /// Moved into a quarantine directory.
+    Moved,
+    /// Left untouched, with the reason.
+    Skipped(String),
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Printed => write!(f, "printed"),
+            Status::Unchanged => write!(f, "unchanged"),
+            Status::Renamed => write!(f, "renamed"),
+            Status::Copied => write!(f, "copied"),
+            Status::Moved => write!(f, "moved"),
+            Status::Skipped(reason) => write!(f, "skipped by {}", reason),
+            Status::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Counts by label, in order of first appearance.
+#[derive(Debug, Default)]
+pub(crate) struct Counts(pub(crate) Vec<(String, usize)>);
+
+impl Counts {
+    pub(crate) fn add(&mut self, label: String, n: usize) {
+        match self.0.iter_mut().find(|(l, _)| *l == label) {
+            Some((_, count)) => *count += n,
+            None => self.0.push((label, n)),
+        }
+    }
+
+    /// Largest count first, ties in order of first appearance.
+    pub(crate) fn sorted(&self) -> Vec<&(String, usize)> {
+        let mut counts: Vec<_> = self.0.iter().collect();
+        counts.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        counts
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    statuses: Counts,
+    rules: Counts,
+    warnings: Counts,
+}
+
+impl Summary {
+    /// Counts `n` files with `status`.
+    pub fn add(&mut self, status: &Status, n: usize) {
+        if n > 0 {
+            self.statuses.add(status.to_string(), n);
+        }
+    }
+
+    /// Counts the rule and warnings of a planned file.
+    pub fn add_plan(&mut self, plan: &Plan) {
+        if let Some(rule) = plan.rule {
+            self.rules.add(format!("rule {}", rule + 1), 1);
+        }
+        let mut warnings: Vec<String> = plan.warnings.iter().map(|w| w.summary()).collect();
+        warnings.dedup();
+        for warning in warnings {
+            self.warnings.add(warning, 1);
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: usize = self.statuses.0.iter().map(|(_, n)| n).sum();
+        let width = total.to_string().len();
+        writeln!(f, "{} files", total)?;
+        let sections = [
+            (None, &self.statuses),
+            (Some("by rule"), &self.rules),
+            (Some("warnings"), &self.warnings),
+        ];
+        for (title, counts) in sections {
+            if counts.0.is_empty() {
+                continue;
+            }
+            if let Some(title) = title {
+                writeln!(f, "{}", title)?;
+            }
+            for (label, n) in counts.sorted() {
+                writeln!(f, "  {:>width$} {}", n, label)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{Outcome, Warning};
+    use crate::Vars;
+
+    #[test]
+    fn test_summary() {
+        let plan = |rule, warnings| Plan {
+            outcome: Outcome::Skip(String::new()),
+            warnings,
+            vars: Vars::new(),
+            date: None,
+            candidates: Vec::new(),
+            rule,
+        };
+        let mut summary = Summary::default();
+        for _ in 0..10 {
+            summary.add_plan(&plan(
+                None,
+                vec![Warning::FallbackDate("the file creation time")],
+            ));
+            summary.add(&Status::Renamed, 1);
+        }
+        let sanitized = Warning::Sanitized("T2".to_string());
+        summary.add_plan(&plan(Some(1), vec![sanitized.clone(), sanitized]));
+        summary.add(&Status::Skipped("rule 2".to_string()), 1);
+        summary.add(&Status::Unchanged, 1);
+        summary.add(&Status::Failed, 2);
+        summary.add(&Status::Copied, 0);
+
+        let expected = [
+            "14 files",
+            "  10 renamed",
+            "   2 failed",
+            "   1 skipped by rule 2",
+            "   1 unchanged",
+            "by rule",
+            "   1 rule 2",
+            "warnings",
+            "  10 no capture date, using the file creation time",
+            "   1 sanitized {T2}",
+        ];
+        assert_eq!(summary.to_string().lines().collect::<Vec<_>>(), expected);
+    }
+}
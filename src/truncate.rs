@@ -0,0 +1,180 @@
+//! `--max-var-len Model=20`: caps the length of variable values in names,
+//! so verbose tags don't blow up filenames.
+//!
+//! | style    | `Canon EOS 5D Mark IV` capped at 12 |
+//! |----------|-------------------------------------|
+//! | `end`    | `Canon EOS 5…` (default)            |
+//! | `middle` | `Canon…rk IV`                       |
+//! | `vowels` | `Cnn EOS 5D…`, lowercase vowels inside words removed first |
+
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use crate::Vars;
+
+const ELLIPSIS: char = '…';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    End,
+    Middle,
+    Vowels,
+}
+
+/// Maximum length in characters of a variable value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaxLen {
+    pub var: String,
+    pub len: usize,
+    pub style: Style,
+}
+
+impl FromStr for MaxLen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "expected VAR=LEN[:STYLE] (e.g. Model=20:middle), got '{}'",
+                s
+            )
+        };
+        let (var, limit) = s.split_once('=').ok_or_else(invalid)?;
+        let (len, style) = limit.split_once(':').unwrap_or((limit, "end"));
+        let len = len.parse::<usize>().ok().filter(|len| *len > 0);
+        if var.is_empty() {
+            return Err(invalid());
+        }
+        let Some(len) = len else {
+            return Err(format!("length of '{}' must be a positive number", var));
+        };
+        let style = match style {
+            "end" => Style::End,
+            "middle" => Style::Middle,
+            "vowels" => Style::Vowels,
+            _ => {
+                return Err(format!(
+                    "unknown truncation style '{}', expected end, middle or vowels",
+                    style
+                ))
+            }
+        };
+        Ok(MaxLen {
+            var: var.to_string(),
+            len,
+            style,
+        })
+    }
+}
+
+impl MaxLen {
+    /// Shortens `value` to at most `len` characters.
+    pub fn apply(&self, value: &str) -> String {
+        let chars: Vec<char> = value.chars().collect();
+        if chars.len() <= self.len {
+            return value.to_string();
+        }
+        match self.style {
+            Style::End => truncate_end(&chars, self.len),
+            Style::Middle if self.len < 3 => truncate_end(&chars, self.len),
+            Style::Middle => {
+                let tail = (self.len - 1) / 2;
+                let head = self.len - 1 - tail;
+                let head: String = chars[..head].iter().collect();
+                let tail: String = chars[chars.len() - tail..].iter().collect();
+                format!("{}{}{}", head.trim_end(), ELLIPSIS, tail.trim_start())
+            }
+            Style::Vowels => {
+                let stripped = strip_vowels(&chars);
+                truncate_end(&stripped, self.len)
+            }
+        }
+    }
+}
+
+fn truncate_end(chars: &[char], len: usize) -> String {
+    if chars.len() <= len {
+        return chars.iter().collect();
+    }
+    if len < 2 {
+        return chars[..len].iter().collect();
+    }
+    let s: String = chars[..len - 1].iter().collect();
+    format!("{}{}", s.trim_end(), ELLIPSIS)
+}
+
+/// Removes lowercase vowels except at the start of a word, keeping
+/// acronyms and word shapes recognizable.
+fn strip_vowels(chars: &[char]) -> Vec<char> {
+    chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, c)| {
+            let word_start = i == 0 || !chars[i - 1].is_alphanumeric();
+            word_start || !"aeiou".contains(*c)
+        })
+        .map(|(_, c)| *c)
+        .collect()
+}
+
+/// Returns `vars` with the limited variables shortened.
+pub fn apply<'a>(limits: &[MaxLen], vars: &'a Vars) -> Cow<'a, Vars> {
+    let mut vars = Cow::Borrowed(vars);
+    for limit in limits {
+        if let Some(value) = vars.get(&limit.var) {
+            let value = limit.apply(value);
+            vars.to_mut().insert(limit.var.clone(), value);
+        }
+    }
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn max_len(s: &str) -> MaxLen {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            max_len("Model=20"),
+            MaxLen {
+                var: "Model".to_string(),
+                len: 20,
+                style: Style::End
+            }
+        );
+        assert_eq!(max_len("Lens=8:middle").style, Style::Middle);
+        assert!("Model".parse::<MaxLen>().is_err());
+        assert!("=20".parse::<MaxLen>().is_err());
+        assert!("Model=0".parse::<MaxLen>().is_err());
+        assert!("Model=20:start".parse::<MaxLen>().is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let model = "Canon EOS 5D Mark IV";
+        assert_eq!(max_len("M=12").apply(model), "Canon EOS 5…");
+        assert_eq!(max_len("M=12:middle").apply(model), "Canon…rk IV");
+        assert_eq!(max_len("M=12:vowels").apply(model), "Cnn EOS 5D…");
+        assert_eq!(max_len("M=16:vowels").apply(model), "Cnn EOS 5D Mrk…");
+        assert_eq!(max_len("M=17:vowels").apply(model), "Cnn EOS 5D Mrk IV");
+        assert_eq!(max_len("M=20").apply(model), model);
+        assert_eq!(max_len("M=1:middle").apply(model), "C");
+        assert_eq!(max_len("M=3:middle").apply("ääääää"), "ä…ä");
+    }
+
+    #[test]
+    fn test_apply_vars() {
+        let mut vars = Vars::new();
+        vars.insert("Model".to_string(), "Canon EOS 5D Mark IV".to_string());
+        let limits = [max_len("Model=5"), max_len("Lens=5")];
+        let limited = apply(&limits, &vars);
+        assert_eq!(limited["Model"], "Cano…");
+        assert!(!limited.contains_key("Lens"));
+        assert!(matches!(apply(&[], &vars), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,207 @@
+//! `--tz`: converts capture dates into one time zone before date variables
+//! are computed, so videos recording UTC and photos recording local time
+//! get names from the same clock.
+//!
+//! A date is local to the offset it carries (`2023:09:08 10:11:12+09:00`),
+//! else to the offset tag paired with its tag (`OffsetTimeDigitized` for
+//! `CreateDate`, `OffsetTimeOriginal` for `DateTimeOriginal`), else to any
+//! offset tag. Videos without one record UTC, other files the system time
+//! zone.
+
+use std::str::FromStr;
+
+use chrono::{FixedOffset, Local, NaiveDateTime, Offset, TimeDelta, TimeZone};
+
+use crate::dates::DateSource;
+use crate::{capture_date, daypart, Vars};
+
+/// Tags the capture date is read from, in the order they are picked, and
+/// the offset tags EXIF pairs them with.
+const DATE_TAGS: &[(&str, &str)] = &[
+    ("CreateDate", "OffsetTimeDigitized"),
+    ("DateTimeOriginal", "OffsetTimeOriginal"),
+    ("Date/TimeOriginal", "OffsetTimeOriginal"),
+];
+const OFFSET_TAGS: &[&str] = &["OffsetTimeOriginal", "OffsetTimeDigitized", "OffsetTime"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tz {
+    /// A fixed offset, e.g. `+09:00` or `UTC`.
+    Fixed(FixedOffset),
+    /// An IANA time zone, e.g. `Asia/Seoul`, following daylight saving time.
+    Named(chrono_tz::Tz),
+    /// The system time zone.
+    Local,
+}
+
+impl Tz {
+    /// Offset from UTC in minutes at the instant `utc`.
+    fn offset(&self, utc: NaiveDateTime) -> i32 {
+        let seconds = match self {
+            Tz::Fixed(offset) => offset.local_minus_utc(),
+            Tz::Named(tz) => tz.offset_from_utc_datetime(&utc).fix().local_minus_utc(),
+            Tz::Local => Local.offset_from_utc_datetime(&utc).local_minus_utc(),
+        };
+        seconds / 60
+    }
+}
+
+impl FromStr for Tz {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("utc") || s == "Z" {
+            return Ok(Tz::Fixed(FixedOffset::east_opt(0).unwrap()));
+        }
+        if s == "local" {
+            return Ok(Tz::Local);
+        }
+        if let Some(minutes) = daypart::parse_offset(s) {
+            return FixedOffset::east_opt(minutes * 60)
+                .map(Tz::Fixed)
+                .ok_or_else(|| format!("offset '{}' out of range", s));
+        }
+        s.parse().map(Tz::Named).map_err(|_| {
+            format!(
+                "unknown time zone '{}', expected an offset such as +09:00, a name such as Asia/Seoul or local",
+                s
+            )
+        })
+    }
+}
+
+/// Converts the capture date of `exif` into `tz`, setting its offset tags
+/// to the ones of `tz`. Returns `time_offset` with the shift added, for
+/// date variables to be computed with.
+pub fn convert(
+    tz: &Tz,
+    exif: &mut Vars,
+    time_offset: TimeDelta,
+    date_source: Option<&DateSource>,
+) -> TimeDelta {
+    let Some(date) = capture_date(exif, time_offset, date_source) else {
+        return time_offset;
+    };
+    let Some(source) = source_offset(exif, date) else {
+        return time_offset;
+    };
+    let utc = date - TimeDelta::minutes(source as i64);
+    let target = tz.offset(utc);
+    let offset = format_offset(target);
+    for tag in OFFSET_TAGS {
+        exif.insert(tag.to_string(), offset.clone());
+    }
+    // GPS times are converted with the offset tags, so they have already moved
+    match capture_date(exif, time_offset, date_source) {
+        Some(moved) => time_offset + (utc + TimeDelta::minutes(target as i64) - moved),
+        None => time_offset,
+    }
+}
+
+/// Returns the offset in minutes of the time zone `date` was recorded in.
+fn source_offset(exif: &Vars, date: NaiveDateTime) -> Option<i32> {
+    let tagged = DATE_TAGS
+        .iter()
+        .find_map(|(tag, offset)| Some((exif.get(*tag)?, offset)));
+    let carried = tagged
+        .map(|(s, _)| s)
+        .filter(|s| s.len() > 19)
+        .and_then(|s| daypart::parse_offset(s.get(s.len() - 6..)?));
+    let paired = tagged.and_then(|(_, offset)| daypart::parse_offset(exif.get(*offset)?));
+    carried
+        .or(paired)
+        .or_else(|| {
+            OFFSET_TAGS
+                .iter()
+                .find_map(|tag| exif.get(*tag).and_then(|s| daypart::parse_offset(s)))
+        })
+        .or_else(|| is_video(exif).then_some(0))
+        .or_else(|| {
+            let local = Local.from_local_datetime(&date).earliest()?;
+            Some(local.offset().local_minus_utc() / 60)
+        })
+}
+
+fn is_video(exif: &Vars) -> bool {
+    exif.get("MIMEType")
+        .is_some_and(|mime| mime.starts_with("video/"))
+}
+
+/// Formats minutes as an exif offset like `+09:00`.
+fn format_offset(minutes: i32) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    let minutes = minutes.abs();
+    format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exif(tags: &[(&str, &str)]) -> Vars {
+        tags.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_convert() {
+        let seoul: Tz = "Asia/Seoul".parse().unwrap();
+        let hours = |h| TimeDelta::hours(h);
+
+        let mut video = exif(&[
+            ("CreateDate", "2023:09:08 01:11:12"),
+            ("MIMEType", "video/quicktime"),
+        ]);
+        assert_eq!(convert(&seoul, &mut video, hours(0), None), hours(9));
+        assert_eq!(video["OffsetTime"], "+09:00");
+
+        let mut photo = exif(&[
+            ("DateTimeOriginal", "2023:09:08 10:11:12"),
+            ("OffsetTimeOriginal", "+09:00"),
+        ]);
+        assert_eq!(convert(&seoul, &mut photo, hours(0), None), hours(0));
+        let utc: Tz = "UTC".parse().unwrap();
+        assert_eq!(convert(&utc, &mut photo, hours(1), None), hours(-8));
+
+        let mut carried = exif(&[("CreateDate", "2023:09:08 10:11:12.50-04:00")]);
+        assert_eq!(convert(&seoul, &mut carried, hours(0), None), hours(13));
+
+        let mut gps = exif(&[
+            ("GPSDateTime", "2023:09:08 01:11:12Z"),
+            ("OffsetTime", "+02:00"),
+        ]);
+        assert_eq!(convert(&seoul, &mut gps, hours(0), None), hours(0));
+        assert_eq!(
+            capture_date(&gps, hours(0), None).unwrap().to_string(),
+            "2023-09-08 10:11:12"
+        );
+
+        let mut paired = exif(&[
+            ("CreateDate", "2023:09:08 10:11:12"),
+            ("OffsetTimeDigitized", "+00:00"),
+            ("OffsetTimeOriginal", "+09:00"),
+        ]);
+        assert_eq!(convert(&seoul, &mut paired, hours(0), None), hours(9));
+        assert_eq!(paired["OffsetTimeDigitized"], "+09:00");
+
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        let mut winter = exif(&[
+            ("CreateDate", "2023:12:08 12:00:00"),
+            ("MIMEType", "video/mp4"),
+        ]);
+        assert_eq!(convert(&new_york, &mut winter, hours(0), None), hours(-5));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "+05:30".parse::<Tz>().unwrap().offset(NaiveDateTime::MIN),
+            330
+        );
+        assert_eq!("UTC".parse::<Tz>().unwrap().offset(NaiveDateTime::MIN), 0);
+        assert_eq!("local".parse::<Tz>(), Ok(Tz::Local));
+        assert!("Mars/Olympus".parse::<Tz>().is_err());
+        assert_eq!(format_offset(-270), "-04:30");
+    }
+}
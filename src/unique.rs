@@ -0,0 +1,116 @@
+//! `{u}`: a suffix telling apart files of a run that would get the same
+//! name, such as burst shots sharing a second.
+//!
+//! The first file with a name keeps it, with an empty `{u}`; the others get
+//! `_1`, `_2`, ... or, with `--unique-style letter`, `a`, `b`, ... by
+//! capture date, then by input order. `--suffix-format` changes how numbers
+//! are written, and with `--suffix-first` the first file is numbered too.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::burst;
+use crate::suffix::SuffixFormat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Style {
+    /// `_1`, `_2`, ...
+    #[default]
+    Number,
+    /// `a`, `b`, ... `z`, `aa`, ...
+    Letter,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "number" => Ok(Style::Number),
+            "letter" => Ok(Style::Letter),
+            _ => Err(format!("invalid style '{}', expected number or letter", s)),
+        }
+    }
+}
+
+/// How `{u}` is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suffixes {
+    pub style: Style,
+    /// Format of numbers, `_{n}` by default.
+    pub format: SuffixFormat,
+    /// Number the first file with a name too.
+    pub first: bool,
+}
+
+/// Assigns `{u}` to files given by index and new path, in order of
+/// precedence.
+/// Files whose name no other file has get an empty suffix.
+pub fn assign(names: Vec<(usize, PathBuf)>, suffixes: &Suffixes) -> Vec<(usize, String)> {
+    let mut by_name: BTreeMap<PathBuf, Vec<usize>> = BTreeMap::new();
+    for (i, name) in names {
+        by_name.entry(name).or_default().push(i);
+    }
+    by_name
+        .into_values()
+        .flat_map(|members| {
+            let first = usize::from(suffixes.first && members.len() > 1);
+            members
+                .into_iter()
+                .enumerate()
+                .map(move |(n, i)| (i, suffix(n + first, suffixes)))
+        })
+        .collect()
+}
+
+/// The suffix of the `n`th file with a name, counting from 0.
+fn suffix(n: usize, suffixes: &Suffixes) -> String {
+    match (n, suffixes.style) {
+        (0, _) => String::new(),
+        (n, Style::Number) => suffixes.format.format(n),
+        (n, Style::Letter) => burst::suffix(n - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign() {
+        let names = vec![
+            (0, PathBuf::from("a.jpg")),
+            (1, PathBuf::from("b.jpg")),
+            (2, PathBuf::from("a.jpg")),
+            (3, PathBuf::from("a.jpg")),
+        ];
+        let mut style = Suffixes {
+            style: Style::Number,
+            format: SuffixFormat::new("_"),
+            first: false,
+        };
+        let mut suffixes = assign(names.clone(), &style);
+        suffixes.sort();
+        let expected = |s: [&str; 4]| {
+            s.iter()
+                .map(|s| s.to_string())
+                .enumerate()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(suffixes, expected(["", "", "_1", "_2"]));
+        style.first = true;
+        style.format = "-{n:02}".parse().unwrap();
+        let mut suffixes = assign(names.clone(), &style);
+        suffixes.sort();
+        assert_eq!(suffixes, expected(["-01", "", "-02", "-03"]));
+        style.style = Style::Letter;
+        style.first = false;
+        let mut suffixes = assign(names, &style);
+        suffixes.sort();
+        assert_eq!(suffixes, expected(["", "", "a", "b"]));
+
+        assert_eq!("letter".parse(), Ok(Style::Letter));
+        assert!("roman".parse::<Style>().is_err());
+    }
+}
@@ -0,0 +1,66 @@
+//! `--paranoid`: files are hashed before they are renamed, copied or moved
+//! and again at their new path, even on the same filesystem, so archives
+//! can vouch for every file end to end.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+/// Returns the SHA-256 digest of the file at `path` in hex.
+pub fn sha256(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hex(&hasher.finalize()));
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Checks that the file at `path` still has the `digest` it had before.
+pub fn check(path: &Path, digest: &str) -> Result<(), String> {
+    let after = sha256(path).map_err(|e| format!("failed to hash {}: {}", path.display(), e))?;
+    if after != digest {
+        return Err(format!(
+            "{} doesn't match the original, sha256 {} before and {} after",
+            path.display(),
+            digest,
+            after
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-verify-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("IMG_1234.JPG");
+        fs::write(&path, "abc").unwrap();
+        let digest = sha256(&path).unwrap();
+        assert_eq!(
+            digest,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(check(&path, &digest), Ok(()));
+
+        fs::write(&path, "abd").unwrap();
+        let err = check(&path, &digest).unwrap_err();
+        assert!(err.contains("doesn't match the original"), "{}", err);
+        assert!(check(&dir.join("missing.JPG"), &digest).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
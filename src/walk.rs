@@ -0,0 +1,154 @@
+//! `--recursive`: the media files in a directory tree, such as a library
+//! archived in year and month folders.
+//!
+//! Only files with a known image or video extension are picked, so sidecars,
+//! journals and other files kept alongside are left alone. Hidden files and
+//! directories are skipped, and symlinked directories aren't followed, so a
+//! link back up the tree can't loop. A directory that can't be read is
+//! reported and the rest of the tree is still walked.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::rules::VIDEO_EXTENSIONS;
+
+/// Extensions of the image formats exiftool reads capture dates from.
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "arw", "avif", "cr2", "cr3", "crw", "dng", "gif", "heic", "heif", "jpeg", "jpg", "nef", "nrw",
+    "orf", "pef", "png", "raf", "rw2", "srw", "tif", "tiff", "webp", "x3f",
+];
+
+/// Returns true if `path` has an image or video extension.
+pub fn is_media(path: &Path) -> bool {
+    let Some(ext) = path.extension() else {
+        return false;
+    };
+    let ext = ext.to_string_lossy().to_lowercase();
+    IMAGE_EXTENSIONS.contains(&ext.as_str()) || VIDEO_EXTENSIONS.contains(&ext.as_str())
+}
+
+/// Returns the media files under `dir`, sorted by path. Files directly in
+/// `dir` are at depth 1; `max_depth` limits how deep the walk goes.
+/// Directories that couldn't be read are returned with their error.
+pub fn files(dir: &Path, max_depth: Option<usize>) -> (Vec<String>, Vec<(PathBuf, io::Error)>) {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    walk(
+        dir,
+        1,
+        max_depth.unwrap_or(usize::MAX),
+        &mut files,
+        &mut errors,
+    );
+    files.sort();
+    (files, errors)
+}
+
+fn walk(
+    dir: &Path,
+    depth: usize,
+    max_depth: usize,
+    files: &mut Vec<String>,
+    errors: &mut Vec<(PathBuf, io::Error)>,
+) {
+    if depth > max_depth {
+        return;
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return errors.push((dir.to_path_buf(), e)),
+    };
+    for entry in entries {
+        let (entry, file_type) = match entry.and_then(|entry| entry.file_type().map(|t| (entry, t)))
+        {
+            Ok(entry) => entry,
+            Err(e) => {
+                errors.push((dir.to_path_buf(), e));
+                continue;
+            }
+        };
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if file_type.is_dir() {
+            walk(&path, depth + 1, max_depth, files, errors);
+        } else if (!file_type.is_symlink() || path.is_file()) && is_media(&path) {
+            files.push(path.to_string_lossy().to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-walk-{}", std::process::id()));
+        for file in [
+            "IMG_1.JPG",
+            "notes.txt",
+            "2023/09/IMG_2.jpg",
+            "2023/09/IMG_2.xmp",
+            "2023/MVI_3.mov",
+            ".thumbs/IMG_1.jpg",
+        ] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "").unwrap();
+        }
+        let root = dir.to_str().unwrap();
+        let walk = |max_depth| -> Vec<String> {
+            let (files, errors) = files(&dir, max_depth);
+            assert!(errors.is_empty(), "{:?}", errors);
+            files
+                .into_iter()
+                .map(|f| f[root.len() + 1..].to_string())
+                .collect()
+        };
+        assert_eq!(
+            walk(None),
+            vec!["2023/09/IMG_2.jpg", "2023/MVI_3.mov", "IMG_1.JPG"]
+        );
+        assert_eq!(walk(Some(2)), vec!["2023/MVI_3.mov", "IMG_1.JPG"]);
+        assert_eq!(walk(Some(1)), vec!["IMG_1.JPG"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_files_errors() {
+        let dir = std::env::temp_dir().join(format!("exif-rename-walk-err-{}", std::process::id()));
+        let (found, errors) = files(&dir, None);
+        assert!(found.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, dir);
+        assert_eq!(errors[0].1.kind(), io::ErrorKind::NotFound);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for file in ["a/IMG_1.jpg", "b/IMG_2.jpg", "c/IMG_3.jpg"] {
+                let path = dir.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, "").unwrap();
+            }
+            let locked = dir.join("b");
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+            // root reads the directory regardless of its mode
+            let readable = fs::read_dir(&locked).is_ok();
+            let (found, errors) = files(&dir, None);
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+            fs::remove_dir_all(&dir).unwrap();
+            if !readable {
+                let root = dir.to_str().unwrap();
+                let found: Vec<_> = found.iter().map(|f| &f[root.len() + 1..]).collect();
+                assert_eq!(found, vec!["a/IMG_1.jpg", "c/IMG_3.jpg"]);
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].0, locked);
+                assert_eq!(errors[0].1.kind(), io::ErrorKind::PermissionDenied);
+            }
+        }
+    }
+}